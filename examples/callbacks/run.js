@@ -0,0 +1,29 @@
+#!/usr/bin/env node
+const fs = require('fs');
+const Callbacks = require('./target/wrapper.js');
+
+function main() {
+    let code = fs.readFileSync("target/wasm32-unknown-unknown/release/callbacks.wasm");
+    let wrapper = new Callbacks(new WebAssembly.Module(code));
+
+    console.log(wrapper.sort_by([3, -1, 20, 7], (a, b) => a > b));
+
+    let total = wrapper.count_words("the quick brown fox", (count, word) => {
+        console.log(`word ${count}: ${word}`);
+    });
+    console.log(`${total} words`);
+
+    // callbacks can call back into the module.
+    console.log(wrapper.describe_all([1, 2, 3], n => `${n} doubled twice is ${wrapper.apply_twice(n, x => x * 2)}`));
+
+    try {
+        wrapper.count_words("stop here", () => {
+            throw new Error("stopped");
+        });
+    } catch (e) {
+        console.log(`callback threw: ${e.message}`);
+    }
+    console.log(wrapper.apply_twice(3, x => x + 0.5));
+}
+
+main();
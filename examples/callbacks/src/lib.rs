@@ -0,0 +1,47 @@
+#[macro_use]
+extern crate wasm_wrapper_gen;
+
+fn sort_by(mut values: Vec<i32>, less: &dyn Fn(i32, i32) -> bool) -> Vec<i32> {
+    values.sort_by(|&a, &b| {
+        if less(a, b) {
+            ::std::cmp::Ordering::Less
+        } else if less(b, a) {
+            ::std::cmp::Ordering::Greater
+        } else {
+            ::std::cmp::Ordering::Equal
+        }
+    });
+    values
+}
+
+fn count_words(text: &str, progress: &dyn Fn(u32, &str)) -> u32 {
+    let mut count = 0;
+    for word in text.split_whitespace() {
+        count += 1;
+        progress(count, word);
+    }
+    count
+}
+
+fn describe_all(numbers: &[u32], describe: &dyn Fn(u32) -> String) -> String {
+    numbers
+        .iter()
+        .map(|&number| describe(number))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+js_fn! {
+    /// Sorts the values, given whether one value should come before another.
+    fn sort_by(values: Vec<i32>, less: &dyn Fn(i32, i32) -> bool) -> Vec<i32> => sort_by;
+
+    /// Counts the words in the text, reporting each word as it's counted.
+    fn count_words(text: &str, progress: &dyn Fn(u32, &str)) -> u32 => count_words;
+
+    fn describe_all(numbers: &[u32], describe: &dyn Fn(u32) -> String) -> String
+        => describe_all;
+
+    fn apply_twice(x: f64, f: &Fn(f64) -> f64) -> f64 {
+        f(f(x))
+    }
+}
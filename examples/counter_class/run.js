@@ -0,0 +1,36 @@
+#!/usr/bin/env node
+const fs = require('fs');
+const CounterModule = require('./target/wrapper.js');
+
+function main() {
+    let code = fs.readFileSync("target/wasm32-unknown-unknown/release/counter_class.wasm");
+    let module = new WebAssembly.Module(code);
+    let instance = new CounterModule(module);
+
+    let counter = instance.Counter.new(5);
+    console.log(`counter.get(): ${counter.get()}`);
+    counter.add(3);
+    counter.add(10);
+    console.log(`after adding 3 and 10: ${counter.get()}`);
+    console.log(`counter.doubled(): ${counter.doubled()}`);
+    console.log(`history: ${counter.history()}`);
+
+    let reset = counter.reset();
+    console.log(`reset.get(): ${reset.get()}`);
+    try {
+        counter.get();
+    } catch (e) {
+        console.log(`using counter after reset: ${e.message}`);
+    }
+
+    reset.add(7);
+    console.log(`reset.into_total(): ${reset.into_total()}`);
+
+    let other = instance.Counter.new(1);
+    other.free();
+    other.free(); // freeing twice is allowed
+
+    console.log(`double(21): ${instance.double(21)}`);
+}
+
+main();
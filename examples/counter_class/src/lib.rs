@@ -0,0 +1,55 @@
+#[macro_use]
+extern crate wasm_wrapper_gen;
+
+pub struct Counter {
+    count: u32,
+    history: Vec<u32>,
+}
+
+impl Counter {
+    fn new(start: u32) -> Counter {
+        Counter {
+            count: start,
+            history: Vec::new(),
+        }
+    }
+
+    fn get(&self) -> u32 {
+        self.count
+    }
+}
+
+js_fn! {
+    impl Counter {
+        fn new(start: u32) -> Counter => Counter::new;
+        fn get(&self) -> u32 => Counter::get;
+        /// Adds `amount` to the count, remembering the previous count.
+        fn add(&mut self, amount: u32) {
+            self.history.push(self.count);
+            self.count += amount;
+        }
+        /// All previous counts, oldest first.
+        fn history(&self) -> Vec<u32> {
+            self.history.clone()
+        }
+        fn reset(self) -> Self {
+            Self::new(0)
+        }
+        /// Consumes the counter, returning the sum of all counts it has had.
+        fn into_total(self) -> u32 {
+            self.history.iter().sum::<u32>() + self.count
+        }
+    }
+
+    fn double(x: u32) -> u32 {
+        x * 2
+    }
+
+    // methods may be split between several impl blocks, as long as they're
+    // all inside the same js_fn! invocation.
+    impl Counter {
+        fn doubled(&self) -> u32 {
+            self.count * 2
+        }
+    }
+}
@@ -0,0 +1,31 @@
+#!/usr/bin/env node
+const fs = require('fs');
+const ErrorResults = require('./target/wrapper.js');
+
+function attempt(description, func) {
+    try {
+        console.log(`${description}: ${func()}`);
+    } catch (e) {
+        if (!(e instanceof ErrorResults.ParseError)) {
+            throw e;
+        }
+        console.log(`${description} threw ${e.name}: ${e.message}`);
+    }
+}
+
+function main() {
+    let code = fs.readFileSync("target/wasm32-unknown-unknown/release/error_results.wasm");
+    let module = new WebAssembly.Module(code);
+    let instance = new ErrorResults(module);
+
+    attempt('parse_number("42")', () => instance.parse_number("42"));
+    attempt('parse_number("forty-two")', () => instance.parse_number("forty-two"));
+
+    attempt('parse_all("1, 2, 3")', () => instance.parse_all("1, 2, 3"));
+    attempt('parse_all("1, two, 3")', () => instance.parse_all("1, two, 3"));
+
+    attempt('checked_divide(7, 2)', () => instance.checked_divide(7, 2));
+    attempt('checked_divide(7, 0)', () => instance.checked_divide(7, 0));
+}
+
+main();
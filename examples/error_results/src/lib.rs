@@ -0,0 +1,20 @@
+#[macro_use]
+extern crate wasm_wrapper_gen;
+
+use std::num::ParseIntError;
+
+js_fn! {
+    fn parse_number(input: String) -> Result<u32, ParseIntError> {
+        input.trim().parse()
+    }
+    fn parse_all(input: String) -> Result<Vec<u32>, ParseIntError> {
+        let mut numbers = Vec::new();
+        for part in input.split(',') {
+            numbers.push(part.trim().parse()?);
+        }
+        Ok(numbers)
+    }
+    fn checked_divide(a: i32, b: i32) -> Result<i32, String> {
+        a.checked_div(b).ok_or_else(|| format!("cannot divide {} by {}", a, b))
+    }
+}
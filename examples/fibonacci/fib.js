@@ -13,7 +13,8 @@ function main() {
     console.log(`fib(20): ${fib.fib(20)}`);
     console.log(`fib(200): ${fib.fib(200)}`);
     for (var i = 20; i < 200; i += 20) {
-        console.log(`fib_u64_saturating(${i}) = ${fib.fib_str(i)}`);
+        console.log(`fib_u64_saturating(${i}) = ${fib.fib_u64(i)}`);
+        console.log(`fib_str(${i}) = ${fib.fib_str(i)}`);
     }
 
     console.log(`all numbers: ${fib.all(64)}`);
@@ -43,5 +43,6 @@ fn fib_str(nth: u32) -> String {
 js_fn! {
     fn fib(nth: u32) -> f64 => fib;
     fn all(num: u32) -> Vec<f64> => fib_all;
+    fn fib_u64(nth: u32) -> u64 => fib_u64;
     fn fib_str(nth: u32) -> String => fib_str;
 }
@@ -0,0 +1,25 @@
+#!/usr/bin/env node
+const fs = require('fs');
+const JsImports = require('./target/wrapper.js');
+
+function main() {
+    let code = fs.readFileSync("target/wasm32-unknown-unknown/release/js_imports.wasm");
+    let wrapper = new JsImports(new WebAssembly.Module(code), {
+        log: message => console.log(`[rust] ${message}`),
+        now: () => Date.now(),
+        ask_name: name => name == null ? "stranger" : name.toUpperCase(),
+        show_rolls: (rolls, total) => console.log(`rolled ${rolls.join(", ")} (total ${total})`),
+    });
+
+    console.log(wrapper.greet(3));
+    console.log(wrapper.roll_dice(4, 12345));
+    wrapper.roll_dice(0, 1);
+
+    try {
+        new JsImports(new WebAssembly.Module(code), { log: console.log });
+    } catch (e) {
+        console.log(`constructing without every import threw: ${e.message}`);
+    }
+}
+
+main();
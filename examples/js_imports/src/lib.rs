@@ -0,0 +1,51 @@
+#[macro_use]
+extern crate wasm_wrapper_gen;
+
+// `js_import!` can't be used in the same module as `js_fn!`.
+mod imports {
+    js_import! {
+        /// Logs a message to the console.
+        pub fn log(message: &str);
+
+        /// Milliseconds since the epoch.
+        pub fn now() -> f64;
+
+        /// Asks for the name to greet, given a default.
+        pub fn ask_name(default: Option<&str>) -> String;
+
+        pub fn show_rolls(rolls: &[u32], total: Option<u32>);
+    }
+}
+
+use imports::{ask_name, log, now, show_rolls};
+
+fn greet(times: u32) -> String {
+    let start = now();
+    let name = ask_name(Some("world"));
+    for i in 0..times {
+        log(&format!("hello {} ({})", name, i + 1));
+    }
+    format!("greeted {} {} times in {}ms", name, times, now() - start)
+}
+
+fn roll_dice(count: u32, seed: u32) -> u32 {
+    let mut state = seed;
+    let rolls = (0..count)
+        .map(|_| {
+            // xorshift, to avoid depending on `rand`.
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state % 6 + 1
+        })
+        .collect::<Vec<_>>();
+    let total = rolls.iter().sum();
+    show_rolls(&rolls, if rolls.is_empty() { None } else { Some(total) });
+    total
+}
+
+js_fn! {
+    fn greet(times: u32) -> String => greet;
+
+    fn roll_dice(count: u32, seed: u32) -> u32 => roll_dice;
+}
@@ -0,0 +1,19 @@
+extern crate failure;
+extern crate wasm_wrapper_gen_build;
+
+fn main() {
+    if let Err(e) = real_main() {
+        eprintln!("error: {}", e);
+        ::std::process::exit(1);
+    }
+}
+
+fn real_main() -> Result<(), failure::Error> {
+    // functions in `mod` declarations are found by following them from the
+    // crate root.
+    wasm_wrapper_gen_build::Config::new()
+        .with_class_name("Modules")
+        .translate("src/lib.rs", "target/wrapper.js")?;
+
+    Ok(())
+}
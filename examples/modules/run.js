@@ -0,0 +1,16 @@
+#!/usr/bin/env node
+const fs = require('fs');
+const Modules = require('./target/wrapper.js');
+
+function main() {
+    let code = fs.readFileSync("target/wasm32-unknown-unknown/release/modules.wasm");
+
+    let instance = new Modules(new WebAssembly.Module(code));
+
+    console.log(`version: ${instance.version()}`);
+    console.log(`perimeter of 3x4: ${instance.perimeter(3, 4)}`);
+    console.log(`area of 3x4: ${instance.area(3, 4)}`);
+    console.log(`shout("hello"): ${instance.shout("hello")}`);
+}
+
+main();
@@ -0,0 +1,7 @@
+mod area;
+
+js_fn! {
+    fn perimeter(width: u32, height: u32) -> u32 {
+        2 * (width + height)
+    }
+}
@@ -0,0 +1,12 @@
+#[macro_use]
+extern crate wasm_wrapper_gen;
+
+mod geometry;
+#[path = "text_utils.rs"]
+mod text;
+
+js_fn! {
+    fn version() -> u32 {
+        1
+    }
+}
@@ -0,0 +1,5 @@
+js_fn! {
+    fn shout(text: String) -> String {
+        text.to_uppercase()
+    }
+}
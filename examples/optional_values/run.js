@@ -0,0 +1,25 @@
+#!/usr/bin/env node
+const fs = require('fs');
+const OptionalValues = require('./target/wrapper.js');
+
+function main() {
+    let code = fs.readFileSync("target/wasm32-unknown-unknown/release/optional_values.wasm");
+    let module = new WebAssembly.Module(code);
+    let instance = new OptionalValues(module);
+
+    let haystack = [4, 8, 15, 16, 23, 42];
+    console.log(`find_index(${haystack}, 15): ${instance.find_index(haystack, 15)}`);
+    console.log(`find_index(${haystack}, 7): ${instance.find_index(haystack, 7)}`);
+
+    console.log(`sum_or_default([1.5, 2.5], null): ${instance.sum_or_default([1.5, 2.5], null)}`);
+    console.log(`sum_or_default(null, 3.5): ${instance.sum_or_default(null, 3.5)}`);
+    console.log(`sum_or_default(): ${instance.sum_or_default()}`);
+
+    console.log(`greeting("world"): ${instance.greeting("world")}`);
+    console.log(`greeting(null): ${instance.greeting(null)}`);
+
+    console.log(`non_empty([1, 2]): ${instance.non_empty([1, 2])}`);
+    console.log(`non_empty([]): ${instance.non_empty([])}`);
+}
+
+main();
@@ -0,0 +1,24 @@
+#[macro_use]
+extern crate wasm_wrapper_gen;
+
+js_fn! {
+    fn find_index(haystack: &[u32], needle: u32) -> Option<u32> {
+        haystack.iter().position(|&x| x == needle).map(|i| i as u32)
+    }
+    fn sum_or_default(input: Option<&[f64]>, default: Option<f64>) -> f64 {
+        match input {
+            Some(input) => input.iter().sum(),
+            None => default.unwrap_or(0.0),
+        }
+    }
+    fn greeting(name: Option<String>) -> Option<String> {
+        name.map(|name| format!("Hello, {}!", name))
+    }
+    fn non_empty(input: Vec<u8>) -> Option<Vec<u8>> {
+        if input.is_empty() {
+            None
+        } else {
+            Some(input)
+        }
+    }
+}
@@ -0,0 +1,14 @@
+extern crate wasm_wrapper_gen_build;
+
+use wasm_wrapper_gen_build::PanicStrategy;
+
+fn main() {
+    wasm_wrapper_gen_build::Config::new()
+        .with_class_name("Panics")
+        .with_panic_strategy(PanicStrategy::ThrowRustPanic)
+        .translate("src/lib.rs", "target/wrapper.js")
+        .unwrap_or_else(|e| {
+            eprintln!("error: {}", e);
+            ::std::process::exit(1);
+        });
+}
@@ -0,0 +1,30 @@
+#!/usr/bin/env node
+const fs = require('fs');
+const Panics = require('./target/wrapper.js');
+
+function attempt(module, description, func) {
+    // instances shouldn't be used again after they panic, since the trap
+    // leaves rust's stack and any locks it held as they were.
+    let instance = new Panics(module);
+    try {
+        console.log(`${description}: ${func(instance)}`);
+    } catch (e) {
+        if (!(e instanceof Panics.RustPanic)) {
+            throw e;
+        }
+        console.log(`${description} panicked at ${e.location}: ${e.message}`);
+    }
+}
+
+function main() {
+    let code = fs.readFileSync("target/wasm32-unknown-unknown/release/panics.wasm");
+    let module = new WebAssembly.Module(code);
+
+    attempt(module, 'nth_item([1, 2, 3], 1)', (instance) => instance.nth_item([1, 2, 3], 1));
+    attempt(module, 'nth_item([1, 2, 3], 5)', (instance) => instance.nth_item([1, 2, 3], 5));
+
+    attempt(module, 'shout("hello")', (instance) => instance.shout("hello"));
+    attempt(module, 'shout("")', (instance) => instance.shout(""));
+}
+
+main();
@@ -0,0 +1,14 @@
+#[macro_use]
+extern crate wasm_wrapper_gen;
+
+js_fn! {
+    fn nth_item(input: &[u32], index: u32) -> u32 {
+        input[index as usize]
+    }
+    fn shout(input: String) -> String {
+        if input.is_empty() {
+            panic!("cannot shout an empty string");
+        }
+        input.to_uppercase()
+    }
+}
@@ -0,0 +1,27 @@
+#!/usr/bin/env node
+const fs = require('fs');
+const ScratchArena = require('./target/wrapper.js');
+
+function main() {
+    let code = fs.readFileSync("target/wasm32-unknown-unknown/release/scratch_arena.wasm");
+    let wrapper = new ScratchArena(new WebAssembly.Module(code));
+
+    // borrowed arguments of every call reuse the same scratch memory.
+    let total = 0;
+    for (let i = 0; i < 1000; i++) {
+        total += wrapper.dot([i, 1, 2], [1, i, 0.5]);
+    }
+    console.log(total);
+
+    console.log(wrapper.count_matches("the cat sat on the mat", "at"));
+
+    // arguments bigger than the scratch region still work, and it grows to fit them.
+    let big = new Float64Array(10000).fill(0.5);
+    console.log(wrapper.dot(big, big));
+    console.log(wrapper.dot(big, big));
+
+    // owned arguments are allocated as usual.
+    console.log(wrapper.reversed([1, 2, 3]));
+}
+
+main();
@@ -0,0 +1,22 @@
+#[macro_use]
+extern crate wasm_wrapper_gen;
+
+fn dot(a: &[f64], b: &[f64]) -> f64 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+fn count_matches(haystack: &str, needle: &str) -> u32 {
+    haystack.matches(needle).count() as u32
+}
+
+js_fn! {
+    /// Multiplies two vectors element by element, summing the products.
+    fn dot(a: &[f64], b: &[f64]) -> f64 => dot;
+
+    /// Counts the times the needle appears in the haystack.
+    fn count_matches(haystack: &str, needle: &str) -> u32 => count_matches;
+
+    fn reversed(values: Vec<u32>) -> Vec<u32> {
+        values.into_iter().rev().collect()
+    }
+}
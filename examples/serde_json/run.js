@@ -0,0 +1,20 @@
+#!/usr/bin/env node
+const fs = require('fs');
+const SerdeJson = require('./target/wrapper.js');
+
+function main() {
+    let code = fs.readFileSync("target/wasm32-unknown-unknown/release/serde_json_example.wasm");
+    let serde = new SerdeJson(new WebAssembly.Module(code));
+
+    let items = [
+        { name: "apple", price: 3, quantity: 4 },
+        { name: "melon", price: 10, quantity: 1 },
+    ];
+
+    console.log(serde.checkout({ customer: "Ferris", items: items, coupon: null }));
+    console.log(serde.checkout({ customer: "Ferris", items: items, coupon: "HALF" }));
+    console.log(serde.most_expensive(items));
+    console.log(serde.most_expensive([]));
+}
+
+main();
@@ -0,0 +1,55 @@
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+#[macro_use]
+extern crate wasm_wrapper_gen;
+
+use wasm_wrapper_gen::Json;
+
+#[derive(Serialize, Deserialize)]
+struct Item {
+    name: String,
+    price: u32,
+    quantity: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Order {
+    customer: String,
+    items: Vec<Item>,
+    coupon: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Receipt {
+    customer: String,
+    total: u32,
+    discounted: bool,
+}
+
+fn checkout(order: Json<Order>) -> Json<Receipt> {
+    let order = order.into_inner();
+    let mut total = order
+        .items
+        .iter()
+        .map(|item| item.price * item.quantity)
+        .sum::<u32>();
+    let discounted = order.coupon.as_ref().map_or(false, |c| c == "HALF");
+    if discounted {
+        total /= 2;
+    }
+    Json(Receipt {
+        customer: order.customer,
+        total,
+        discounted,
+    })
+}
+
+fn most_expensive(items: Json<Vec<Item>>) -> Option<Json<Item>> {
+    items.into_inner().into_iter().max_by_key(|item| item.price).map(Json)
+}
+
+js_fn! {
+    fn checkout(order: Json<Order>) -> Json<Receipt> => checkout;
+    fn most_expensive(items: Json<Vec<Item>>) -> Option<Json<Item>> => most_expensive;
+}
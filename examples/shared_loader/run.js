@@ -0,0 +1,25 @@
+#!/usr/bin/env node
+const fs = require('fs');
+const Shapes = require('./target/wrapper.js');
+const { Point, Polygon } = Shapes;
+
+function main() {
+    let code = fs.readFileSync("target/wasm32-unknown-unknown/release/shared_loader.wasm");
+    // every class uses this one instance.
+    Shapes.load(new WebAssembly.Module(code));
+
+    let point = Point.new(3, 4);
+    console.log(`point: (${point.x()}, ${point.y()})`);
+    console.log(`distance to origin: ${Shapes.distance(0, 0, point.x(), point.y())}`);
+    point.free();
+
+    let square = Polygon.new();
+    square.push(0, 0);
+    square.push(1, 0);
+    square.push(1, 1);
+    square.push(0, 1);
+    console.log(`square corners: ${square.corners()}, perimeter: ${square.perimeter()}`);
+    square.free();
+}
+
+main();
@@ -0,0 +1,52 @@
+#[macro_use]
+extern crate wasm_wrapper_gen;
+
+pub struct Point {
+    x: f64,
+    y: f64,
+}
+
+pub struct Polygon {
+    points: Vec<Point>,
+}
+
+js_fn! {
+    impl Point {
+        fn new(x: f64, y: f64) -> Self {
+            Point { x, y }
+        }
+        fn x(&self) -> f64 {
+            self.x
+        }
+        fn y(&self) -> f64 {
+            self.y
+        }
+    }
+
+    impl Polygon {
+        fn new() -> Self {
+            Polygon { points: Vec::new() }
+        }
+        /// Adds a corner at the given position.
+        fn push(&mut self, point_x: f64, point_y: f64) {
+            self.points.push(Point { x: point_x, y: point_y });
+        }
+        fn corners(&self) -> usize {
+            self.points.len()
+        }
+        /// Length of the polygon's outline.
+        fn perimeter(&self) -> f64 {
+            let points = &self.points;
+            (0..points.len())
+                .map(|i| {
+                    let (a, b) = (&points[i], &points[(i + 1) % points.len()]);
+                    ((b.x - a.x).powi(2) + (b.y - a.y).powi(2)).sqrt()
+                })
+                .sum()
+        }
+    }
+
+    fn distance(x1: f64, y1: f64, x2: f64, y2: f64) -> f64 {
+        ((x2 - x1).powi(2) + (y2 - y1).powi(2)).sqrt()
+    }
+}
@@ -0,0 +1,14 @@
+extern crate wasm_wrapper_gen_build;
+
+use wasm_wrapper_gen_build::StringValidation;
+
+fn main() {
+    wasm_wrapper_gen_build::Config::new()
+        .with_class_name("StringValidation")
+        .with_string_validation(StringValidation::Strict)
+        .translate("src/lib.rs", "target/wrapper.js")
+        .unwrap_or_else(|e| {
+            eprintln!("error: {}", e);
+            ::std::process::exit(1);
+        });
+}
@@ -0,0 +1,23 @@
+#!/usr/bin/env node
+const fs = require('fs');
+const StringValidation = require('./target/wrapper.js');
+
+function main() {
+    let code = fs.readFileSync("target/wasm32-unknown-unknown/release/string_validation.wasm");
+    let strings = new StringValidation(new WebAssembly.Module(code));
+
+    console.log(`shout("héllo"): ${strings.shout("héllo")}`);
+    try {
+        strings.shout("lone \uD800 surrogate");
+    } catch (e) {
+        console.log(`shout("lone \\uD800 surrogate") threw ${e.name}: ${e.message}`);
+    }
+
+    // JsString arguments see the exact code units.
+    let input = "a\uDC00b";
+    console.log(`code_unit_count("a\\uDC00b"): ${strings.code_unit_count(input)}`);
+    console.log(`is_well_formed("a\\uDC00b"): ${strings.is_well_formed(input)}`);
+    console.log(`is_well_formed("ab"): ${strings.is_well_formed("ab")}`);
+}
+
+main();
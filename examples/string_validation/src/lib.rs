@@ -0,0 +1,18 @@
+#[macro_use]
+extern crate wasm_wrapper_gen;
+
+use wasm_wrapper_gen::JsString;
+
+fn code_unit_count(text: JsString) -> usize {
+    text.len()
+}
+
+js_fn! {
+    fn shout(text: &str) -> String {
+        text.to_uppercase()
+    }
+    fn code_unit_count(text: JsString) -> usize => code_unit_count;
+    fn is_well_formed(text: JsString) -> bool {
+        text.is_well_formed()
+    }
+}
@@ -0,0 +1,21 @@
+#!/usr/bin/env node
+const fs = require('fs');
+const TupleReturns = require('./target/wrapper.js');
+
+function main() {
+    let code = fs.readFileSync("target/wasm32-unknown-unknown/release/tuple_returns.wasm");
+    let tuples = new TupleReturns(new WebAssembly.Module(code));
+
+    console.log(tuples.min_max([4, -2, 9, 3]));
+    console.log(tuples.min_max([]));
+    console.log(tuples.split_first_word("hello tuple world"));
+    console.log(tuples.word_lengths("one three fifteen"));
+    console.log(tuples.divide(17, 5));
+    try {
+        tuples.divide(1, 0);
+    } catch (e) {
+        console.log(`divide(1, 0) threw: ${e.message}`);
+    }
+}
+
+main();
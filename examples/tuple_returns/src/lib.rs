@@ -0,0 +1,43 @@
+#[macro_use]
+extern crate wasm_wrapper_gen;
+
+fn min_max(numbers: &[i32]) -> Option<(i32, i32)> {
+    let min = *numbers.iter().min()?;
+    let max = *numbers.iter().max()?;
+    Some((min, max))
+}
+
+fn split_first_word(text: &str) -> (String, String) {
+    let text = text.trim_start();
+    match text.find(char::is_whitespace) {
+        Some(index) => (text[..index].to_owned(), text[index..].trim_start().to_owned()),
+        None => (text.to_owned(), String::new()),
+    }
+}
+
+fn word_lengths(text: &str) -> (usize, Vec<u32>) {
+    let lengths = text
+        .split_whitespace()
+        .map(|word| word.chars().count() as u32)
+        .collect::<Vec<_>>();
+    (lengths.len(), lengths)
+}
+
+js_fn! {
+    /// Smallest and largest of the numbers, or null if there are none.
+    #[js_return_names(min, max)]
+    fn min_max(numbers: &[i32]) -> Option<(i32, i32)> => min_max;
+
+    /// The first word of the text and the rest of it.
+    fn split_first_word(text: &str) -> (String, String) => split_first_word;
+
+    fn word_lengths(text: &str) -> (usize, Vec<u32>) => word_lengths;
+
+    fn divide(a: u32, b: u32) -> Result<(u32, u32), String> {
+        if b == 0 {
+            Err("division by zero".to_owned())
+        } else {
+            Ok((a / b, a % b))
+        }
+    }
+}
@@ -0,0 +1,15 @@
+extern crate wasm_wrapper_gen_build;
+
+use wasm_wrapper_gen_build::StringEncoding;
+
+fn main() {
+    // must match wasm-wrapper-gen's "utf8-strings" feature.
+    wasm_wrapper_gen_build::Config::new()
+        .with_class_name("Utf8Strings")
+        .with_string_encoding(StringEncoding::Utf8)
+        .translate("src/lib.rs", "target/wrapper.js")
+        .unwrap_or_else(|e| {
+            eprintln!("error: {}", e);
+            ::std::process::exit(1);
+        });
+}
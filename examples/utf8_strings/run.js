@@ -0,0 +1,16 @@
+#!/usr/bin/env node
+const fs = require('fs');
+const Utf8Strings = require('./target/wrapper.js');
+
+function main() {
+    let code = fs.readFileSync("target/wasm32-unknown-unknown/release/utf8_strings.wasm");
+    let strings = new Utf8Strings(new WebAssembly.Module(code));
+
+    console.log(strings.greeting());
+
+    let input = "añb 🎉 c";
+    console.log(`reverse("${input}"): ${strings.reverse(input)}`);
+    console.log(`char_count("${input}"): ${strings.char_count(input)}`);
+}
+
+main();
@@ -0,0 +1,18 @@
+#[macro_use]
+extern crate wasm_wrapper_gen;
+
+fn reverse(text: String) -> String {
+    text.chars().rev().collect()
+}
+
+fn char_count(text: &str) -> usize {
+    text.chars().count()
+}
+
+js_fn! {
+    fn reverse(text: String) -> String => reverse;
+    fn char_count(text: &str) -> usize => char_count;
+    fn greeting() -> &str {
+        "¡Hola, señor! 👋"
+    }
+}
@@ -0,0 +1,39 @@
+#!/usr/bin/env node
+const fs = require('fs');
+const Pixels = require('./target/wrapper.js');
+
+function main() {
+    let code = fs.readFileSync("target/wasm32-unknown-unknown/release/zero_copy_buffers.wasm");
+    let pixels = new Pixels(new WebAssembly.Module(code));
+
+    // returned buffers are viewed in place, and freed explicitly.
+    let image = pixels.gradient(4, 3);
+    console.log(Array.from(image.view));
+
+    // they can be passed back, and changed in place, without being copied.
+    pixels.brighten(image, 100);
+    console.log(Array.from(image.view));
+    let counts = pixels.histogram(image, 4);
+    console.log(Array.from(counts.view));
+    counts.free();
+    image.free();
+
+    // buffers allocated from JavaScript are filled once and reused.
+    let values = pixels.allocBuffer("f64", 4);
+    values.view.set([1.5, 2.5, 3.5, 4.5]);
+    console.log(pixels.mean(values));
+    values.view[0] = 9.5;
+    console.log(pixels.mean(values));
+    values.free();
+
+    // plain arrays are still copied in.
+    console.log(pixels.mean([1, 2, 3]));
+
+    try {
+        pixels.mean(values);
+    } catch (e) {
+        console.log(`${e.name}: ${e.message}`);
+    }
+}
+
+main();
@@ -0,0 +1,43 @@
+#[macro_use]
+extern crate wasm_wrapper_gen;
+
+use wasm_wrapper_gen::JsBuffer;
+
+fn gradient(width: u32, height: u32) -> JsBuffer<u8> {
+    let mut pixels = Vec::with_capacity((width * height) as usize);
+    for y in 0..height {
+        for x in 0..width {
+            pixels.push(((x + y) * 255 / (width + height - 2).max(1)) as u8);
+        }
+    }
+    pixels.into()
+}
+
+fn brighten(pixels: &mut [u8], amount: u8) {
+    for pixel in pixels {
+        *pixel = pixel.saturating_add(amount);
+    }
+}
+
+fn histogram(pixels: &[u8], buckets: u32) -> JsBuffer<u32> {
+    let mut counts = vec![0; buckets as usize];
+    for &pixel in pixels {
+        counts[pixel as usize * buckets as usize / 256] += 1;
+    }
+    counts.into()
+}
+
+js_fn! {
+    /// Creates a grayscale image fading diagonally from black to white.
+    fn gradient(width: u32, height: u32) -> JsBuffer<u8> => gradient;
+
+    /// Brightens every pixel of a grayscale image in place.
+    fn brighten(pixels: &mut [u8], amount: u8) => brighten;
+
+    /// Counts the pixels falling into each of the given number of buckets.
+    fn histogram(pixels: &[u8], buckets: u32) -> JsBuffer<u32> => histogram;
+
+    fn mean(values: &[f64]) -> f64 {
+        values.iter().sum::<f64>() / values.len() as f64
+    }
+}
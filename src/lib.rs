@@ -4,13 +4,394 @@ extern crate proc_macro_hack;
 #[allow(unused_imports)]
 #[macro_use]
 extern crate wasm_wrapper_gen_impl;
+#[cfg(feature = "serde-json")]
+extern crate serde;
+#[cfg(feature = "serde-json")]
+extern crate serde_json;
 #[doc(hidden)]
 pub use wasm_wrapper_gen_impl::*;
 
+use std::borrow::Cow;
+use std::ops::Deref;
+use std::string::FromUtf16Error;
+use std::{mem, slice};
+
 proc_macro_item_decl! {
     js_fn! => __js_fn_impl
 }
 
+proc_macro_item_decl! {
+    js_import! => __js_import_impl
+}
+
+/// A JavaScript string argument's UTF-16 code units, passed through without
+/// validation.
+///
+/// JavaScript strings may contain lone surrogates, which `String` and `&str`
+/// arguments replace (or reject, with strict validation). Taking `JsString`
+/// instead gives rust the exact code units, in either string encoding.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct JsString {
+    code_units: Vec<u16>,
+}
+
+impl JsString {
+    pub fn code_units(&self) -> &[u16] {
+        &self.code_units
+    }
+
+    pub fn into_code_units(self) -> Vec<u16> {
+        self.code_units
+    }
+
+    /// Whether the string is valid UTF-16, without any lone surrogates.
+    pub fn is_well_formed(&self) -> bool {
+        ::std::char::decode_utf16(self.code_units.iter().cloned()).all(|c| c.is_ok())
+    }
+
+    pub fn to_string_lossy(&self) -> String {
+        String::from_utf16_lossy(&self.code_units)
+    }
+
+    pub fn try_to_string(&self) -> Result<String, FromUtf16Error> {
+        String::from_utf16(&self.code_units)
+    }
+}
+
+impl From<Vec<u16>> for JsString {
+    fn from(code_units: Vec<u16>) -> Self {
+        JsString { code_units }
+    }
+}
+
+impl Deref for JsString {
+    type Target = [u16];
+
+    fn deref(&self) -> &[u16] {
+        &self.code_units
+    }
+}
+
+/// A value of any serde type, passed to or returned from JavaScript as JSON.
+///
+/// JavaScript gives arguments as values which `JSON.stringify` accepts, and
+/// receives return values as `JSON.parse` would give them. Arguments which
+/// don't deserialize into `T` cause a panic.
+///
+/// Requires the "serde-json" feature.
+#[cfg(feature = "serde-json")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Json<T>(pub T);
+
+#[cfg(feature = "serde-json")]
+impl<T> Json<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+#[cfg(feature = "serde-json")]
+impl<T> From<T> for Json<T> {
+    fn from(value: T) -> Self {
+        Json(value)
+    }
+}
+
+#[cfg(feature = "serde-json")]
+impl<T> Deref for Json<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+#[cfg(feature = "serde-json")]
+impl<T> ::std::ops::DerefMut for Json<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+/// Numbers returned to JavaScript without copying them, as a `JsBuffer`
+/// object with a `TypedArray` view over WebAssembly memory.
+///
+/// JavaScript owns the memory once it's returned, and frees it by calling
+/// `free()`. Requires generating the wrapper with zero-copy buffers enabled.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct JsBuffer<T> {
+    items: Vec<T>,
+}
+
+impl<T> JsBuffer<T> {
+    pub fn into_inner(self) -> Vec<T> {
+        self.items
+    }
+}
+
+impl<T> From<Vec<T>> for JsBuffer<T> {
+    fn from(items: Vec<T>) -> Self {
+        JsBuffer { items }
+    }
+}
+
+impl<T> Deref for JsBuffer<T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        &self.items
+    }
+}
+
+impl<T> ::std::ops::DerefMut for JsBuffer<T> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        &mut self.items
+    }
+}
+
+/// Installs the panic hook recording panic messages for JavaScript, if the
+/// "panic-messages" feature is enabled. Called by every generated function.
+#[doc(hidden)]
+#[inline]
+pub fn __js_fn_install_panic_hook() {
+    #[cfg(feature = "panic-messages")]
+    _extern_definitions::panic_messages::install_hook();
+}
+
+/// Reads a string argument from memory owned by JavaScript: UTF-16 code
+/// units, or UTF-8 bytes if the "utf8-strings" feature is enabled.
+///
+/// Invalid data is replaced with U+FFFD REPLACEMENT CHARACTER.
+#[doc(hidden)]
+pub unsafe fn __js_fn_string_argument(ptr: *const u8, len: usize) -> String {
+    __js_fn_str_argument(ptr, len).into_owned()
+}
+
+/// Reads a `&str` argument from memory owned by JavaScript, which stays
+/// allocated until the call returns.
+///
+/// Valid UTF-8 strings are borrowed without copying, UTF-16 strings always
+/// have to be converted.
+#[doc(hidden)]
+pub unsafe fn __js_fn_str_argument<'a>(ptr: *const u8, len: usize) -> Cow<'a, str> {
+    #[cfg(not(feature = "utf8-strings"))]
+    let string = {
+        let units = slice::from_raw_parts(ptr as *const u16, len);
+        Cow::Owned(String::from_utf16_lossy(units))
+    };
+    #[cfg(feature = "utf8-strings")]
+    let string = String::from_utf8_lossy(slice::from_raw_parts(ptr, len));
+    string
+}
+
+/// Copies a `JsString` argument's UTF-16 code units from memory owned by
+/// JavaScript.
+#[doc(hidden)]
+pub unsafe fn __js_fn_js_string_argument(ptr: *const u16, len: usize) -> JsString {
+    JsString::from(slice::from_raw_parts(ptr, len).to_vec())
+}
+
+/// Deserializes a `Json` argument from a string in memory owned by
+/// JavaScript.
+#[cfg(feature = "serde-json")]
+#[doc(hidden)]
+pub unsafe fn __js_fn_json_argument<T>(ptr: *const u8, len: usize) -> Json<T>
+where
+    T: serde::de::DeserializeOwned,
+{
+    let text = __js_fn_str_argument(ptr, len);
+    match serde_json::from_str(&text) {
+        Ok(value) => Json(value),
+        Err(e) => panic!("failed to deserialize JSON argument: {}", e),
+    }
+}
+
+/// Serializes a `Json` return value, boxed like a string return value.
+#[cfg(feature = "serde-json")]
+#[doc(hidden)]
+pub fn __js_fn_return_json<T>(value: Json<T>) -> *const usize
+where
+    T: serde::Serialize,
+{
+    match serde_json::to_string(&value.0) {
+        Ok(text) => __js_fn_return_string(text),
+        Err(e) => panic!("failed to serialize JSON return value: {}", e),
+    }
+}
+
+/// Boxes a string return value as `[ptr, len, capacity]` of UTF-16 code
+/// units, or of UTF-8 bytes if the "utf8-strings" feature is enabled.
+#[doc(hidden)]
+pub fn __js_fn_return_string<S>(value: S) -> *const usize
+where
+    S: AsRef<str> + Into<String>,
+{
+    #[cfg(not(feature = "utf8-strings"))]
+    let buffer: Vec<u16> = value.as_ref().encode_utf16().collect();
+    // UTF-8 strings are returned without copying.
+    #[cfg(feature = "utf8-strings")]
+    let buffer: Vec<u8> = value.into().into_bytes();
+
+    let to_return = Box::new([buffer.as_ptr() as usize, buffer.len(), buffer.capacity()]);
+    mem::forget(buffer);
+    Box::into_raw(to_return) as *const usize
+}
+
+/// Encodes a string passed to an imported JavaScript function as UTF-16 code
+/// units.
+#[cfg(not(feature = "utf8-strings"))]
+#[doc(hidden)]
+pub fn __js_fn_encode_string(value: &str) -> Vec<u16> {
+    value.encode_utf16().collect()
+}
+
+/// Encodes a string passed to an imported JavaScript function, which is
+/// already UTF-8.
+#[cfg(feature = "utf8-strings")]
+#[doc(hidden)]
+pub fn __js_fn_encode_string(value: &str) -> &[u8] {
+    value.as_bytes()
+}
+
+/// Serializes a `Json` value passed to an imported JavaScript function.
+#[cfg(feature = "serde-json")]
+#[doc(hidden)]
+pub fn __js_fn_encode_json<T>(value: &Json<T>) -> String
+where
+    T: serde::Serialize,
+{
+    match serde_json::to_string(&value.0) {
+        Ok(text) => text,
+        Err(e) => panic!("failed to serialize JSON argument: {}", e),
+    }
+}
+
+/// Takes a string returned from an imported JavaScript function, which wrote
+/// `[ptr, len, byte_len]` of memory allocated with the alloc builtin into
+/// `slot`.
+#[doc(hidden)]
+pub unsafe fn __js_fn_import_string_result(slot: [usize; 3]) -> String {
+    let [ptr, len, byte_len] = slot;
+    let string = __js_fn_string_argument(ptr as *const u8, len);
+    _extern_definitions::__js_fn__builtin_dealloc(ptr as *mut u8, byte_len);
+    string
+}
+
+/// Deserializes a `Json` value returned from an imported JavaScript function,
+/// see `__js_fn_import_string_result`.
+#[cfg(feature = "serde-json")]
+#[doc(hidden)]
+pub unsafe fn __js_fn_import_json_result<T>(slot: [usize; 3]) -> Json<T>
+where
+    T: serde::de::DeserializeOwned,
+{
+    let text = __js_fn_import_string_result(slot);
+    match serde_json::from_str(&text) {
+        Ok(value) => Json(value),
+        Err(e) => panic!("failed to deserialize JSON returned from JavaScript: {}", e),
+    }
+}
+
+/// A value passed to or returned from a JavaScript callback through an
+/// 8-byte slot, so that every callback can be called through the same
+/// imported function. Values are stored in the slot's low bytes, as the
+/// WebAssembly type they would be passed as.
+#[doc(hidden)]
+pub trait __JsCallbackSlot {
+    fn __into_slot(self) -> u64;
+    fn __from_slot(slot: u64) -> Self;
+}
+
+macro_rules! impl_callback_slot {
+    ($($ty:ty => $wasm_ty:ty),* $(,)*) => {
+        $(
+            impl __JsCallbackSlot for $ty {
+                fn __into_slot(self) -> u64 {
+                    self as $wasm_ty as u64
+                }
+
+                fn __from_slot(slot: u64) -> Self {
+                    slot as $wasm_ty as $ty
+                }
+            }
+        )*
+    };
+}
+
+impl_callback_slot! {
+    u8 => u32,
+    u16 => u32,
+    u32 => u32,
+    usize => u32,
+    i8 => u32,
+    i16 => u32,
+    i32 => u32,
+    isize => u32,
+    u64 => u64,
+    i64 => u64,
+}
+
+impl __JsCallbackSlot for bool {
+    fn __into_slot(self) -> u64 {
+        self as u64
+    }
+
+    fn __from_slot(slot: u64) -> Self {
+        slot as u32 != 0
+    }
+}
+
+impl __JsCallbackSlot for f32 {
+    fn __into_slot(self) -> u64 {
+        u64::from(self.to_bits())
+    }
+
+    fn __from_slot(slot: u64) -> Self {
+        f32::from_bits(slot as u32)
+    }
+}
+
+impl __JsCallbackSlot for f64 {
+    fn __into_slot(self) -> u64 {
+        self.to_bits()
+    }
+
+    fn __from_slot(slot: u64) -> Self {
+        f64::from_bits(slot)
+    }
+}
+
+impl<T> __JsCallbackSlot for *const T {
+    fn __into_slot(self) -> u64 {
+        self as usize as u32 as u64
+    }
+
+    fn __from_slot(slot: u64) -> Self {
+        slot as u32 as usize as *const T
+    }
+}
+
+#[allow(non_snake_case)]
+extern "C" {
+    fn __js_import__builtin_callback(index: usize, args: *const u64, return_slot: *mut u64);
+}
+
+/// Calls the JavaScript callback registered at `index` with arguments stored
+/// in slots, returning the slot its result was written to.
+#[doc(hidden)]
+pub fn __js_fn_call_callback(index: usize, args: &[u64]) -> [u64; 3] {
+    let mut return_slot = [0u64; 3];
+    unsafe { __js_import__builtin_callback(index, args.as_ptr(), return_slot.as_mut_ptr()) };
+    return_slot
+}
+
+/// Reads the `[ptr, len, byte_len]` of a string returned from a callback out
+/// of its return slot, see `__js_fn_import_string_result`.
+#[doc(hidden)]
+pub fn __js_fn_callback_string_slot(slot: [u64; 3]) -> [usize; 3] {
+    unsafe { *(slot.as_ptr() as *const [usize; 3]) }
+}
+
 #[doc(hidden)]
 pub mod _extern_definitions {
     use std::mem;
@@ -37,4 +418,98 @@ pub mod _extern_definitions {
 
         Vec::<u8>::from_raw_parts(ptr, 0, len);
     }
+
+    /// Marks modules expecting UTF-8 strings, so that wrappers can be checked
+    /// against the module's string encoding.
+    #[cfg(feature = "utf8-strings")]
+    #[allow(non_snake_case)]
+    #[no_mangle]
+    pub extern "C" fn __js_fn__builtin_utf8_strings() {}
+
+    #[cfg(feature = "scratch-arena")]
+    pub mod scratch_arena {
+        use std::cell::RefCell;
+
+        thread_local! {
+            /// Region JavaScript bump-allocates borrowed arguments in, as
+            /// 8-byte words so that any argument can be aligned in it.
+            static SCRATCH: RefCell<Vec<u64>> = RefCell::new(Vec::new());
+        }
+
+        /// Grows the scratch region to at least `len` bytes, returning where it
+        /// now is. Nothing in the region may be in use, since it may move.
+        #[allow(non_snake_case)]
+        #[no_mangle]
+        pub extern "C" fn __js_fn__builtin_scratch_grow(len: usize) -> *mut u8 {
+            SCRATCH.with(|scratch| {
+                let mut scratch = scratch.borrow_mut();
+                let words = (len + 7) / 8;
+                if scratch.capacity() < words {
+                    // replaced rather than reserved, since the contents don't
+                    // need to be kept.
+                    *scratch = Vec::with_capacity(words);
+                }
+                scratch.as_mut_ptr() as *mut u8
+            })
+        }
+    }
+
+    #[cfg(feature = "panic-messages")]
+    pub mod panic_messages {
+        use std::cell::RefCell;
+        use std::panic;
+        use std::sync::Once;
+
+        thread_local! {
+            /// Message and location of the last panic, until read from JavaScript.
+            static LAST_PANIC: RefCell<(Option<String>, Option<String>)> =
+                RefCell::new((None, None));
+        }
+
+        static INSTALL_HOOK: Once = Once::new();
+
+        pub fn install_hook() {
+            INSTALL_HOOK.call_once(|| {
+                // kept so that hooks installed before this one, like ones logging
+                // panics to the console, still run.
+                let previous_hook = panic::take_hook();
+                panic::set_hook(Box::new(move |info| {
+                    let payload = info.payload();
+                    let message = match payload.downcast_ref::<&str>() {
+                        Some(message) => message.to_string(),
+                        None => match payload.downcast_ref::<String>() {
+                            Some(message) => message.clone(),
+                            None => "Box<Any>".to_owned(),
+                        },
+                    };
+                    let location = info.location().map(|location| {
+                        format!("{}:{}:{}", location.file(), location.line(), location.column())
+                    });
+                    LAST_PANIC.with(|last| *last.borrow_mut() = (Some(message), location));
+                    previous_hook(info);
+                }));
+            });
+        }
+
+        /// Returns the string boxed the same way as `String` return values, or
+        /// null if there is none.
+        fn return_string(value: Option<String>) -> *const usize {
+            match value {
+                Some(value) => ::__js_fn_return_string(value),
+                None => ::std::ptr::null(),
+            }
+        }
+
+        #[allow(non_snake_case)]
+        #[no_mangle]
+        pub extern "C" fn __js_fn__builtin_panic_message() -> *const usize {
+            return_string(LAST_PANIC.with(|last| last.borrow_mut().0.take()))
+        }
+
+        #[allow(non_snake_case)]
+        #[no_mangle]
+        pub extern "C" fn __js_fn__builtin_panic_location() -> *const usize {
+            return_string(LAST_PANIC.with(|last| last.borrow_mut().1.take()))
+        }
+    }
 }
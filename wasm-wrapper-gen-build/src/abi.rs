@@ -0,0 +1,179 @@
+//! How `js_fn!` functions are exported from the compiled module, and how
+//! `js_import!` functions are imported, mirroring the code
+//! `wasm-wrapper-gen-impl` generates.
+use std::fmt;
+
+use wasm_wrapper_gen_shared::{drop_fn_name, import_fn_name, JsFnInfo, JsImportInfo,
+                              SupportedArgumentType, SupportedCopyTy, SupportedRetType,
+                              TransformedRustIdent};
+
+/// Prefix of every function exported by `js_fn!`.
+pub(crate) static EXPORT_PREFIX: &str = "__js_fn_";
+/// Prefix of functions exported by `wasm-wrapper-gen` itself, rather than
+/// by `js_fn!`. These are exported whether or not they're used.
+pub(crate) static BUILTIN_EXPORT_PREFIX: &str = "__js_fn__builtin_";
+/// Module `js_import!` functions are imported from.
+pub(crate) static IMPORT_MODULE: &str = "env";
+
+/// A WebAssembly value type.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum WasmType {
+    I32,
+    I64,
+    F32,
+    F64,
+    /// A type never used by `js_fn!` exports, such as `v128` or a reference.
+    Other(String),
+}
+
+/// One parameter of an exported function.
+pub(crate) struct WasmParam {
+    pub name: String,
+    pub ty: WasmType,
+    /// Index of the argument this is part of, or `None` for `self` and the
+    /// slot imported functions return strings through.
+    pub argument: Option<usize>,
+}
+
+impl fmt::Display for WasmType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            WasmType::I32 => write!(f, "i32"),
+            WasmType::I64 => write!(f, "i64"),
+            WasmType::F32 => write!(f, "f32"),
+            WasmType::F64 => write!(f, "f64"),
+            WasmType::Other(ref name) => write!(f, "{}", name),
+        }
+    }
+}
+
+pub(crate) fn export_name(info: &JsFnInfo) -> String {
+    TransformedRustIdent::new(info.qualified_name()).to_string()
+}
+
+pub(crate) fn drop_export_name(self_ty: &str) -> String {
+    TransformedRustIdent::new(drop_fn_name(self_ty)).to_string()
+}
+
+pub(crate) fn builtin_export_name(builtin: &str) -> String {
+    format!("{}{}", BUILTIN_EXPORT_PREFIX, builtin)
+}
+
+pub(crate) fn import_name(info: &JsImportInfo) -> String {
+    import_fn_name(&info.rust_name)
+}
+
+pub(crate) fn copy_ty_to_wasm(ty: SupportedCopyTy) -> WasmType {
+    match ty {
+        SupportedCopyTy::F32 => WasmType::F32,
+        SupportedCopyTy::F64 => WasmType::F64,
+        _ if ty.is_64_bit_integer() => WasmType::I64,
+        // everything else, including bools, is passed as an i32.
+        _ => WasmType::I32,
+    }
+}
+
+/// Finds the parameters of a function's export, named after the rust
+/// arguments they come from.
+pub(crate) fn lowered_params(info: &JsFnInfo) -> Vec<WasmParam> {
+    let mut params = Vec::new();
+    // pointer to the boxed value.
+    if info.self_arg.is_some() {
+        params.push(WasmParam {
+            name: "self_ptr".to_owned(),
+            ty: WasmType::I32,
+            argument: None,
+        });
+    }
+    for (index, (name, ty)) in info.args_names.iter().zip(&info.args_ty).enumerate() {
+        lower_argument_into(index, name, ty, &mut params);
+    }
+    params
+}
+
+/// Adds the parameters an argument is lowered to, matching
+/// `expand_argument_into` in `wasm-wrapper-gen-impl`.
+fn lower_argument_into(
+    index: usize,
+    name: &str,
+    ty: &SupportedArgumentType,
+    params: &mut Vec<WasmParam>,
+) {
+    let mut push = |suffix: &str, ty: WasmType| {
+        params.push(WasmParam {
+            name: format!("{}{}", name, suffix),
+            ty,
+            argument: Some(index),
+        })
+    };
+    match *ty {
+        SupportedArgumentType::Integer(int_ty) => push("", copy_ty_to_wasm(int_ty)),
+        SupportedArgumentType::IntegerSliceRef(_)
+        | SupportedArgumentType::IntegerSliceMutRef(_)
+        | SupportedArgumentType::IntegerVec(_)
+        | SupportedArgumentType::OwnedString
+        | SupportedArgumentType::StringSlice
+        | SupportedArgumentType::JsString
+        | SupportedArgumentType::Json => {
+            push("_ptr", WasmType::I32);
+            push("_len", WasmType::I32);
+        }
+        // the index the callback is registered at.
+        SupportedArgumentType::Callback(..) => push("_index", WasmType::I32),
+        SupportedArgumentType::Optional(ref inner) => {
+            // integers have a separate flag for whether or not they're present.
+            if let SupportedArgumentType::Integer(_) = **inner {
+                push("_is_some", WasmType::I32);
+            }
+            lower_argument_into(index, name, inner, params);
+        }
+    }
+}
+
+/// Finds the results of a function's export, matching `WrittenReturnType` in
+/// `wasm-wrapper-gen-impl`.
+pub(crate) fn lowered_results(ty: &SupportedRetType) -> Vec<WasmType> {
+    match *ty {
+        SupportedRetType::Unit => Vec::new(),
+        SupportedRetType::Integer(int_ty) => vec![copy_ty_to_wasm(int_ty)],
+        // everything else is returned as a pointer.
+        _ => vec![WasmType::I32],
+    }
+}
+
+/// Finds the parameters of an imported function, matching
+/// `lower_import_argument` in `wasm-wrapper-gen-impl`.
+pub(crate) fn lowered_import_params(info: &JsImportInfo) -> Vec<WasmParam> {
+    let mut params = Vec::new();
+    for (index, (name, ty)) in info.args_names.iter().zip(&info.args_ty).enumerate() {
+        lower_argument_into(index, name, ty, &mut params);
+    }
+    match info.ret_ty {
+        SupportedRetType::Unit | SupportedRetType::Integer(_) => {}
+        // strings are written to memory the caller passes a pointer to.
+        _ => params.push(WasmParam {
+            name: "return_slot".to_owned(),
+            ty: WasmType::I32,
+            argument: None,
+        }),
+    }
+    params
+}
+
+/// Finds the values a callback's arguments are lowered to, in the order
+/// they're stored in 8-byte slots. Arguments are named by their position.
+pub(crate) fn lowered_callback_args(args_ty: &[SupportedArgumentType]) -> Vec<WasmParam> {
+    let mut params = Vec::new();
+    for (index, ty) in args_ty.iter().enumerate() {
+        lower_argument_into(index, &format!("arg{}", index), ty, &mut params);
+    }
+    params
+}
+
+/// Finds the results of an imported function.
+pub(crate) fn lowered_import_results(ty: &SupportedRetType) -> Vec<WasmType> {
+    match *ty {
+        SupportedRetType::Integer(int_ty) => vec![copy_ty_to_wasm(int_ty)],
+        _ => Vec::new(),
+    }
+}
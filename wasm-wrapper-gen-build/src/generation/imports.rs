@@ -0,0 +1,491 @@
+//! Glue calling functions declared in `js_import!` with the implementations
+//! passed to the constructor: the import object given to the module, and a
+//! method per function converting its arguments and result.
+
+use std::fmt::{self, Display, Write};
+
+use failure::Error;
+
+use wasm_wrapper_gen_shared::{callback_import_name, import_fn_name, JsImportInfo, SupportedArgumentType,
+                              SupportedRetType};
+
+use abi::{self, WasmType};
+use style::{AccessStyle, Config};
+
+use super::indented_write::WriteExt;
+use super::stats::import_uses_memory_access;
+use super::{copy_array_out, copy_string_out, js_argument_names, prepare_argument_allocation,
+            read_return_value_copy_into, validate_argument, write_usize_array};
+
+/// Name of the method converting the arguments and result of an imported
+/// function.
+pub(super) fn import_method_name(info: &JsImportInfo) -> String {
+    format!("_import_{}", info.rust_name)
+}
+
+/// Writes the check that every imported function was passed to the
+/// constructor.
+pub(super) fn write_imports_check<T>(
+    config: &Config,
+    buf: &mut T,
+    imports: &[JsImportInfo],
+) -> Result<(), Error>
+where
+    T: Write,
+{
+    let names = imports
+        .iter()
+        .map(|info| format!("\"{}\"", info.rust_name))
+        .collect::<Vec<_>>();
+    writeln!(buf, "for (let name of [{}]) {{", names.join(", "))?;
+    {
+        let buf = &mut buf.indented(config.indent);
+        writeln!(
+            buf,
+            "if (imports == null || typeof imports[name] != 'function') {{"
+        )?;
+        writeln!(
+            buf.indented(config.indent),
+            "throw new TypeError(\"missing imported function `\" + name + \"`\");"
+        )?;
+        writeln!(buf, "}}")?;
+    }
+    writeln!(buf, "}}")?;
+    writeln!(buf, "this._imports = imports;")?;
+
+    Ok(())
+}
+
+/// Writes the static method creating the import object for the module, which
+/// calls into the wrapper returned by `get_wrapper`. Callbacks are called
+/// through a single import, by their index in the wrapper's `_callbacks`.
+///
+/// The wrapper is looked up on every call since `create` can only construct
+/// it once the module is instantiated.
+pub(super) fn write_import_object_method<T>(
+    config: &Config,
+    buf: &mut T,
+    imports: &[JsImportInfo],
+    uses_callbacks: bool,
+) -> Result<(), Error>
+where
+    T: Write,
+{
+    write!(buf, "\nstatic _import_object(get_wrapper) {{\n")?;
+    {
+        let buf = &mut buf.indented(config.indent);
+        writeln!(buf, "return {{")?;
+        {
+            let buf = &mut buf.indented(config.indent);
+            // rust imports from "env" unless told otherwise.
+            writeln!(buf, "env: {{")?;
+            for info in imports {
+                writeln!(
+                    buf.indented(config.indent),
+                    "{}: (...args) => get_wrapper().{}(...args),",
+                    import_fn_name(&info.rust_name),
+                    import_method_name(info)
+                )?;
+            }
+            if uses_callbacks {
+                writeln!(
+                    buf.indented(config.indent),
+                    "{}: (index, args_ptr, return_slot) => \
+                     get_wrapper()._callbacks[index](args_ptr, return_slot),",
+                    callback_import_name()
+                )?;
+            }
+            writeln!(buf, "}},")?;
+        }
+        writeln!(buf, "}};")?;
+    }
+    writeln!(buf, "}}")?;
+
+    Ok(())
+}
+
+/// Writes the method called by the module for an imported function, reading
+/// its arguments from memory and passing its result back.
+pub(super) fn write_import_method<T>(
+    config: &Config,
+    buf: &mut T,
+    info: &JsImportInfo,
+) -> Result<(), Error>
+where
+    T: Write,
+{
+    let arg_names = js_argument_names(&info.args_names);
+
+    let mut params = Vec::new();
+    for (arg_name, ty) in arg_names.iter().zip(&info.args_ty) {
+        push_import_params(&mut params, arg_name, ty);
+    }
+    if returns_through_slot(&info.ret_ty) {
+        params.push("return_slot".to_owned());
+    }
+
+    write!(buf, "\n{}({}) {{\n", import_method_name(info), params.join(", "))?;
+    {
+        let buf = &mut buf.indented(config.indent);
+        // memory may have grown since the wrapper last called into the module.
+        if config.access_style == AccessStyle::DataView && import_uses_memory_access(info) {
+            writeln!(buf, "this._check_mem_realloc();")?;
+        }
+        write_js_call(
+            config,
+            buf,
+            &arg_names,
+            &info.args_ty,
+            &info.ret_ty,
+            JsFunction::Import(&info.rust_name),
+        )?;
+    }
+    writeln!(buf, "}}")?;
+
+    Ok(())
+}
+
+/// Registers a callback argument in the wrapper's table of callbacks, as a
+/// function reading the callback's arguments from the slots rust stores them
+/// in and writing its result to the return slot.
+///
+/// The callback's index is `{arg_name}_index`, and it's unregistered again
+/// by `write_callback_unregistration` once the call returns.
+pub(super) fn write_callback_registration<T>(
+    config: &Config,
+    buf: &mut T,
+    arg_name: &str,
+    args_ty: &[SupportedArgumentType],
+    ret_ty: &SupportedRetType,
+) -> fmt::Result
+where
+    T: Write,
+{
+    // variables are prefixed with the argument's name so that they can't
+    // shadow it.
+    let args_ptr = format!("{}_args_ptr", arg_name);
+    let callback_arg_names = (0..args_ty.len())
+        .map(|index| format!("{}_arg{}", arg_name, index))
+        .collect::<Vec<_>>();
+
+    writeln!(buf, "var {0}_index = this._callbacks.length;", arg_name)?;
+    writeln!(
+        buf,
+        "this._callbacks.push(({}, {}_return_slot) => {{",
+        args_ptr, arg_name
+    )?;
+    {
+        let buf = &mut buf.indented(config.indent);
+        if config.access_style == AccessStyle::DataView {
+            writeln!(buf, "this._check_mem_realloc();")?;
+        }
+        let mut params = Vec::new();
+        for (callback_arg_name, ty) in callback_arg_names.iter().zip(args_ty) {
+            push_import_params(&mut params, callback_arg_name, ty);
+        }
+        let slots = abi::lowered_callback_args(args_ty);
+        for (index, (param, slot)) in params.iter().zip(&slots).enumerate() {
+            write!(buf, "let {} = ", param)?;
+            access_slot(
+                config,
+                buf,
+                format_args!("{} + {}", args_ptr, index * SLOT_SIZE),
+                &slot.ty,
+                None,
+            )?;
+            writeln!(buf, ";")?;
+        }
+        write_js_call(
+            config,
+            buf,
+            &callback_arg_names,
+            args_ty,
+            ret_ty,
+            JsFunction::Callback(arg_name),
+        )?;
+    }
+    writeln!(buf, "}});")?;
+
+    Ok(())
+}
+
+/// Unregisters a callback registered by `write_callback_registration`,
+/// along with any registered after it.
+pub(super) fn write_callback_unregistration<T>(
+    config: &Config,
+    buf: &mut T,
+    arg_name: &str,
+) -> fmt::Result
+where
+    T: Write,
+{
+    // callbacks aren't registered yet if preparing an earlier argument threw.
+    writeln!(buf, "if ({}_index !== undefined) {{", arg_name)?;
+    writeln!(
+        buf.indented(config.indent),
+        "this._callbacks.splice({}_index);",
+        arg_name
+    )?;
+    writeln!(buf, "}}")
+}
+
+/// Size of the slots callback arguments and results are stored in.
+const SLOT_SIZE: usize = 8;
+
+/// A JavaScript function called from rust.
+enum JsFunction<'a> {
+    /// An imported function with the given name. Strings are written to
+    /// `return_slot`, and integers are returned to WebAssembly.
+    Import(&'a str),
+    /// A callback passed as the given argument, whose results are all written
+    /// to `{arg_name}_return_slot`.
+    Callback(&'a str),
+}
+
+/// Strings are returned by writing where they were allocated into a slot.
+fn returns_through_slot(ret_ty: &SupportedRetType) -> bool {
+    !matches!(
+        *ret_ty,
+        SupportedRetType::Unit | SupportedRetType::Integer(_)
+    )
+}
+
+/// Writes the conversion of arguments passed from rust, the call to the
+/// JavaScript function, and the conversion of its result.
+///
+/// Arguments are read from parameters named like the ones
+/// `push_import_params` gives.
+fn write_js_call<T>(
+    config: &Config,
+    buf: &mut T,
+    arg_names: &[String],
+    args_ty: &[SupportedArgumentType],
+    ret_ty: &SupportedRetType,
+    function: JsFunction,
+) -> fmt::Result
+where
+    T: Write,
+{
+    for (arg_name, ty) in arg_names.iter().zip(args_ty) {
+        read_import_argument(config, buf, arg_name, ty, arg_name)?;
+    }
+    if *ret_ty != SupportedRetType::Unit {
+        write!(buf, "let result = ")?;
+    }
+    match function {
+        JsFunction::Import(name) => write!(buf, "this._imports.{}", name)?,
+        JsFunction::Callback(arg_name) => write!(buf, "{}", arg_name)?,
+    }
+    writeln!(buf, "({});", arg_names.join(", "))?;
+    let result_ty = match *ret_ty {
+        SupportedRetType::Unit => return Ok(()),
+        SupportedRetType::Integer(int_ty) => SupportedArgumentType::Integer(int_ty),
+        SupportedRetType::Json => SupportedArgumentType::Json,
+        _ => SupportedArgumentType::OwnedString,
+    };
+    // results are converted like arguments passed to exported functions.
+    validate_argument(
+        config,
+        buf,
+        "result",
+        &result_ty,
+        match function {
+            JsFunction::Import(name) => format!(
+                "throw new TypeError(\"imported function `{}` returned an invalid value\");",
+                name
+            ),
+            JsFunction::Callback(arg_name) => format!(
+                "throw new TypeError(\"callback `{}` returned an invalid value\");",
+                arg_name
+            ),
+        },
+    )?;
+    // results are owned by rust, which frees them.
+    prepare_argument_allocation(config, buf, "result", &result_ty, false)?;
+    let return_slot = match function {
+        JsFunction::Import(_) => "return_slot".to_owned(),
+        JsFunction::Callback(arg_name) => format!("{}_return_slot", arg_name),
+    };
+    match (function, ret_ty) {
+        (JsFunction::Import(_), &SupportedRetType::Integer(_)) => {
+            writeln!(buf, "return result;")?;
+        }
+        (JsFunction::Callback(_), &SupportedRetType::Integer(int_ty)) => {
+            // the function may have called back into the module.
+            if config.access_style == AccessStyle::DataView {
+                writeln!(buf, "this._check_mem_realloc();")?;
+            }
+            access_slot(
+                config,
+                buf,
+                &return_slot,
+                &abi::copy_ty_to_wasm(int_ty),
+                Some("result"),
+            )?;
+            writeln!(buf, ";")?;
+        }
+        _ => {
+            write_usize_array(
+                config,
+                buf,
+                &return_slot,
+                &return_slot,
+                &["result_ptr", "result_len", "result_byte_len"],
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes an expression reading the slot at `ptr` as the given type, or
+/// storing `value` in it if given.
+fn access_slot<T, U>(
+    config: &Config,
+    buf: &mut T,
+    ptr: U,
+    ty: &WasmType,
+    value: Option<&str>,
+) -> fmt::Result
+where
+    T: Write,
+    U: Display,
+{
+    let (accessor, typed_array) = match *ty {
+        WasmType::I32 => ("Int32", "Int32Array"),
+        WasmType::I64 => ("BigInt64", "BigInt64Array"),
+        WasmType::F32 => ("Float32", "Float32Array"),
+        WasmType::F64 => ("Float64", "Float64Array"),
+        WasmType::Other(_) => unreachable!("callbacks only take and return numbers"),
+    };
+    match (config.access_style, value) {
+        (AccessStyle::TypedArrays, None) => {
+            write!(buf, "new {}(this._mem.buffer, {}, 1)[0]", typed_array, ptr)
+        }
+        (AccessStyle::TypedArrays, Some(value)) => write!(
+            buf,
+            "new {}(this._mem.buffer, {}, 1)[0] = {}",
+            typed_array, ptr, value
+        ),
+        (AccessStyle::DataView, None) => {
+            write!(buf, "this._mem.get{}({}, true)", accessor, ptr)
+        }
+        (AccessStyle::DataView, Some(value)) => {
+            write!(buf, "this._mem.set{}({}, {}, true)", accessor, ptr, value)
+        }
+    }
+}
+
+/// Adds the names of the parameters an argument is passed as, matching
+/// `lower_import_argument` in `wasm-wrapper-gen-impl`.
+fn push_import_params(params: &mut Vec<String>, arg_name: &str, ty: &SupportedArgumentType) {
+    match *ty {
+        SupportedArgumentType::Integer(_) => params.push(format!("{}_raw", arg_name)),
+        SupportedArgumentType::Optional(ref inner) => {
+            if let SupportedArgumentType::Integer(_) = **inner {
+                params.push(format!("{}_is_some", arg_name));
+            }
+            push_import_params(params, arg_name, inner);
+        }
+        _ => {
+            params.push(format!("{}_ptr", arg_name));
+            params.push(format!("{}_len", arg_name));
+        }
+    }
+}
+
+/// Copies an argument of an imported function out of memory owned by rust,
+/// from the parameters named after `param_prefix` into a new variable.
+fn read_import_argument<T, U, V>(
+    config: &Config,
+    buf: &mut T,
+    param_prefix: U,
+    ty: &SupportedArgumentType,
+    to_var: V,
+) -> fmt::Result
+where
+    T: Write,
+    U: Display,
+    V: Display,
+{
+    match *ty {
+        // integers are converted like integers returned from exported functions.
+        SupportedArgumentType::Integer(int_ty) => {
+            read_return_value_copy_into(
+                config,
+                buf,
+                &SupportedRetType::Integer(int_ty),
+                None,
+                format_args!("{}_raw", param_prefix),
+                to_var,
+            )?;
+        }
+        SupportedArgumentType::IntegerSliceRef(int_ty)
+        | SupportedArgumentType::IntegerSliceMutRef(int_ty)
+        | SupportedArgumentType::IntegerVec(int_ty) => {
+            copy_array_out(
+                config,
+                buf,
+                format_args!("{}_ptr", param_prefix),
+                format_args!("{}_len", param_prefix),
+                format_args!("{}_tmp", param_prefix),
+                to_var,
+                int_ty,
+            )?;
+        }
+        SupportedArgumentType::OwnedString
+        | SupportedArgumentType::StringSlice
+        | SupportedArgumentType::JsString => {
+            copy_string_out(
+                config,
+                buf,
+                format_args!("{}_ptr", param_prefix),
+                format_args!("{}_len", param_prefix),
+                format_args!("{}_tmp", param_prefix),
+                to_var,
+            )?;
+        }
+        SupportedArgumentType::Json => {
+            let json_var = format!("{}_json", to_var);
+            copy_string_out(
+                config,
+                buf,
+                format_args!("{}_ptr", param_prefix),
+                format_args!("{}_len", param_prefix),
+                format_args!("{}_tmp", param_prefix),
+                &json_var,
+            )?;
+            writeln!(buf, "let {} = JSON.parse({});", to_var, json_var)?;
+        }
+        SupportedArgumentType::Optional(ref inner) => {
+            // not present values are null pointers, or have their flag unset.
+            writeln!(buf, "let {} = null;", to_var)?;
+            match **inner {
+                SupportedArgumentType::Integer(_) => {
+                    writeln!(buf, "if ({}_is_some) {{", param_prefix)?;
+                }
+                _ => writeln!(buf, "if ({}_ptr != 0) {{", param_prefix)?,
+            }
+            {
+                let buf = &mut buf.indented(config.indent);
+                let inner_var = format!("{}_inner", to_var);
+                // types are erased so recursion doesn't instantiate infinitely nested types.
+                let mut inner_buf: &mut dyn Write = buf;
+                read_import_argument(
+                    config,
+                    &mut inner_buf,
+                    &param_prefix as &dyn Display,
+                    inner,
+                    &inner_var,
+                )?;
+                writeln!(inner_buf, "{} = {};", to_var, inner_var)?;
+            }
+            writeln!(buf, "}}")?;
+        }
+        SupportedArgumentType::Callback(..) => {
+            unreachable!("JavaScript functions can't take callbacks")
+        }
+    }
+
+    Ok(())
+}
@@ -1,55 +1,154 @@
+mod imports;
 mod stats;
+mod typescript;
 
 use self::stats::FuncStats;
+use std::borrow::Cow;
 use std::fmt::{self, Display, Write};
 
 use failure::Error;
 
-use wasm_wrapper_gen_shared::{JsFnInfo, SupportedArgumentType, SupportedCopyTy, SupportedRetType,
+use wasm_wrapper_gen_shared::{drop_fn_name, JsFnInfo, JsImportInfo, SupportedArgumentType,
+                              SupportedCopyTy, SupportedRetType, SupportedSelfArgument,
                               TransformedRustIdent};
 
-use style::{AccessStyle, Config};
+use style::{AccessStyle, Config, ModuleFormat, PanicStrategy, StringEncoding, StringValidation};
 
 use self::indented_write::WriteExt;
 
-pub fn generate_javascript<'a, 'b, I>(config: &Config, iter: &'a I) -> Result<String, Error>
+pub use self::typescript::generate_typescript_declarations;
+
+pub fn generate_javascript<'a, 'b, I>(
+    config: &Config,
+    iter: &'a I,
+    imports: &[JsImportInfo],
+) -> Result<String, Error>
 where
     &'a I: IntoIterator<Item = &'b JsFnInfo> + 'a,
 {
-    let mut any_alloc = false;
-    let func_stats = iter.into_iter()
-        .map(|info| {
-            let stat = FuncStats::new(info);
-            if stat.uses_memory_access {
-                any_alloc = true;
-            }
-            stat
-        })
-        .collect::<Vec<_>>();
+    let catch_panics = config.panic_strategy == PanicStrategy::ThrowRustPanic;
+    let func_stats = iter.into_iter().map(FuncStats::new).collect::<Vec<_>>();
+    let any_alloc = uses_memory_access(config, &func_stats, imports);
+    let uses_callbacks = func_stats.iter().any(|stat| stat.uses_callbacks);
+
+    for info in imports {
+        let method_name = imports::import_method_name(info);
+        if let Some(stat) = func_stats
+            .iter()
+            .find(|stat| js_method_name(config, stat) == method_name)
+        {
+            bail!(
+                "function `{}` clashes with the method calling imported function `{}`",
+                stat.qualified_name(),
+                info.rust_name
+            );
+        }
+    }
+
+    if config.zero_copy_buffers {
+        if let Some(stat) = func_stats
+            .iter()
+            .find(|stat| stat.self_ty.is_none() && stat.rust_name == "allocBuffer")
+        {
+            bail!(
+                "function `{}` clashes with the method allocating buffers",
+                stat.qualified_name()
+            );
+        }
+    } else if let Some(stat) = func_stats.iter().find(|stat| stats::returns_buffer(&stat.ret_ty)) {
+        bail!(
+            "function `{}` returns a `JsBuffer`, which needs zero-copy buffers to be enabled",
+            stat.qualified_name()
+        );
+    }
+
+    let self_types = collect_self_types(func_stats.iter().map(|stat| stat.inner));
 
     let mut output_buffer = String::new();
     {
         let buf = &mut output_buffer;
 
-        write_class_definition_up_to_exports_grabbing(config, buf, any_alloc)?;
+        write_module_start(config, buf)?;
 
+        // UMD and global scripts wrap everything in a function.
+        let body_indent = match config.module_format {
+            ModuleFormat::CommonJs | ModuleFormat::EsModule => 0,
+            ModuleFormat::Umd | ModuleFormat::Global => config.indent,
+        };
         {
-            let buf = &mut buf.indented(config.indent * 3);
-            for stat in &func_stats {
-                write_func_unexport(buf, stat.inner, stat)?;
+            let buf = &mut buf.indented(body_indent);
+
+            write_class_definition_up_to_exports_grabbing(
+                config,
+                buf,
+                any_alloc,
+                imports,
+                uses_callbacks,
+            )?;
+
+            {
+                let buf = &mut buf.indented(config.indent * 3);
+                for stat in &func_stats {
+                    write_func_unexport(buf, stat.inner, stat)?;
+                }
+                for self_ty in &self_types {
+                    write_drop_func_unexport(buf, self_ty)?;
+                }
             }
-        }
 
-        write_class_definition_post_exports_grabbing_up_to_methods(config, buf, any_alloc)?;
+            write_class_definition_post_exports_grabbing_up_to_methods(
+                config,
+                buf,
+                any_alloc,
+                &func_stats,
+                &self_types,
+                imports,
+                uses_callbacks,
+            )?;
 
-        {
-            let buf = &mut buf.indented(config.indent);
-            for stat in &func_stats {
-                write_method(config, buf, stat.inner, stat)?;
+            {
+                let buf = &mut buf.indented(config.indent);
+                for stat in &func_stats {
+                    write_method(config, buf, stat.inner, stat)?;
+                }
+                if config.zero_copy_buffers {
+                    write_alloc_buffer_method(config, buf)?;
+                }
+                if !imports.is_empty() || uses_callbacks {
+                    imports::write_import_object_method(config, buf, imports, uses_callbacks)?;
+                }
+                for info in imports {
+                    imports::write_import_method(config, buf, info)?;
+                }
+            }
+
+            writeln!(buf, "}}")?;
+
+            if config.shared_loader {
+                write_shared_loader(config, buf, &func_stats, imports)?;
+            }
+
+            let any_result = func_stats
+                .iter()
+                .any(|stat| matches!(stat.ret_ty, SupportedRetType::Result(_)));
+            if any_result {
+                write_error_class(config, buf)?;
+            }
+            if catch_panics {
+                write_rust_panic_class(config, buf)?;
+            }
+            if config.zero_copy_buffers {
+                write_buffer_class(config, buf)?;
+            }
+
+            for self_ty in &self_types {
+                write_self_type_class(config, buf, self_ty, &func_stats)?;
             }
+
+            write_class_definition_finish(config, buf, any_result, &self_types)?;
         }
 
-        write_class_definition_finish(config, buf)?;
+        write_module_end(config, buf)?;
     }
     Ok(output_buffer)
 }
@@ -58,24 +157,44 @@ fn write_class_definition_up_to_exports_grabbing<T>(
     config: &Config,
     buf: &mut T,
     any_alloc: bool,
+    imports: &[JsImportInfo],
+    uses_callbacks: bool,
 ) -> Result<(), Error>
 where
     T: Write,
 {
-    write!(buf, "class {} {{\n", config.class_name)?;
+    writeln!(buf, "class {} {{", config.instance_class_name())?;
     {
         let mut buf = buf.indented(config.indent);
-        write!(buf, "constructor (wasm_module) {{\n")?;
+        if imports.is_empty() {
+            writeln!(buf, "constructor (wasm_module) {{")?;
+        } else {
+            writeln!(buf, "constructor (wasm_module, imports) {{")?;
+        }
         {
             let mut buf = buf.indented(config.indent);
-            write!(
-                buf,
-                "this._mod = new WebAssembly.Instance(wasm_module, {{}});\n"
+            if !imports.is_empty() {
+                imports::write_imports_check(config, &mut buf, imports)?;
+            }
+            // instances are passed in by `create`.
+            writeln!(buf, "if (wasm_module instanceof WebAssembly.Instance) {{")?;
+            writeln!(buf.indented(config.indent), "this._mod = wasm_module;")?;
+            writeln!(buf, "}} else {{")?;
+            writeln!(
+                buf.indented(config.indent),
+                "this._mod = new WebAssembly.Instance(wasm_module, {});",
+                import_object_expression(config, imports, uses_callbacks, "() => this")
             )?;
+            writeln!(buf, "}}")?;
+            if uses_callbacks {
+                // callbacks passed to the function being called, indexed by
+                // what rust calls them with.
+                writeln!(buf, "this._callbacks = [];")?;
+            }
             if any_alloc {
                 match config.access_style {
                     AccessStyle::TypedArrays => {
-                        write!(buf, "this._mem = this._mod.exports[\"memory\"];\n")?;
+                        writeln!(buf, "this._mem = this._mod.exports[\"memory\"];")?;
                     }
                     AccessStyle::DataView => {
                         write!(
@@ -84,6 +203,13 @@ where
 this._mem = new DataView(this._raw_mem.buffer);
 "#
                         )?;
+                        // typed arrays use the platform's byte order, so they
+                        // can only be copied in bulk where it matches memory's.
+                        writeln!(
+                            buf,
+                            "this._little_endian = \
+                             new Uint8Array(new Uint16Array([1]).buffer)[0] == 1;"
+                        )?;
                     }
                 }
                 write!(
@@ -91,6 +217,32 @@ this._mem = new DataView(this._raw_mem.buffer);
                     r#"
 this._alloc = this._mod.exports["__js_fn__builtin_alloc"];
 this._dealloc = this._mod.exports["__js_fn__builtin_dealloc"];
+"#
+                )?;
+            }
+            if any_alloc && config.scratch_arena {
+                write!(
+                    buf,
+                    r#"this._scratch_grow = this._mod.exports["__js_fn__builtin_scratch_grow"];
+this._scratch_cap = {};
+this._scratch_ptr = this._scratch_grow(this._scratch_cap);
+this._scratch_used = 0;
+this._scratch_wanted = 0;
+"#,
+                    INITIAL_SCRATCH_CAPACITY
+                )?;
+            }
+            if any_alloc && config.string_encoding == StringEncoding::Utf8 {
+                write!(
+                    buf,
+                    "this._encoder = new TextEncoder();\nthis._decoder = new TextDecoder();\n"
+                )?;
+            }
+            if config.panic_strategy == PanicStrategy::ThrowRustPanic {
+                write!(
+                    buf,
+                    r#"this._panic_message = this._mod.exports["__js_fn__builtin_panic_message"];
+this._panic_location = this._mod.exports["__js_fn__builtin_panic_location"];
 "#
                 )?;
             }
@@ -109,11 +261,27 @@ fn write_func_unexport<T>(buf: &mut T, info: &JsFnInfo, _stats: &FuncStats) -> R
 where
     T: Write,
 {
-    write!(
+    let name = info.qualified_name();
+    writeln!(
         buf,
-        "['{}']: this._mod.exports[\"{}\"],\n",
-        info.rust_name,
-        TransformedRustIdent::new(&info.rust_name)
+        "['{}']: this._mod.exports[\"{}\"],",
+        name,
+        TransformedRustIdent::new(&name)
+    )?;
+
+    Ok(())
+}
+
+fn write_drop_func_unexport<T>(buf: &mut T, self_ty: &str) -> Result<(), Error>
+where
+    T: Write,
+{
+    let name = drop_fn_name(self_ty);
+    writeln!(
+        buf,
+        "['{}']: this._mod.exports[\"{}\"],",
+        name,
+        TransformedRustIdent::new(&name)
     )?;
 
     Ok(())
@@ -123,12 +291,50 @@ fn write_class_definition_post_exports_grabbing_up_to_methods<T>(
     config: &Config,
     buf: &mut T,
     any_alloc: bool,
+    func_stats: &[FuncStats],
+    self_types: &[&str],
+    imports: &[JsImportInfo],
+    uses_callbacks: bool,
 ) -> Result<(), Error>
 where
     T: Write,
 {
-    write!(buf.indented(config.indent * 2), "}};\n")?;
-    write!(buf.indented(config.indent), "}}\n")?;
+    {
+        let buf = &mut buf.indented(config.indent * 2);
+        writeln!(buf, "}};")?;
+        // functions in impl blocks which don't take self, like constructors,
+        // are accessible through `wrapper.TypeName.func()`. With a shared
+        // loader, they're static methods of the type's class instead.
+        let bound_self_types = if config.shared_loader { &[][..] } else { self_types };
+        for self_ty in bound_self_types {
+            let mut static_funcs = func_stats
+                .iter()
+                .filter(|stat| is_static_in_type(stat, self_ty))
+                .peekable();
+            if static_funcs.peek().is_none() {
+                continue;
+            }
+            write!(buf, "\nthis.{} = {{\n", self_ty)?;
+            for stat in static_funcs {
+                writeln!(
+                    buf.indented(config.indent),
+                    "['{}']: this.{}.bind(this),",
+                    stat.rust_name,
+                    js_method_name(config, stat)
+                )?;
+            }
+            writeln!(buf, "}};")?;
+        }
+    }
+    writeln!(buf.indented(config.indent), "}}")?;
+    if config.async_create {
+        write_async_create(
+            config,
+            &mut buf.indented(config.indent),
+            imports,
+            uses_callbacks,
+        )?;
+    }
     match config.access_style {
         AccessStyle::TypedArrays => {}
         AccessStyle::DataView => {
@@ -137,723 +343,2589 @@ where
                 write!(buf, "\n_check_mem_realloc() {{\n")?;
                 {
                     let buf = &mut buf.indented(config.indent);
-                    write!(
+                    writeln!(
                         buf,
-                        "if (this._mem.byteLength != this._raw_mem.byteLength) {{\n"
+                        "if (this._mem.buffer != this._raw_mem.buffer) {{"
                     )?;
-                    write!(
+                    writeln!(
                         buf.indented(config.indent),
-                        "this._mem = new DataView(this._raw_mem.buffer);\n"
+                        "this._mem = new DataView(this._raw_mem.buffer);"
                     )?;
-                    write!(buf, "}}\n")?;
+                    writeln!(buf, "}}")?;
                 }
-                write!(buf, "}}\n")?;
+                writeln!(buf, "}}")?;
             }
         }
     }
+    if any_alloc && config.scratch_arena {
+        write_scratch_methods(config, &mut buf.indented(config.indent))?;
+    }
+    if any_alloc && config.string_validation == StringValidation::Strict {
+        write_is_well_formed_method(config, &mut buf.indented(config.indent))?;
+    }
+    if config.panic_strategy == PanicStrategy::ThrowRustPanic {
+        write_rust_panic_method(config, &mut buf.indented(config.indent))?;
+    }
     Ok(())
 }
 
-fn validate_argument<T, U, V>(
-    config: &Config,
-    buf: &mut T,
-    arg_name: U,
-    ty: SupportedArgumentType,
-    failure: V,
-) -> fmt::Result
+/// Writes the method checking strings for lone surrogates, using
+/// `String.prototype.isWellFormed` where it exists (node 20 and current
+/// browsers).
+fn write_is_well_formed_method<T>(config: &Config, buf: &mut T) -> Result<(), Error>
 where
     T: Write,
-    U: Display,
-    V: Display,
 {
-    match ty {
-        SupportedArgumentType::IntegerSliceRef(_)
-        | SupportedArgumentType::IntegerSliceMutRef(_)
-        | SupportedArgumentType::IntegerVec(_) => {
-            write!(buf, "if ({0} == null || isNaN({0}).length) {{\n", arg_name)?;
-            write!(buf.indented(config.indent), "{}\n", failure)?;
-            write!(buf, "}}\n")?;
-        }
-        SupportedArgumentType::OwnedString => {
-            write!(buf, "if ({0} == null) {{\n", arg_name)?;
-            write!(buf.indented(config.indent), "{}\n", failure)?;
-            write!(buf, "}}\n")?;
-        }
-        SupportedArgumentType::Integer(_) => {
-            write!(buf, "if (isNaN({0})) {{\n", arg_name)?;
-            write!(buf.indented(config.indent), "{}\n", failure)?;
-            write!(buf, "}}\n")?;
-        }
+    write!(buf, "\n_is_well_formed(string) {{\n")?;
+    {
+        let buf = &mut buf.indented(config.indent);
+        writeln!(buf, "if (typeof string.isWellFormed == 'function') {{")?;
+        writeln!(buf.indented(config.indent), "return string.isWellFormed();")?;
+        writeln!(buf, "}}")?;
+        write!(
+            buf,
+            r#"let lone_high = /[\uD800-\uDBFF](?![\uDC00-\uDFFF])/;
+let lone_low = /(?:^|[^\uD800-\uDBFF])[\uDC00-\uDFFF]/;
+return !lone_high.test(string) && !lone_low.test(string);
+"#
+        )?;
+    }
+    writeln!(buf, "}}")?;
+    Ok(())
+}
+
+/// Writes the methods bump-allocating borrowed arguments in the scratch
+/// region, and releasing them after the call.
+fn write_scratch_methods<T>(config: &Config, buf: &mut T) -> Result<(), Error>
+where
+    T: Write,
+{
+    write!(buf, "\n_scratch_alloc(len) {{\n")?;
+    {
+        let buf = &mut buf.indented(config.indent);
+        writeln!(buf, "let offset = (this._scratch_used + 7) & ~7;")?;
+        writeln!(buf, "if (offset + len > this._scratch_cap) {{")?;
+        // arguments which don't fit are allocated normally, and the region
+        // grows to fit all of them once nothing is using it.
+        write!(
+            buf.indented(config.indent),
+            r#"this._scratch_wanted = Math.max(this._scratch_wanted, offset) + len;
+return this._alloc(len);
+"#
+        )?;
+        writeln!(buf, "}}")?;
+        write!(
+            buf,
+            "this._scratch_used = offset + len;\nreturn this._scratch_ptr + offset;\n"
+        )?;
+    }
+    writeln!(buf, "}}")?;
+
+    write!(buf, "\n_scratch_dealloc(ptr, len) {{\n")?;
+    {
+        let buf = &mut buf.indented(config.indent);
+        writeln!(
+            buf,
+            "if (ptr < this._scratch_ptr || ptr >= this._scratch_ptr + this._scratch_cap) {{"
+        )?;
+        writeln!(buf.indented(config.indent), "this._dealloc(ptr, len);")?;
+        writeln!(buf, "}}")?;
     }
+    writeln!(buf, "}}")?;
+
+    // calls back into the module from callbacks release only what they used,
+    // and the region can only move once the outermost call is done with it.
+    write!(buf, "\n_scratch_release(mark) {{\n")?;
+    {
+        let buf = &mut buf.indented(config.indent);
+        writeln!(buf, "this._scratch_used = mark;")?;
+        writeln!(
+            buf,
+            "if (mark == 0 && this._scratch_wanted > this._scratch_cap) {{"
+        )?;
+        write!(
+            buf.indented(config.indent),
+            r#"this._scratch_cap = Math.max(this._scratch_wanted, this._scratch_cap * 2);
+this._scratch_ptr = this._scratch_grow(this._scratch_cap);
+this._scratch_wanted = 0;
+"#
+        )?;
+        writeln!(buf, "}}")?;
+    }
+    writeln!(buf, "}}")?;
 
     Ok(())
 }
 
-fn prepare_argument_allocation<T, U>(
+/// Writes the static function asynchronously instantiating the module from
+/// a `WebAssembly.Module`, `ArrayBuffer`, `Response` or URL.
+fn write_async_create<T>(
     config: &Config,
     buf: &mut T,
-    arg_name: U,
-    ty: SupportedArgumentType,
-) -> fmt::Result
+    imports: &[JsImportInfo],
+    uses_callbacks: bool,
+) -> Result<(), Error>
 where
     T: Write,
-    U: Display,
 {
-    match ty {
-        SupportedArgumentType::IntegerSliceRef(int_ty)
-        | SupportedArgumentType::IntegerSliceMutRef(int_ty)
-        | SupportedArgumentType::IntegerVec(int_ty) => {
+    // imported functions and callbacks call into the wrapper, which is only
+    // constructed once the module is instantiated.
+    let uses_import_object = !imports.is_empty() || uses_callbacks;
+    let (params, constructor_args) = if imports.is_empty() {
+        ("source", "")
+    } else {
+        ("source, imports", ", imports")
+    };
+    let import_object = if uses_import_object { "import_object" } else { "{}" };
+    let write_return = |buf: &mut dyn Write, instance: &str| -> fmt::Result {
+        if !uses_import_object {
+            writeln!(buf, "return new {}({});", config.instance_class_name(), instance)
+        } else {
             write!(
                 buf,
-                r#"let {0}_len = {0}.length;
-let {0}_byte_len = {0}_len * {1};
-let {0}_ptr = this._alloc({0}_byte_len);
-"#,
-                arg_name,
-                int_ty.size_in_bytes(),
-            )?;
-            match config.access_style {
-                AccessStyle::TypedArrays => {
-                    write!(
-                        buf,
-                        r#"let {0}_view = new {1}(this._mem.buffer, {0}_ptr, {0}_byte_len);
-{0}_view.set({0});
-"#,
-                        arg_name,
-                        javascript_typed_array_for_int(int_ty)
-                    )?;
-                }
-                AccessStyle::DataView => {
-                    write!(
-                        buf,
-                        r#"this._check_mem_realloc();
-for (var {0}_i = 0; {0}_i < {0}_len; {0}_i++) {{
-"#,
-                        arg_name,
-                    )?;
-                    js_set_ith_ty_at(
-                        buf.indented(config.indent),
-                        "this._mem",
-                        int_ty,
-                        format_args!("{0}_ptr", arg_name),
-                        format_args!("{0}_i", arg_name),
-                        format_args!("{0}[{0}_i]", arg_name),
-                    )?;
-                    write!(buf, "}}\n")?;
-                }
-            }
+                "wrapper = new {}({}{});\nreturn wrapper;\n",
+                config.instance_class_name(),
+                instance,
+                constructor_args
+            )
         }
-        SupportedArgumentType::OwnedString => {
-            // TODO: test if this handles unicode correctly!
+    };
+    write!(buf, "\nstatic async create({}) {{\n", params)?;
+    {
+        let buf = &mut buf.indented(config.indent);
+        if uses_import_object {
             write!(
                 buf,
-                r#"let {0}_str = String({0});
-let {0}_len = {0}_str.length;
-let {0}_byte_len = {0}_len * {1};
-let {0}_ptr = this._alloc({0}_byte_len);
-"#,
-                arg_name,
-                SupportedCopyTy::U16.size_in_bytes(),
+                "let wrapper = null;\nlet import_object = {};\n",
+                import_object_expression(config, imports, uses_callbacks, "() => wrapper")
             )?;
-            match config.access_style {
-                AccessStyle::TypedArrays => {
-                    write!(
-                        buf,
-                        "let {0}_view = new {1}(this._mem.buffer, {0}_ptr, {0}_byte_len);\n",
-                        arg_name,
-                        javascript_typed_array_for_int(SupportedCopyTy::U16),
-                    )?;
-                }
-                AccessStyle::DataView => {
-                    write!(buf, "this._check_mem_realloc();\n")?;
-                }
-            }
-            write!(
+        }
+        writeln!(buf, "if (source instanceof WebAssembly.Module) {{")?;
+        write_return(
+            &mut buf.indented(config.indent),
+            &format!("await WebAssembly.instantiate(source, {})", import_object),
+        )?;
+        writeln!(buf, "}}")?;
+        writeln!(
+            buf,
+            "if (typeof source == 'string' \
+             || (typeof URL != 'undefined' && source instanceof URL)) {{"
+        )?;
+        writeln!(buf.indented(config.indent), "source = await fetch(source);")?;
+        writeln!(buf, "}}")?;
+        writeln!(buf, "let instance;")?;
+        writeln!(
+            buf,
+            "if (typeof Response != 'undefined' && source instanceof Response) {{"
+        )?;
+        {
+            let buf = &mut buf.indented(config.indent);
+            writeln!(
                 buf,
-                "for (var {0}_i = 0; {0}_i < {0}_len; {0}_i++) {{\n",
-                arg_name
+                "if (typeof WebAssembly.instantiateStreaming == 'function') {{"
             )?;
             {
                 let buf = &mut buf.indented(config.indent);
-                match config.access_style {
-                    AccessStyle::TypedArrays => {
-                        write!(
-                            buf,
-                            "{0}_view[{0}_i] = {0}_str.charCodeAt({0}_i);",
-                            arg_name
-                        )?;
-                    }
-                    AccessStyle::DataView => {
-                        js_set_ith_ty_at(
-                            buf,
-                            "this._mem",
-                            SupportedCopyTy::U16,
-                            format_args!("{0}_ptr", arg_name),
-                            format_args!("{0}_i", arg_name),
-                            format_args!("{0}_str.charCodeAt({0}_i)", arg_name),
-                        )?;
-                    }
+                writeln!(buf, "try {{")?;
+                writeln!(
+                    buf.indented(config.indent),
+                    "instance = (await WebAssembly.instantiateStreaming(source, {})).instance;",
+                    import_object
+                )?;
+                writeln!(buf, "}} catch (e) {{")?;
+                {
+                    let buf = &mut buf.indented(config.indent);
+                    // the MIME type is checked before the body is read, so
+                    // it can still be read below.
+                    writeln!(
+                        buf,
+                        "if (source.headers.get('Content-Type') == 'application/wasm') {{"
+                    )?;
+                    writeln!(buf.indented(config.indent), "throw e;")?;
+                    writeln!(buf, "}}")?;
                 }
+                writeln!(buf, "}}")?;
             }
-            write!(buf, "}}\n")?;
+            writeln!(buf, "}}")?;
+            writeln!(buf, "if (instance == null) {{")?;
+            writeln!(
+                buf.indented(config.indent),
+                "source = await source.arrayBuffer();"
+            )?;
+            writeln!(buf, "}}")?;
         }
-        SupportedArgumentType::Integer(_) => {} // no allocation needed for integers.
+        writeln!(buf, "}}")?;
+        writeln!(buf, "if (instance == null) {{")?;
+        writeln!(
+            buf.indented(config.indent),
+            "instance = (await WebAssembly.instantiate(source, {})).instance;",
+            import_object
+        )?;
+        writeln!(buf, "}}")?;
+        write_return(buf, "instance")?;
     }
+    writeln!(buf, "}}")?;
 
     Ok(())
 }
 
-fn deallocate_argument_allocation<T, U>(
-    _config: &Config,
-    buf: &mut T,
-    arg_name: U,
-    ty: SupportedArgumentType,
-) -> fmt::Result
+/// The import object to instantiate the module with, given an expression for
+/// a function returning the wrapper.
+fn import_object_expression(
+    config: &Config,
+    imports: &[JsImportInfo],
+    uses_callbacks: bool,
+    get_wrapper: &str,
+) -> String {
+    if imports.is_empty() && !uses_callbacks {
+        "{}".to_owned()
+    } else {
+        format!("{}._import_object({})", config.instance_class_name(), get_wrapper)
+    }
+}
+
+/// Writes the method turning a trap caused by a rust panic into a `RustPanic`,
+/// passing other errors through unchanged.
+fn write_rust_panic_method<T>(config: &Config, buf: &mut T) -> Result<(), Error>
 where
     T: Write,
-    U: Display,
 {
-    // deallocate
-    match ty {
-        SupportedArgumentType::Integer(_) | SupportedArgumentType::IntegerVec(_) => {}
-        SupportedArgumentType::IntegerSliceRef(_)
-        | SupportedArgumentType::IntegerSliceMutRef(_)
-        | SupportedArgumentType::OwnedString => {
-            write!(buf, "this._dealloc({0}_ptr, {0}_byte_len);\n", arg_name)?;
+    let optional_string = SupportedRetType::Optional(Box::new(SupportedRetType::OwnedString));
+    write!(buf, "\n_rust_panic(error) {{\n")?;
+    {
+        let buf = &mut buf.indented(config.indent);
+        // modules built without the "panic-messages" feature don't export the
+        // functions reading the message, so their traps are passed through.
+        writeln!(
+            buf,
+            "if (!(error instanceof WebAssembly.RuntimeError) \
+             || this._panic_message == undefined) {{"
+        )?;
+        writeln!(buf.indented(config.indent), "return error;")?;
+        writeln!(buf, "}}")?;
+        writeln!(buf, "let message_result = this._panic_message();")?;
+        writeln!(buf, "let location_result = this._panic_location();")?;
+        if config.access_style == AccessStyle::DataView {
+            writeln!(buf, "this._check_mem_realloc();")?;
         }
+        read_return_value_copy_into(
+            config,
+            buf,
+            &optional_string,
+            None,
+            "message_result",
+            "message",
+        )?;
+        read_return_value_copy_into(
+            config,
+            buf,
+            &optional_string,
+            None,
+            "location_result",
+            "location",
+        )?;
+        // traps not caused by panics, or panics without the hook installed.
+        writeln!(buf, "if (message == null) {{")?;
+        writeln!(buf.indented(config.indent), "return error;")?;
+        writeln!(buf, "}}")?;
+        writeln!(buf, "return new RustPanic(message, location, error);")?;
     }
+    writeln!(buf, "}}")?;
 
     Ok(())
 }
 
-fn reconstruct_typed_array_if_memory_changed<T, U, V, W>(
+fn validate_argument<T, U, V>(
     config: &Config,
     buf: &mut T,
-    view_name: U,
-    ptr_name: V,
-    length_name: W,
-    ty: SupportedCopyTy,
+    arg_name: U,
+    ty: &SupportedArgumentType,
+    failure: V,
 ) -> fmt::Result
 where
     T: Write,
     U: Display,
     V: Display,
-    W: Display,
 {
-    write!(
+    match *ty {
+        SupportedArgumentType::IntegerSliceRef(int_ty)
+        | SupportedArgumentType::IntegerSliceMutRef(int_ty)
+            if accepts_buffer(config, int_ty) =>
+        {
+            // buffers can only be borrowed by the module they were allocated in.
+            writeln!(buf, "if ({0} instanceof JsBuffer) {{", arg_name)?;
+            {
+                let buf = &mut buf.indented(config.indent);
+                writeln!(
+                    buf,
+                    "if ({0}._wrapper !== this || {0}._array_type !== {1} || {0}._ptr == 0) {{",
+                    arg_name,
+                    javascript_typed_array_for_int(int_ty)
+                )?;
+                writeln!(
+                    buf.indented(config.indent),
+                    "throw new TypeError(\"argument `{}` must be a JsBuffer<{}> of this module \
+                     which hasn't been freed\");",
+                    arg_name,
+                    javascript_typed_array_for_int(int_ty)
+                )?;
+                writeln!(buf, "}}")?;
+            }
+            writeln!(buf, "}} else {{")?;
+            validate_array(config, &mut buf.indented(config.indent), arg_name, int_ty, failure)?;
+            writeln!(buf, "}}")?;
+        }
+        SupportedArgumentType::IntegerSliceRef(int_ty)
+        | SupportedArgumentType::IntegerSliceMutRef(int_ty)
+        | SupportedArgumentType::IntegerVec(int_ty) => {
+            validate_array(config, buf, arg_name, int_ty, failure)?;
+        }
+        SupportedArgumentType::OwnedString
+        | SupportedArgumentType::StringSlice
+        | SupportedArgumentType::JsString => {
+            writeln!(buf, "if ({0} == null) {{", arg_name)?;
+            writeln!(buf.indented(config.indent), "{}", failure)?;
+            writeln!(buf, "}}")?;
+            // `JsString` arguments are meant to see lone surrogates.
+            let strict = config.string_validation == StringValidation::Strict;
+            if strict && *ty != SupportedArgumentType::JsString {
+                writeln!(buf, "if (!this._is_well_formed(String({0}))) {{", arg_name)?;
+                writeln!(
+                    buf.indented(config.indent),
+                    "throw new TypeError(\"argument `{}` is not well-formed UTF-16\");",
+                    arg_name
+                )?;
+                writeln!(buf, "}}")?;
+            }
+        }
+        // null is valid JSON, but undefined has no JSON representation.
+        SupportedArgumentType::Json => {
+            writeln!(buf, "if ({0} === undefined) {{", arg_name)?;
+            writeln!(buf.indented(config.indent), "{}", failure)?;
+            writeln!(buf, "}}")?;
+        }
+        SupportedArgumentType::Integer(int_ty) if int_ty.is_64_bit_integer() => {
+            validate_big_int(config, buf, arg_name, failure)?;
+        }
+        SupportedArgumentType::Integer(_) => {
+            writeln!(buf, "if ({0} == null || isNaN({0})) {{", arg_name)?;
+            writeln!(buf.indented(config.indent), "{}", failure)?;
+            writeln!(buf, "}}")?;
+        }
+        SupportedArgumentType::Callback(..) => {
+            writeln!(buf, "if (typeof {0} != 'function') {{", arg_name)?;
+            writeln!(buf.indented(config.indent), "{}", failure)?;
+            writeln!(buf, "}}")?;
+        }
+        SupportedArgumentType::Optional(ref inner) => {
+            // both null and undefined are accepted as None.
+            writeln!(buf, "if ({0} != null) {{", arg_name)?;
+            // types are erased so recursion doesn't instantiate infinitely nested types.
+            let mut inner_buf: &mut dyn Write = &mut buf.indented(config.indent);
+            validate_argument(
+                config,
+                &mut inner_buf,
+                &arg_name as &dyn Display,
+                inner,
+                failure,
+            )?;
+            writeln!(buf, "}}")?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks that an array argument is array-like, with valid elements.
+fn validate_array<T, U, V>(
+    config: &Config,
+    buf: &mut T,
+    arg_name: U,
+    int_ty: SupportedCopyTy,
+    failure: V,
+) -> fmt::Result
+where
+    T: Write,
+    U: Display,
+    V: Display,
+{
+    writeln!(
         buf,
-        "if ({0}.buffer.byteLength != this._mem.buffer.byteLength) {{\n",
-        view_name
+        "if ({0} == null || typeof {0}.length != 'number') {{",
+        arg_name
     )?;
-    write!(
-        buf.indented(config.indent),
-        "{0} = new {1}(this._mem.buffer, {2}, {3});\n",
-        view_name,
-        javascript_typed_array_for_int(ty),
-        ptr_name,
-        length_name
+    writeln!(buf.indented(config.indent), "{}", failure)?;
+    writeln!(buf, "}}")?;
+    if int_ty.is_64_bit_integer() {
+        writeln!(
+            buf,
+            "for (var {0}_i = 0; {0}_i < {0}.length; {0}_i++) {{",
+            arg_name
+        )?;
+        {
+            let buf = &mut buf.indented(config.indent);
+            validate_big_int(
+                config,
+                buf,
+                format_args!("{0}[{0}_i]", arg_name),
+                &failure,
+            )?;
+        }
+        writeln!(buf, "}}")?;
+    }
+
+    Ok(())
+}
+
+/// Checks that a value can be converted into a `BigInt` without losing
+/// precision (`isNaN` can't be used since it throws for `BigInt`s).
+fn validate_big_int<T, U, V>(config: &Config, buf: &mut T, value: U, failure: V) -> fmt::Result
+where
+    T: Write,
+    U: Display,
+    V: Display,
+{
+    writeln!(
+        buf,
+        "if (typeof {0} != 'bigint' && !Number.isSafeInteger({0})) {{",
+        value
     )?;
-    write!(buf, "}}\n")?;
+    writeln!(buf.indented(config.indent), "{}", failure)?;
+    writeln!(buf, "}}")?;
 
     Ok(())
 }
 
-fn propogate_argument_changes_outwards<T, U>(
+// Variables declared here use `var` rather than `let` so that they can be
+// declared conditionally for optional arguments and still used afterwards.
+//
+// With `use_scratch`, memory freed after the call is allocated in the scratch
+// region.
+fn prepare_argument_allocation<T, U>(
     config: &Config,
     buf: &mut T,
     arg_name: U,
-    ty: SupportedArgumentType,
+    ty: &SupportedArgumentType,
+    use_scratch: bool,
 ) -> fmt::Result
 where
     T: Write,
     U: Display,
 {
-    // copy changes back for mutable references
-    match ty {
-        SupportedArgumentType::IntegerSliceMutRef(int_ty) => {
-            // propagate modifications outwards.
+    let alloc = if use_scratch && stats::argument_uses_borrowed_memory(ty) {
+        "this._scratch_alloc"
+    } else {
+        "this._alloc"
+    };
+    match *ty {
+        SupportedArgumentType::IntegerSliceRef(int_ty)
+        | SupportedArgumentType::IntegerSliceMutRef(int_ty)
+            if accepts_buffer(config, int_ty) =>
+        {
+            // buffers are passed in place, with no length to deallocate since
+            // dealloc ignores zero lengths.
+            write!(
+                buf,
+                r#"var {0}_borrowed = {0} instanceof JsBuffer;
+if ({0}_borrowed) {{
+    var {0}_ptr = {0}._ptr;
+    var {0}_len = {0}._len;
+    var {0}_byte_len = 0;
+}} else {{
+"#,
+                arg_name,
+            )?;
+            prepare_array_allocation(
+                config,
+                &mut buf.indented(config.indent),
+                arg_name,
+                int_ty,
+                alloc,
+            )?;
+            writeln!(buf, "}}")?;
+        }
+        SupportedArgumentType::IntegerSliceRef(int_ty)
+        | SupportedArgumentType::IntegerSliceMutRef(int_ty)
+        | SupportedArgumentType::IntegerVec(int_ty) => {
+            prepare_array_allocation(config, buf, arg_name, int_ty, alloc)?;
+        }
+        SupportedArgumentType::OwnedString
+        | SupportedArgumentType::StringSlice
+        | SupportedArgumentType::Json
+            if config.string_encoding == StringEncoding::Utf8 =>
+        {
+            write_string_source(buf, &arg_name, ty)?;
+            // encoded directly into memory allocated for the longest possible
+            // result, since each UTF-16 code unit takes at most 3 UTF-8 bytes.
+            write!(
+                buf,
+                r#"var {0}_byte_len = {0}_str.length * 3;
+var {0}_ptr = {1}({0}_byte_len);
+"#,
+                arg_name,
+                alloc,
+            )?;
+            if config.access_style == AccessStyle::DataView {
+                writeln!(buf, "this._check_mem_realloc();")?;
+            }
+            write!(
+                buf,
+                r#"var {0}_len = this._encoder.encodeInto(
+    {0}_str,
+    new Uint8Array(this._mem.buffer, {0}_ptr, {0}_byte_len)
+).written;
+"#,
+                arg_name,
+            )?;
+        }
+        SupportedArgumentType::OwnedString
+        | SupportedArgumentType::StringSlice
+        | SupportedArgumentType::JsString
+        | SupportedArgumentType::Json => {
+            write_string_source(buf, &arg_name, ty)?;
+            // TODO: test if this handles unicode correctly!
+            write!(
+                buf,
+                r#"var {0}_len = {0}_str.length;
+var {0}_byte_len = {0}_len * {1};
+var {0}_ptr = {2}({0}_byte_len);
+"#,
+                arg_name,
+                SupportedCopyTy::U16.size_in_bytes(),
+                alloc,
+            )?;
             match config.access_style {
                 AccessStyle::TypedArrays => {
-                    reconstruct_typed_array_if_memory_changed(
-                        config,
+                    writeln!(
                         buf,
-                        format_args!("{}_view", arg_name),
-                        format_args!("{}_ptr", arg_name),
-                        format_args!("{}_byte_len", arg_name),
-                        int_ty,
-                    )?;
-                    write!(buf, "if (typeof {0}.set == 'function') {{", arg_name)?;
-                    write!(
-                        buf.indented(config.indent),
-                        "{0}.set({0}_view);\n",
-                        arg_name
+                        "var {0}_view = new {1}(this._mem.buffer, {0}_ptr, {0}_len);",
+                        arg_name,
+                        javascript_typed_array_for_int(SupportedCopyTy::U16),
                     )?;
-                    write!(buf, "}} else {{")?;
-                    {
-                        let mut buf = buf.indented(config.indent);
-
+                }
+                AccessStyle::DataView => {
+                    writeln!(buf, "this._check_mem_realloc();")?;
+                }
+            }
+            writeln!(
+                buf,
+                "for (var {0}_i = 0; {0}_i < {0}_len; {0}_i++) {{",
+                arg_name
+            )?;
+            {
+                let buf = &mut buf.indented(config.indent);
+                match config.access_style {
+                    AccessStyle::TypedArrays => {
                         write!(
                             buf,
-                            "for (var {0}_i = 0; {0}_i < {0}_len; {0}_i++) {{\n",
+                            "{0}_view[{0}_i] = {0}_str.charCodeAt({0}_i);",
                             arg_name
                         )?;
-                        {
-                            let mut buf = buf.indented(config.indent);
-                            write!(buf, "{0}[{0}_i] = ", arg_name)?;
-                            if int_ty == SupportedCopyTy::Bool {
-                                write!(buf, "Boolean({0}_view[{0}_i])", arg_name)?;
-                            } else {
-                                write!(buf, "{0}_view[{0}_i]", arg_name)?;
-                            }
-                            write!(buf, ";\n")?;
-                        }
-                        write!(buf, "}}\n")?;
                     }
-                    write!(buf, "}}\n")?;
-                }
-                AccessStyle::DataView => {
-                    write!(
-                        buf,
-                        "for (var {0}_i = 0; {0}_i < {0}_len; {0}_i++) {{\n",
-                        arg_name
-                    )?;
-                    {
-                        let mut buf = buf.indented(config.indent);
-                        write!(buf, "{0}[{0}_i] = ", arg_name)?;
-                        js_get_ith_ty_at(
-                            &mut buf,
+                    AccessStyle::DataView => {
+                        js_set_ith_ty_at(
+                            buf,
                             "this._mem",
-                            int_ty,
+                            SupportedCopyTy::U16,
                             format_args!("{0}_ptr", arg_name),
                             format_args!("{0}_i", arg_name),
+                            format_args!("{0}_str.charCodeAt({0}_i)", arg_name),
                         )?;
-                        write!(buf, ";\n")?;
                     }
-                    write!(buf, "}}\n")?;
                 }
             }
+            writeln!(buf, "}}")?;
         }
-        SupportedArgumentType::IntegerSliceRef(_)
-        | SupportedArgumentType::IntegerVec(_)
-        | SupportedArgumentType::Integer(_)
-        | SupportedArgumentType::OwnedString => {}
+        SupportedArgumentType::Integer(int_ty) if int_ty.is_64_bit_integer() => {
+            // 64-bit integers are passed to WebAssembly as BigInts.
+            writeln!(buf, "{0} = BigInt({0});", arg_name)?;
+        }
+        SupportedArgumentType::Integer(_) => {} // no allocation needed for integers.
+        SupportedArgumentType::Callback(ref args_ty, ref ret_ty) => {
+            // types are erased since the callback's result is prepared in turn.
+            let mut erased_buf: &mut dyn Write = buf;
+            imports::write_callback_registration(
+                config,
+                &mut erased_buf,
+                &arg_name.to_string(),
+                args_ty,
+                ret_ty,
+            )?;
+        }
+        SupportedArgumentType::Optional(ref inner) => match **inner {
+            SupportedArgumentType::Integer(_) => {
+                // integers are passed alongside a flag for whether they're present.
+                write!(
+                    buf,
+                    r#"var {0}_is_some = {0} != null;
+if (!{0}_is_some) {{
+    {0} = 0;
+}}
+"#,
+                    arg_name
+                )?;
+                prepare_argument_allocation(config, buf, arg_name, inner, use_scratch)?;
+            }
+            _ => {
+                // everything else is passed as a null pointer when not present.
+                writeln!(buf, "if ({0} != null) {{", arg_name)?;
+                // types are erased so recursion doesn't instantiate infinitely nested types.
+                let mut inner_buf: &mut dyn Write = &mut buf.indented(config.indent);
+                prepare_argument_allocation(
+                    config,
+                    &mut inner_buf,
+                    &arg_name as &dyn Display,
+                    inner,
+                    use_scratch,
+                )?;
+                write!(
+                    buf,
+                    r#"}} else {{
+    var {0}_ptr = 0;
+    var {0}_len = 0;
+    var {0}_byte_len = 0;
+}}
+"#,
+                    arg_name
+                )?;
+            }
+        },
     }
 
     Ok(())
 }
 
-fn read_three_usize_array<T, U, V, W, X, Y>(
+/// Copies an array argument into memory allocated with the given function.
+fn prepare_array_allocation<T, U>(
     config: &Config,
     buf: &mut T,
-    ptr_name: U,
-    temp_prefix: V,
-    item1: W,
-    item2: X,
-    item3: Y,
+    arg_name: U,
+    int_ty: SupportedCopyTy,
+    alloc: &str,
 ) -> fmt::Result
 where
     T: Write,
     U: Display,
-    V: Display,
-    W: Display,
-    X: Display,
-    Y: Display,
 {
+    write!(
+        buf,
+        r#"var {0}_len = {0}.length;
+var {0}_byte_len = {0}_len * {1};
+var {0}_ptr = {2}({0}_byte_len);
+"#,
+        arg_name,
+        int_ty.size_in_bytes(),
+        alloc,
+    )?;
     match config.access_style {
-        AccessStyle::TypedArrays => {
+        AccessStyle::TypedArrays if int_ty.is_64_bit_integer() => {
+            // BigInt64Array.set() throws on regular numbers.
             write!(
                 buf,
-                r#"let {0}_view = new {2}(this._mem.buffer, {3}, {1});
-let {4} = {0}_view[0];
-let {5} = {0}_view[1];
-let {6} = {0}_view[2];
+                r#"var {0}_view = new {1}(this._mem.buffer, {0}_ptr, {0}_len);
+{0}_view.set(Array.from({0}, BigInt));
 "#,
-                temp_prefix,
-                3 * SupportedCopyTy::USize.size_in_bytes(),
-                javascript_typed_array_for_int(SupportedCopyTy::USize),
-                ptr_name,
-                item1,
-                item2,
-                item3
+                arg_name,
+                javascript_typed_array_for_int(int_ty)
             )?;
         }
-        AccessStyle::DataView => {
+        AccessStyle::TypedArrays => {
             write!(
                 buf,
-                r#"
-let {3} = this._mem.getUint32({0}, true);
-let {4} = this._mem.getUint32({0} + {1}, true);
-let {5} = this._mem.getUint32({0} + {2}, true);
+                r#"var {0}_view = new {1}(this._mem.buffer, {0}_ptr, {0}_len);
+{0}_view.set({0});
 "#,
-                ptr_name,
-                SupportedCopyTy::USize.size_in_bytes(),
-                SupportedCopyTy::USize.size_in_bytes() * 2,
-                item1,
-                item2,
-                item3
+                arg_name,
+                javascript_typed_array_for_int(int_ty)
             )?;
         }
+        AccessStyle::DataView => {
+            writeln!(buf, "this._check_mem_realloc();")?;
+            // typed arrays of the same type are copied in bulk.
+            let bulk_copy = int_ty != SupportedCopyTy::Bool;
+            if bulk_copy {
+                write!(
+                    buf,
+                    r#"if (this._little_endian && {0} instanceof {1}) {{
+    new Uint8Array(this._raw_mem.buffer, {0}_ptr, {0}_byte_len)
+        .set(new Uint8Array({0}.buffer, {0}.byteOffset, {0}_byte_len));
+}} else {{
+"#,
+                    arg_name,
+                    javascript_typed_array_for_int(int_ty),
+                )?;
+            }
+            {
+                let buf = &mut buf.indented(if bulk_copy { config.indent } else { 0 });
+                writeln!(
+                    buf,
+                    "for (var {0}_i = 0; {0}_i < {0}_len; {0}_i++) {{",
+                    arg_name
+                )?;
+                js_set_ith_ty_at(
+                    buf.indented(config.indent),
+                    "this._mem",
+                    int_ty,
+                    format_args!("{0}_ptr", arg_name),
+                    format_args!("{0}_i", arg_name),
+                    format_args!("{0}[{0}_i]", arg_name),
+                )?;
+                writeln!(buf, "}}")?;
+            }
+            if bulk_copy {
+                writeln!(buf, "}}")?;
+            }
+        }
     }
 
     Ok(())
 }
-fn dealloc_three_usize_array<T, U>(_config: &Config, buf: &mut T, ptr_name: U) -> fmt::Result
+
+/// Declares the string an argument passed as a string is copied from.
+fn write_string_source<T, U>(buf: &mut T, arg_name: U, ty: &SupportedArgumentType) -> fmt::Result
 where
     T: Write,
     U: Display,
 {
-    write!(
-        buf,
-        "this._dealloc({0}, {1});\n",
-        ptr_name,
-        SupportedCopyTy::USize.size_in_bytes() * 3
-    )
+    match *ty {
+        SupportedArgumentType::Json => {
+            writeln!(buf, "var {0}_str = JSON.stringify({0});", arg_name)
+        }
+        _ => writeln!(buf, "var {0}_str = String({0});", arg_name),
+    }
 }
 
-fn copy_array_out<T, U, V, W, X, Y>(
+fn deallocate_argument_allocation<T, U>(
     config: &Config,
     buf: &mut T,
-    ptr_name: U,
-    length_name: V,
-    byte_length_name: W,
-    temp_name: X,
-    result_name: Y,
-    int_ty: SupportedCopyTy,
+    arg_name: U,
+    ty: &SupportedArgumentType,
 ) -> fmt::Result
 where
     T: Write,
     U: Display,
-    V: Display,
-    W: Display,
-    X: Display,
-    Y: Display,
 {
-    match (config.access_style, int_ty) {
-        (AccessStyle::TypedArrays, SupportedCopyTy::Bool) => {
-            write!(
-                buf,
-                r#"let {0}_view = new {3}(this._mem.buffer, return_ptr, {4});
-let {2} = [];
-for (var {0}_i = 0; {0}_i < {1}; {0}_i++) {{
-"#,
-                temp_name,
-                length_name,
-                result_name,
-                javascript_typed_array_for_int(int_ty),
-                byte_length_name,
+    // deallocate
+    match *ty {
+        SupportedArgumentType::Integer(_) | SupportedArgumentType::IntegerVec(_) => {}
+        SupportedArgumentType::IntegerSliceRef(_)
+        | SupportedArgumentType::IntegerSliceMutRef(_)
+        | SupportedArgumentType::OwnedString
+        | SupportedArgumentType::StringSlice
+        | SupportedArgumentType::JsString
+        | SupportedArgumentType::Json => {
+            // only arguments which didn't fit in the scratch region are freed.
+            let dealloc = if config.scratch_arena {
+                "this._scratch_dealloc"
+            } else {
+                "this._dealloc"
+            };
+            writeln!(buf, "{1}({0}_ptr, {0}_byte_len);", arg_name, dealloc)?;
+        }
+        // not present arguments have a zero length, which dealloc ignores, as
+        // do the undefined lengths of ones not allocated before a throw.
+        SupportedArgumentType::Optional(ref inner) => {
+            deallocate_argument_allocation(config, buf, arg_name, inner)?;
+        }
+        SupportedArgumentType::Callback(..) => {
+            imports::write_callback_unregistration(config, buf, &arg_name.to_string())?;
+        }
+    }
+
+    Ok(())
+}
+
+fn reconstruct_typed_array_if_memory_changed<T, U, V, W>(
+    config: &Config,
+    buf: &mut T,
+    view_name: U,
+    ptr_name: V,
+    length_name: W,
+    ty: SupportedCopyTy,
+) -> fmt::Result
+where
+    T: Write,
+    U: Display,
+    V: Display,
+    W: Display,
+{
+    writeln!(
+        buf,
+        "if ({0}.buffer.byteLength != this._mem.buffer.byteLength) {{",
+        view_name
+    )?;
+    writeln!(
+        buf.indented(config.indent),
+        "{0} = new {1}(this._mem.buffer, {2}, {3});",
+        view_name,
+        javascript_typed_array_for_int(ty),
+        ptr_name,
+        length_name
+    )?;
+    writeln!(buf, "}}")?;
+
+    Ok(())
+}
+
+fn propogate_argument_changes_outwards<T, U>(
+    config: &Config,
+    buf: &mut T,
+    arg_name: U,
+    ty: &SupportedArgumentType,
+) -> fmt::Result
+where
+    T: Write,
+    U: Display,
+{
+    // copy changes back for mutable references
+    match *ty {
+        SupportedArgumentType::IntegerSliceMutRef(int_ty) if accepts_buffer(config, int_ty) => {
+            // borrowed buffers were changed in place.
+            writeln!(buf, "if (!{0}_borrowed) {{", arg_name)?;
+            propagate_array_changes(config, &mut buf.indented(config.indent), arg_name, int_ty)?;
+            writeln!(buf, "}}")?;
+        }
+        SupportedArgumentType::IntegerSliceMutRef(int_ty) => {
+            propagate_array_changes(config, buf, arg_name, int_ty)?;
+        }
+        SupportedArgumentType::Optional(ref inner) => {
+            if let SupportedArgumentType::IntegerSliceMutRef(_) = **inner {
+                writeln!(buf, "if ({0} != null) {{", arg_name)?;
+                // types are erased so recursion doesn't instantiate infinitely nested types.
+                let mut inner_buf: &mut dyn Write = &mut buf.indented(config.indent);
+                propogate_argument_changes_outwards(
+                    config,
+                    &mut inner_buf,
+                    &arg_name as &dyn Display,
+                    inner,
+                )?;
+                writeln!(buf, "}}")?;
+            }
+        }
+        SupportedArgumentType::IntegerSliceRef(_)
+        | SupportedArgumentType::IntegerVec(_)
+        | SupportedArgumentType::Integer(_)
+        | SupportedArgumentType::OwnedString
+        | SupportedArgumentType::StringSlice
+        | SupportedArgumentType::JsString
+        | SupportedArgumentType::Json
+        | SupportedArgumentType::Callback(..) => {}
+    }
+
+    Ok(())
+}
+
+/// Copies changes made to an array argument back into it.
+fn propagate_array_changes<T, U>(
+    config: &Config,
+    buf: &mut T,
+    arg_name: U,
+    int_ty: SupportedCopyTy,
+) -> fmt::Result
+where
+    T: Write,
+    U: Display,
+{
+    // propagate modifications outwards.
+    match config.access_style {
+        AccessStyle::TypedArrays => {
+            reconstruct_typed_array_if_memory_changed(
+                config,
+                buf,
+                format_args!("{}_view", arg_name),
+                format_args!("{}_ptr", arg_name),
+                format_args!("{}_len", arg_name),
+                int_ty,
             )?;
-            write!(
+            write!(buf, "if (typeof {0}.set == 'function') {{", arg_name)?;
+            writeln!(
                 buf.indented(config.indent),
-                "{1}.push(Boolean({0}_view[{0}_i]));\n",
-                temp_name,
-                result_name,
+                "{0}.set({0}_view);",
+                arg_name
             )?;
-            write!(buf, r"}}\n")?;
+            write!(buf, "}} else {{")?;
+            {
+                let mut buf = buf.indented(config.indent);
+
+                writeln!(
+                    buf,
+                    "for (var {0}_i = 0; {0}_i < {0}_len; {0}_i++) {{",
+                    arg_name
+                )?;
+                {
+                    let mut buf = buf.indented(config.indent);
+                    write!(buf, "{0}[{0}_i] = ", arg_name)?;
+                    if int_ty == SupportedCopyTy::Bool {
+                        write!(buf, "Boolean({0}_view[{0}_i])", arg_name)?;
+                    } else {
+                        write!(buf, "{0}_view[{0}_i]", arg_name)?;
+                    }
+                    writeln!(buf, ";")?;
+                }
+                writeln!(buf, "}}")?;
+            }
+            writeln!(buf, "}}")?;
         }
-        (AccessStyle::TypedArrays, _) => {
+        AccessStyle::DataView => {
+            // typed arrays of the same type are copied back in bulk.
+            let bulk_copy = int_ty != SupportedCopyTy::Bool;
+            if bulk_copy {
+                write!(
+                    buf,
+                    r#"if (this._little_endian && {0} instanceof {1}) {{
+    new Uint8Array({0}.buffer, {0}.byteOffset, {0}_byte_len)
+        .set(new Uint8Array(this._raw_mem.buffer, {0}_ptr, {0}_byte_len));
+}} else {{
+"#,
+                    arg_name,
+                    javascript_typed_array_for_int(int_ty),
+                )?;
+            }
+            {
+                let buf = &mut buf.indented(if bulk_copy { config.indent } else { 0 });
+                writeln!(
+                    buf,
+                    "for (var {0}_i = 0; {0}_i < {0}_len; {0}_i++) {{",
+                    arg_name
+                )?;
+                {
+                    let mut buf = buf.indented(config.indent);
+                    write!(buf, "{0}[{0}_i] = ", arg_name)?;
+                    js_get_ith_ty_at(
+                        &mut buf,
+                        "this._mem",
+                        int_ty,
+                        format_args!("{0}_ptr", arg_name),
+                        format_args!("{0}_i", arg_name),
+                    )?;
+                    writeln!(buf, ";")?;
+                }
+                writeln!(buf, "}}")?;
+            }
+            if bulk_copy {
+                writeln!(buf, "}}")?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn read_three_usize_array<T, U, V, W, X, Y>(
+    config: &Config,
+    buf: &mut T,
+    ptr_name: U,
+    temp_prefix: V,
+    item1: W,
+    item2: X,
+    item3: Y,
+) -> fmt::Result
+where
+    T: Write,
+    U: Display,
+    V: Display,
+    W: Display,
+    X: Display,
+    Y: Display,
+{
+    match config.access_style {
+        AccessStyle::TypedArrays => {
             write!(
                 buf,
-                r#"let {0} = {3}.from(new {3}(this._mem.buffer, {1}, {2}));
+                r#"let {0}_view = new {1}(this._mem.buffer, {2}, 3);
+let {3} = {0}_view[0];
+let {4} = {0}_view[1];
+let {5} = {0}_view[2];
 "#,
-                result_name,
+                temp_prefix,
+                javascript_typed_array_for_int(SupportedCopyTy::USize),
                 ptr_name,
-                byte_length_name,
-                javascript_typed_array_for_int(int_ty)
+                item1,
+                item2,
+                item3
             )?;
         }
-        (AccessStyle::DataView, _) => {
+        AccessStyle::DataView => {
             write!(
                 buf,
-                r#"let {0} = [];
-for (var {1}_i = 0; {1}_i < {2}; {1}_i++) {{
+                r#"
+let {3} = this._mem.getUint32({0}, true);
+let {4} = this._mem.getUint32({0} + {1}, true);
+let {5} = this._mem.getUint32({0} + {2}, true);
 "#,
-                result_name,
-                temp_name,
-                length_name,
+                ptr_name,
+                SupportedCopyTy::USize.size_in_bytes(),
+                SupportedCopyTy::USize.size_in_bytes() * 2,
+                item1,
+                item2,
+                item3
             )?;
-            {
-                let mut buf = buf.indented(config.indent);
-                write!(buf, "{0}.push(", result_name)?;
-                js_get_ith_ty_at(
-                    &mut buf,
-                    "this._mem",
-                    int_ty,
+        }
+    }
+
+    Ok(())
+}
+fn read_two_usize_array<T, U, V, W, X>(
+    config: &Config,
+    buf: &mut T,
+    ptr_name: U,
+    temp_prefix: V,
+    item1: W,
+    item2: X,
+) -> fmt::Result
+where
+    T: Write,
+    U: Display,
+    V: Display,
+    W: Display,
+    X: Display,
+{
+    match config.access_style {
+        AccessStyle::TypedArrays => {
+            write!(
+                buf,
+                r#"let {0}_view = new {2}(this._mem.buffer, {3}, {1});
+let {4} = {0}_view[0];
+let {5} = {0}_view[1];
+"#,
+                temp_prefix,
+                2,
+                javascript_typed_array_for_int(SupportedCopyTy::USize),
+                ptr_name,
+                item1,
+                item2
+            )?;
+        }
+        AccessStyle::DataView => {
+            write!(
+                buf,
+                r#"let {2} = this._mem.getUint32({0}, true);
+let {3} = this._mem.getUint32({0} + {1}, true);
+"#,
+                ptr_name,
+                SupportedCopyTy::USize.size_in_bytes(),
+                item1,
+                item2
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads any number of usizes from `ptr_name` into new variables.
+fn read_usize_array<T, U, V, W>(
+    config: &Config,
+    buf: &mut T,
+    ptr_name: U,
+    temp_prefix: V,
+    items: &[W],
+) -> fmt::Result
+where
+    T: Write,
+    U: Display,
+    V: Display,
+    W: Display,
+{
+    if config.access_style == AccessStyle::TypedArrays {
+        writeln!(
+            buf,
+            "let {0}_view = new {1}(this._mem.buffer, {2}, {3});",
+            temp_prefix,
+            javascript_typed_array_for_int(SupportedCopyTy::USize),
+            ptr_name,
+            items.len()
+        )?;
+    }
+    for (i, item) in items.iter().enumerate() {
+        match config.access_style {
+            AccessStyle::TypedArrays => {
+                writeln!(buf, "let {} = {}_view[{}];", item, temp_prefix, i)?;
+            }
+            AccessStyle::DataView => {
+                writeln!(
+                    buf,
+                    "let {} = this._mem.getUint32({} + {}, true);",
+                    item,
                     ptr_name,
-                    format_args!("{}_i", temp_name),
+                    SupportedCopyTy::USize.size_in_bytes() * i
                 )?;
-                write!(buf, ");\n")?;
             }
-            write!(buf, "}}\n")?;
         }
     }
+
     Ok(())
 }
 
-fn copy_string_out<T, U, V, W, X, Y>(
+/// Writes any number of usizes to `ptr_name`.
+fn write_usize_array<T, U, V, W>(
     config: &Config,
     buf: &mut T,
     ptr_name: U,
-    length_name: V,
-    byte_length_name: W,
-    temp_name: X,
-    result_name: Y,
+    temp_prefix: V,
+    items: &[W],
 ) -> fmt::Result
 where
     T: Write,
     U: Display,
     V: Display,
     W: Display,
-    X: Display,
-    Y: Display,
 {
-    if config.access_style == AccessStyle::TypedArrays {
+    if config.access_style == AccessStyle::TypedArrays {
+        writeln!(
+            buf,
+            "let {0}_view = new {1}(this._mem.buffer, {2}, {3});",
+            temp_prefix,
+            javascript_typed_array_for_int(SupportedCopyTy::USize),
+            ptr_name,
+            items.len()
+        )?;
+    }
+    for (i, item) in items.iter().enumerate() {
+        match config.access_style {
+            AccessStyle::TypedArrays => {
+                writeln!(buf, "{}_view[{}] = {};", temp_prefix, i, item)?;
+            }
+            AccessStyle::DataView => {
+                writeln!(
+                    buf,
+                    "this._mem.setUint32({} + {}, {}, true);",
+                    ptr_name,
+                    SupportedCopyTy::USize.size_in_bytes() * i,
+                    item
+                )?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn dealloc_three_usize_array<T, U>(_config: &Config, buf: &mut T, ptr_name: U) -> fmt::Result
+where
+    T: Write,
+    U: Display,
+{
+    writeln!(
+        buf,
+        "this._dealloc({0}, {1});",
+        ptr_name,
+        SupportedCopyTy::USize.size_in_bytes() * 3
+    )
+}
+
+fn copy_array_out<T, U, V, X, Y>(
+    config: &Config,
+    buf: &mut T,
+    ptr_name: U,
+    length_name: V,
+    temp_name: X,
+    result_name: Y,
+    int_ty: SupportedCopyTy,
+) -> fmt::Result
+where
+    T: Write,
+    U: Display,
+    V: Display,
+    X: Display,
+    Y: Display,
+{
+    match (config.access_style, int_ty) {
+        (AccessStyle::TypedArrays, SupportedCopyTy::Bool) => {
+            write!(
+                buf,
+                r#"let {0}_view = new {3}(this._mem.buffer, {4}, {1});
+let {2} = [];
+for (var {0}_i = 0; {0}_i < {1}; {0}_i++) {{
+"#,
+                temp_name,
+                length_name,
+                result_name,
+                javascript_typed_array_for_int(int_ty),
+                ptr_name,
+            )?;
+            writeln!(
+                buf.indented(config.indent),
+                "{1}.push(Boolean({0}_view[{0}_i]));",
+                temp_name,
+                result_name,
+            )?;
+            write!(buf, r"}}\n")?;
+        }
+        (AccessStyle::TypedArrays, _) => {
+            writeln!(
+                buf,
+                "let {0} = {3}.from(new {3}(this._mem.buffer, {1}, {2}));",
+                result_name,
+                ptr_name,
+                length_name,
+                javascript_typed_array_for_int(int_ty)
+            )?;
+        }
+        (AccessStyle::DataView, SupportedCopyTy::Bool) => {
+            write!(
+                buf,
+                r#"let {0} = [];
+for (var {1}_i = 0; {1}_i < {2}; {1}_i++) {{
+"#,
+                result_name,
+                temp_name,
+                length_name,
+            )?;
+            {
+                let mut buf = buf.indented(config.indent);
+                write!(buf, "{0}.push(", result_name)?;
+                js_get_ith_ty_at(
+                    &mut buf,
+                    "this._mem",
+                    int_ty,
+                    ptr_name,
+                    format_args!("{}_i", temp_name),
+                )?;
+                writeln!(buf, ");")?;
+            }
+            writeln!(buf, "}}")?;
+        }
+        (AccessStyle::DataView, _) => {
+            // copied in bulk through a typed array where the byte order allows.
+            write!(
+                buf,
+                r#"let {0};
+if (this._little_endian) {{
+    {0} = Array.from(new {3}(this._raw_mem.buffer, {4}, {2}));
+}} else {{
+    {0} = [];
+    for (var {1}_i = 0; {1}_i < {2}; {1}_i++) {{
+"#,
+                result_name,
+                temp_name,
+                length_name,
+                javascript_typed_array_for_int(int_ty),
+                ptr_name,
+            )?;
+            {
+                let mut buf = buf.indented(config.indent * 2);
+                write!(buf, "{0}.push(", result_name)?;
+                js_get_ith_ty_at(
+                    &mut buf,
+                    "this._mem",
+                    int_ty,
+                    &ptr_name,
+                    format_args!("{}_i", temp_name),
+                )?;
+                writeln!(buf, ");")?;
+            }
+            writeln!(buf.indented(config.indent), "}}")?;
+            writeln!(buf, "}}")?;
+        }
+    }
+    Ok(())
+}
+
+fn copy_string_out<T, U, V, X, Y>(
+    config: &Config,
+    buf: &mut T,
+    ptr_name: U,
+    length_name: V,
+    temp_name: X,
+    result_name: Y,
+) -> fmt::Result
+where
+    T: Write,
+    U: Display,
+    V: Display,
+    X: Display,
+    Y: Display,
+{
+    if config.string_encoding == StringEncoding::Utf8 {
+        write!(
+            buf,
+            r#"let {0} = this._decoder.decode(
+    new Uint8Array(this._mem.buffer, {1}, {2})
+);
+"#,
+            result_name,
+            ptr_name,
+            length_name
+        )?;
+        return Ok(());
+    }
+
+    if config.access_style == AccessStyle::TypedArrays {
+        writeln!(
+            buf,
+            "let {0}_view = new {1}(this._mem.buffer, {2}, {3});",
+            temp_name,
+            javascript_typed_array_for_int(SupportedCopyTy::U16),
+            ptr_name,
+            length_name
+        )?;
+    }
+
+    write!(
+        buf,
+        r#"let {2} = "";
+for (var {0}_i = 0; {0}_i < {1}; {0}_i++) {{
+"#,
+        temp_name,
+        length_name,
+        result_name
+    )?;
+    {
+        let mut buf = buf.indented(config.indent);
+        write!(buf, "{0} += String.fromCharCode(", result_name)?;
+        match config.access_style {
+            AccessStyle::TypedArrays => {
+                write!(buf, "{0}_view[{0}_i]", temp_name)?;
+            }
+            AccessStyle::DataView => {
+                js_get_ith_ty_at(
+                    &mut buf,
+                    "this._mem",
+                    SupportedCopyTy::U16,
+                    ptr_name,
+                    format_args!("{0}_i", temp_name),
+                )?;
+            }
+        }
+        writeln!(buf, ");")?;
+    }
+    writeln!(buf, "}}")?;
+
+    Ok(())
+}
+
+fn read_return_value_copy_into<T, U, V>(
+    config: &Config,
+    buf: &mut T,
+    ty: &SupportedRetType,
+    self_ty: Option<&str>,
+    from_var: U,
+    to_var: V,
+) -> fmt::Result
+where
+    T: Write,
+    U: Display,
+    V: Display,
+{
+    match *ty {
+        SupportedRetType::Unit => {
+            writeln!(buf, "let {} = {};", to_var, from_var)?;
+        }
+        SupportedRetType::Integer(SupportedCopyTy::Bool) => {
+            writeln!(buf, "let {} = Boolean({});", to_var, from_var)?;
+        }
+        SupportedRetType::Integer(SupportedCopyTy::U64) => {
+            // WebAssembly always returns i64s as signed BigInts.
+            writeln!(buf, "let {} = BigInt.asUintN(64, {});", to_var, from_var)?;
+        }
+        SupportedRetType::Integer(_) => {
+            writeln!(buf, "let {} = {};", to_var, from_var)?;
+        }
+        SupportedRetType::OwnedSelf => {
+            writeln!(
+                buf,
+                "let {} = new {}(this, {});",
+                to_var,
+                self_ty.expect("expected Self return type only inside impl block"),
+                from_var
+            )?;
+        }
+        SupportedRetType::IntegerVec(int_ty) => {
+            read_three_usize_array(
+                config,
+                buf,
+                &from_var,
+                format_args!("{}_temp", from_var),
+                "return_ptr",
+                "return_len",
+                "return_cap",
+            )?;
+            writeln!(
+                buf,
+                "let return_byte_cap = return_cap * {};",
+                int_ty.size_in_bytes()
+            )?;
+            copy_array_out(
+                config,
+                buf,
+                "return_ptr",
+                "return_len",
+                "return_tmp",
+                to_var,
+                int_ty,
+            )?;
+        }
+        SupportedRetType::Buffer(int_ty) => {
+            read_three_usize_array(
+                config,
+                buf,
+                &from_var,
+                format_args!("{}_temp", from_var),
+                "return_ptr",
+                "return_len",
+                "return_cap",
+            )?;
+            // the data stays allocated until the buffer is freed.
+            writeln!(
+                buf,
+                "let {} = new JsBuffer(this, {}, return_ptr, return_len, return_ptr, \
+                 return_cap * {});",
+                to_var,
+                javascript_typed_array_for_int(int_ty),
+                int_ty.size_in_bytes()
+            )?;
+        }
+        SupportedRetType::OwnedString | SupportedRetType::StringSlice => {
+            read_string_return_value(config, buf, from_var, to_var)?;
+        }
+        SupportedRetType::Json => {
+            let json_var = format!("{}_json", to_var);
+            read_string_return_value(config, buf, from_var, &json_var)?;
+            writeln!(buf, "let {} = JSON.parse({});", to_var, json_var)?;
+        }
+        SupportedRetType::Optional(ref inner) => {
+            // not present values are returned as null pointers.
+            write!(buf, "let {0};\nif ({1} == 0) {{\n", to_var, from_var)?;
+            writeln!(buf.indented(config.indent), "{} = null;", to_var)?;
+            writeln!(buf, "}} else {{")?;
+            {
+                let buf = &mut buf.indented(config.indent);
+                let inner_var = format!("{}_inner", to_var);
+                read_boxed_return_value(config, buf, inner, self_ty, &from_var, &inner_var)?;
+                writeln!(buf, "{} = {};", to_var, inner_var)?;
+            }
+            writeln!(buf, "}}")?;
+        }
+        SupportedRetType::Result(ref inner) => {
+            // results are a [discriminant, payload] pair, where the payload is
+            // either the Ok value or the error message.
+            let is_ok_var = format!("{}_is_ok", from_var);
+            let payload_var = format!("{}_payload", from_var);
+            let error_var = format!("{}_error", to_var);
+            read_two_usize_array(
+                config,
+                buf,
+                &from_var,
+                format_args!("{}_temp", from_var),
+                &is_ok_var,
+                &payload_var,
+            )?;
+            writeln!(
+                buf,
+                "this._dealloc({}, {});",
+                from_var,
+                SupportedCopyTy::USize.size_in_bytes() * 2
+            )?;
+            // the error is thrown by the caller, once everything is deallocated.
+            write!(buf, "let {};\nlet {} = null;\n", to_var, error_var)?;
+            writeln!(buf, "if ({}) {{", is_ok_var)?;
+            if **inner != SupportedRetType::Unit {
+                let buf = &mut buf.indented(config.indent);
+                let inner_var = format!("{}_inner", to_var);
+                read_boxed_return_value(config, buf, inner, self_ty, &payload_var, &inner_var)?;
+                writeln!(buf, "{} = {};", to_var, inner_var)?;
+            }
+            writeln!(buf, "}} else {{")?;
+            {
+                let buf = &mut buf.indented(config.indent);
+                let message_var = format!("{}_message", error_var);
+                read_boxed_return_value(
+                    config,
+                    buf,
+                    &SupportedRetType::OwnedString,
+                    self_ty,
+                    &payload_var,
+                    &message_var,
+                )?;
+                writeln!(
+                    buf,
+                    "{} = new {}({});",
+                    error_var,
+                    config.error_class_name(),
+                    message_var
+                )?;
+            }
+            writeln!(buf, "}}")?;
+        }
+        SupportedRetType::Tuple(ref elements, ref names) => {
+            // tuples are an array of payloads, each read like a result's Ok
+            // value.
+            let payload_vars = (0..elements.len())
+                .map(|i| format!("{}_{}", from_var, i))
+                .collect::<Vec<_>>();
+            let element_vars = (0..elements.len())
+                .map(|i| format!("{}_{}", to_var, i))
+                .collect::<Vec<_>>();
+            read_usize_array(
+                config,
+                buf,
+                &from_var,
+                format_args!("{}_temp", from_var),
+                &payload_vars,
+            )?;
+            writeln!(
+                buf,
+                "this._dealloc({}, {});",
+                from_var,
+                SupportedCopyTy::USize.size_in_bytes() * elements.len()
+            )?;
+            for ((element, payload_var), element_var) in
+                elements.iter().zip(&payload_vars).zip(&element_vars)
+            {
+                // each element is read in its own block, since reading
+                // declares the same temporary variables for every string.
+                write!(buf, "let {};\n{{\n", element_var)?;
+                {
+                    let buf = &mut buf.indented(config.indent);
+                    let inner_var = format!("{}_inner", element_var);
+                    read_boxed_return_value(
+                        config,
+                        buf,
+                        element,
+                        self_ty,
+                        payload_var,
+                        &inner_var,
+                    )?;
+                    writeln!(buf, "{} = {};", element_var, inner_var)?;
+                }
+                writeln!(buf, "}}")?;
+            }
+            match *names {
+                Some(ref names) => {
+                    let fields = names
+                        .iter()
+                        .zip(&element_vars)
+                        .map(|(name, element_var)| format!("{}: {}", name, element_var))
+                        .collect::<Vec<_>>();
+                    writeln!(buf, "let {} = {{ {} }};", to_var, fields.join(", "))?;
+                }
+                None => writeln!(buf, "let {} = [{}];", to_var, element_vars.join(", "))?,
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads a returned string, in the layout of a `Vec`.
+fn read_string_return_value<T, U, V>(
+    config: &Config,
+    buf: &mut T,
+    from_var: U,
+    to_var: V,
+) -> fmt::Result
+where
+    T: Write,
+    U: Display,
+    V: Display,
+{
+    read_three_usize_array(
+        config,
+        buf,
+        &from_var,
+        format_args!("{}_temp", from_var),
+        "return_ptr",
+        "return_len",
+        "return_cap",
+    )?;
+    writeln!(
+        buf,
+        "let return_byte_cap = return_cap * {};",
+        string_element_ty(config).size_in_bytes()
+    )?;
+    copy_string_out(
+        config,
+        buf,
+        "return_ptr",
+        "return_len",
+        "return_tmp",
+        to_var,
+    )?;
+
+    Ok(())
+}
+
+/// Reads a returned value which is known to be present, boxing integers, and
+/// deallocates it.
+fn read_boxed_return_value<T, U, V>(
+    config: &Config,
+    buf: &mut T,
+    ty: &SupportedRetType,
+    self_ty: Option<&str>,
+    from_var: U,
+    to_var: V,
+) -> fmt::Result
+where
+    T: Write,
+    U: Display,
+    V: Display,
+{
+    match *ty {
+        SupportedRetType::Integer(int_ty) => {
+            write!(buf, "let {} = ", to_var)?;
+            match (config.access_style, int_ty) {
+                (AccessStyle::TypedArrays, SupportedCopyTy::Bool) => {
+                    write!(
+                        buf,
+                        "Boolean(new {}(this._mem.buffer, {}, 1)[0])",
+                        javascript_typed_array_for_int(int_ty),
+                        from_var
+                    )?;
+                }
+                (AccessStyle::TypedArrays, _) => {
+                    write!(
+                        buf,
+                        "new {}(this._mem.buffer, {}, 1)[0]",
+                        javascript_typed_array_for_int(int_ty),
+                        from_var
+                    )?;
+                }
+                (AccessStyle::DataView, _) => {
+                    js_get_ith_ty_at(&mut *buf, "this._mem", int_ty, &from_var, 0)?;
+                }
+            }
+            writeln!(buf, ";")?;
+            writeln!(
+                buf,
+                "this._dealloc({}, {});",
+                from_var,
+                int_ty.size_in_bytes()
+            )?;
+        }
+        ref other => {
+            // types are erased so recursion doesn't instantiate infinitely
+            // nested types.
+            let mut inner_buf: &mut dyn Write = buf;
+            read_return_value_copy_into(
+                config,
+                &mut inner_buf,
+                other,
+                self_ty,
+                &from_var as &dyn Display,
+                &to_var as &dyn Display,
+            )?;
+            deallocate_return_allocation(
+                config,
+                &mut inner_buf,
+                &from_var as &dyn Display,
+                other,
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+fn deallocate_return_allocation<T, U>(
+    config: &Config,
+    buf: &mut T,
+    from_var: U,
+    ty: &SupportedRetType,
+) -> fmt::Result
+where
+    T: Write,
+    U: Display,
+{
+    match *ty {
+        SupportedRetType::Unit | SupportedRetType::Integer(_) | SupportedRetType::OwnedSelf => {}
+        // StringSlice as well because the actual returned value is still a new vec
+        // (this is because of translation to utf16)
+        SupportedRetType::IntegerVec(_)
+        | SupportedRetType::OwnedString
+        | SupportedRetType::StringSlice
+        | SupportedRetType::Json => {
+            writeln!(buf, "this._dealloc(return_ptr, return_byte_cap);")?;
+            dealloc_three_usize_array(config, buf, from_var)?;
+        }
+        SupportedRetType::Buffer(_) => {
+            dealloc_three_usize_array(config, buf, from_var)?;
+        }
+        // deallocated along with reading the value, since that's conditional.
+        SupportedRetType::Optional(_)
+        | SupportedRetType::Result(_)
+        | SupportedRetType::Tuple(..) => {}
+    }
+
+    Ok(())
+}
+
+/// Writes the values passed to the exported function for an argument.
+fn write_passed_argument<T, U>(buf: &mut T, arg_name: U, ty: &SupportedArgumentType) -> fmt::Result
+where
+    T: Write,
+    U: Display,
+{
+    match *ty {
+        SupportedArgumentType::IntegerSliceRef(_)
+        | SupportedArgumentType::IntegerSliceMutRef(_)
+        | SupportedArgumentType::IntegerVec(_)
+        | SupportedArgumentType::OwnedString
+        | SupportedArgumentType::StringSlice
+        | SupportedArgumentType::JsString
+        | SupportedArgumentType::Json => {
+            write!(buf, "{0}_ptr, {0}_len", arg_name)?;
+        }
+        SupportedArgumentType::Integer(_) => {
+            write!(buf, "{0}", arg_name)?;
+        }
+        SupportedArgumentType::Callback(..) => {
+            write!(buf, "{0}_index", arg_name)?;
+        }
+        SupportedArgumentType::Optional(ref inner) => {
+            if let SupportedArgumentType::Integer(_) = **inner {
+                write!(buf, "{0}_is_some, ", arg_name)?;
+            }
+            write_passed_argument(buf, arg_name, inner)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn write_method<T>(
+    config: &Config,
+    buf: &mut T,
+    info: &JsFnInfo,
+    stats: &FuncStats,
+) -> Result<(), Error>
+where
+    T: Write,
+{
+    let arg_names = js_argument_names(&info.args_names);
+
+    writeln!(buf)?;
+    // methods taking self are documented in their type's class instead, as
+    // are all methods when using a shared loader.
+    if info.self_arg.is_none() && !config.shared_loader {
+        write_jsdoc(config, buf, info, &arg_names)?;
+    }
+    write!(buf, "{}(", js_method_name(config, info))?;
+    let mut first_iteration = true;
+    if info.self_arg.is_some() {
+        write!(buf, "self_ptr")?;
+        first_iteration = false;
+    }
+    for arg_name in &arg_names {
+        if !first_iteration {
+            write!(buf, ", ")?;
+        }
+        write!(buf, "{}", arg_name)?;
+        first_iteration = false;
+    }
+    writeln!(buf, ") {{")?;
+
+    // with panics caught, arguments are deallocated even if the call traps,
+    // as they are if a callback throws. the scratch region is always released
+    // this way, since one trap would otherwise leave it in use for good.
+    let catch_panics = config.panic_strategy == PanicStrategy::ThrowRustPanic;
+    let uses_scratch = config.scratch_arena && stats.uses_borrowed_memory;
+    let uses_finally = catch_panics || stats.uses_callbacks || uses_scratch;
+
+    {
+        let buf = &mut buf.indented(config.indent);
+        // argument testing
+        for (i, ty) in info.args_ty.iter().enumerate() {
+            validate_argument(
+                config,
+                buf,
+                &arg_names[i],
+                ty,
+                "throw new Error();",
+            )?;
+        }
+        if uses_scratch {
+            writeln!(buf, "let scratch_mark = this._scratch_used;")?;
+        }
+        // allocations are inside the try, so that earlier arguments are freed
+        // if preparing a later one throws.
+        if uses_finally {
+            writeln!(buf, "try {{")?;
+        }
+        let buf = &mut buf.indented(if uses_finally { config.indent } else { 0 });
+
+        // allocation
+        for (i, ty) in info.args_ty.iter().enumerate() {
+            prepare_argument_allocation(config, buf, &arg_names[i], ty, config.scratch_arena)?;
+        }
+
+        // actual function call
+        write!(buf, "let result = this._funcs['{}'](", info.qualified_name())?;
+        let mut first_iteration = true;
+        if info.self_arg.is_some() {
+            write!(buf, "self_ptr")?;
+            first_iteration = false;
+        }
+        for (i, ty) in info.args_ty.iter().enumerate() {
+            if !first_iteration {
+                write!(buf, ", ")?;
+            }
+
+            write_passed_argument(buf, &arg_names[i], ty)?;
+            first_iteration = false;
+        }
+
+        writeln!(buf, ");")?;
+        if config.access_style == AccessStyle::DataView && stats.uses_post_function_memory_access {
+            writeln!(buf, "this._check_mem_realloc();")?;
+        }
+
+        for (i, ty) in info.args_ty.iter().enumerate() {
+            propogate_argument_changes_outwards(config, buf, &arg_names[i], ty)?;
+        }
+
+        read_return_value_copy_into(
+            config,
+            buf,
+            &info.ret_ty,
+            info.self_ty.as_deref(),
+            "result",
+            "return_value",
+        )?;
+
+        if !uses_finally {
+            for (i, ty) in info.args_ty.iter().enumerate() {
+                deallocate_argument_allocation(config, buf, &arg_names[i], ty)?;
+            }
+        }
+
+        deallocate_return_allocation(config, buf, "result", &info.ret_ty)?;
+
+        if let SupportedRetType::Result(_) = info.ret_ty {
+            writeln!(buf, "if (return_value_error != null) {{")?;
+            writeln!(buf.indented(config.indent), "throw return_value_error;")?;
+            writeln!(buf, "}}")?;
+        }
+
+        writeln!(buf, "return return_value;")?;
+    }
+
+    if uses_finally {
+        let buf = &mut buf.indented(config.indent);
+        if catch_panics {
+            writeln!(buf, "}} catch (e) {{")?;
+            writeln!(buf.indented(config.indent), "throw this._rust_panic(e);")?;
+        }
+        writeln!(buf, "}} finally {{")?;
+        {
+            let buf = &mut buf.indented(config.indent);
+            for (i, ty) in info.args_ty.iter().enumerate() {
+                deallocate_argument_allocation(config, buf, &arg_names[i], ty)?;
+            }
+            if uses_scratch {
+                writeln!(buf, "this._scratch_release(scratch_mark);")?;
+            }
+        }
+        writeln!(buf, "}}")?;
+    }
+
+    writeln!(buf, "}}")?;
+
+    Ok(())
+}
+
+/// Writes the module-level loader and the main class, whose static methods
+/// load the module and call free functions through the loader.
+fn write_shared_loader<T>(
+    config: &Config,
+    buf: &mut T,
+    func_stats: &[FuncStats],
+    imports: &[JsImportInfo],
+) -> Result<(), Error>
+where
+    T: Write,
+{
+    // static methods can't replace `prototype`, and shouldn't hide `load`.
+    const RESERVED_STATICS: &[&str] = &["load", "loadAsync", "prototype"];
+    for stat in func_stats {
+        if stat.self_arg.is_none() && RESERVED_STATICS.contains(&&*stat.rust_name) {
+            bail!(
+                "function `{}` can't be a static method when using a shared loader",
+                stat.qualified_name()
+            );
+        }
+    }
+
+    write!(buf, "\nlet loader = null;\n")?;
+
+    write!(buf, "\nfunction get_loader() {{\n")?;
+    {
+        let buf = &mut buf.indented(config.indent);
+        writeln!(buf, "if (loader == null) {{")?;
+        writeln!(
+            buf.indented(config.indent),
+            "throw new Error(\"{}.load() must be called before using the module\");",
+            config.class_name
+        )?;
+        writeln!(buf, "}}")?;
+        writeln!(buf, "return loader;")?;
+    }
+    writeln!(buf, "}}")?;
+
+    write!(buf, "\nclass {} {{\n", config.class_name)?;
+    {
+        let buf = &mut buf.indented(config.indent);
+        // imported functions are passed through to the constructor.
+        let imports_param = if imports.is_empty() { "" } else { ", imports" };
+        writeln!(buf, "static load(wasm_module{}) {{", imports_param)?;
+        writeln!(
+            buf.indented(config.indent),
+            "loader = new {}(wasm_module{});",
+            config.instance_class_name(),
+            imports_param
+        )?;
+        writeln!(buf, "}}")?;
+        if config.async_create {
+            write!(buf, "\nstatic async loadAsync(source{}) {{\n", imports_param)?;
+            writeln!(
+                buf.indented(config.indent),
+                "loader = await {}.create(source{});",
+                config.instance_class_name(),
+                imports_param
+            )?;
+            writeln!(buf, "}}")?;
+        }
+        if config.zero_copy_buffers {
+            write!(buf, "\nstatic allocBuffer(type, length) {{\n")?;
+            writeln!(
+                buf.indented(config.indent),
+                "return get_loader().allocBuffer(type, length);"
+            )?;
+            writeln!(buf, "}}")?;
+        }
+        for stat in func_stats.iter().filter(|stat| stat.self_ty.is_none()) {
+            write_loader_static_method(config, buf, stat)?;
+        }
+    }
+    writeln!(buf, "}}")?;
+
+    Ok(())
+}
+
+/// Writes a static method calling a function without `self` through the
+/// shared loader.
+fn write_loader_static_method<T>(
+    config: &Config,
+    buf: &mut T,
+    info: &JsFnInfo,
+) -> Result<(), Error>
+where
+    T: Write,
+{
+    let arg_names = js_argument_names(&info.args_names);
+    writeln!(buf)?;
+    write_jsdoc(config, buf, info, &arg_names)?;
+    writeln!(buf, "static {}({}) {{", info.rust_name, arg_names.join(", "))?;
+    writeln!(
+        buf.indented(config.indent),
+        "return get_loader().{}({});",
+        js_method_name(config, info),
+        arg_names.join(", ")
+    )?;
+    writeln!(buf, "}}")?;
+
+    Ok(())
+}
+
+/// Writes a class wrapping a boxed rust value of the given type, forwarding
+/// each method to the main class (or the loader class).
+fn write_self_type_class<T>(
+    config: &Config,
+    buf: &mut T,
+    self_ty: &str,
+    func_stats: &[FuncStats],
+) -> Result<(), Error>
+where
+    T: Write,
+{
+    write!(buf, "\nclass {} {{\n", self_ty)?;
+    {
+        let buf = &mut buf.indented(config.indent);
+        writeln!(buf, "constructor (wrapper, ptr) {{")?;
+        write!(
+            buf.indented(config.indent),
+            "this._wrapper = wrapper;\nthis._ptr = ptr;\n"
+        )?;
+        writeln!(buf, "}}")?;
+
+        write!(buf, "\n_check_ptr() {{\n")?;
+        {
+            let buf = &mut buf.indented(config.indent);
+            writeln!(buf, "if (this._ptr == 0) {{")?;
+            writeln!(
+                buf.indented(config.indent),
+                "throw new Error(\"{} has already been freed\");",
+                self_ty
+            )?;
+            writeln!(buf, "}}")?;
+        }
+        writeln!(buf, "}}")?;
+
+        if config.shared_loader {
+            for stat in func_stats.iter().filter(|stat| is_static_in_type(stat, self_ty)) {
+                write_loader_static_method(config, buf, stat)?;
+            }
+        }
+
+        for stat in func_stats {
+            let self_arg = match stat.self_arg {
+                Some(self_arg) if stat.self_ty.as_deref() == Some(self_ty) => {
+                    self_arg
+                }
+                _ => continue,
+            };
+            let arg_names = js_argument_names(&stat.args_names);
+            writeln!(buf)?;
+            write_jsdoc(config, buf, stat, &arg_names)?;
+            writeln!(buf, "{}({}) {{", stat.rust_name, arg_names.join(", "))?;
+            {
+                let buf = &mut buf.indented(config.indent);
+                writeln!(buf, "this._check_ptr();")?;
+                writeln!(buf, "let ptr = this._ptr;")?;
+                if self_arg == SupportedSelfArgument::Value {
+                    // the rust side takes ownership of the value and frees it,
+                    // even if the call ends up throwing.
+                    writeln!(buf, "this._ptr = 0;")?;
+                }
+                write!(buf, "return this._wrapper.{}(ptr", js_method_name(config, stat))?;
+                for arg_name in &arg_names {
+                    write!(buf, ", {}", arg_name)?;
+                }
+                writeln!(buf, ");")?;
+            }
+            writeln!(buf, "}}")?;
+        }
+
+        write!(buf, "\nfree() {{\n")?;
+        {
+            let buf = &mut buf.indented(config.indent);
+            writeln!(buf, "if (this._ptr != 0) {{")?;
+            write!(
+                buf.indented(config.indent),
+                "this._wrapper._funcs['{}'](this._ptr);\nthis._ptr = 0;\n",
+                drop_fn_name(self_ty)
+            )?;
+            writeln!(buf, "}}")?;
+        }
+        writeln!(buf, "}}")?;
+    }
+    writeln!(buf, "}}")?;
+
+    Ok(())
+}
+
+/// Writes the Error subclass thrown with the message of rust `Err` results.
+fn write_error_class<T>(config: &Config, buf: &mut T) -> Result<(), Error>
+where
+    T: Write,
+{
+    let error_class_name = config.error_class_name();
+    write!(buf, "\nclass {} extends Error {{\n", error_class_name)?;
+    {
+        let buf = &mut buf.indented(config.indent);
+        writeln!(buf, "constructor (message) {{")?;
+        write!(
+            buf.indented(config.indent),
+            "super(message);\nthis.name = \"{}\";\n",
+            error_class_name
+        )?;
+        writeln!(buf, "}}")?;
+    }
+    writeln!(buf, "}}")?;
+
+    Ok(())
+}
+
+/// Writes the method allocating a `JsBuffer` of the given element type, filled
+/// with zeros.
+///
+/// The alloc builtin only guarantees byte alignment, so buffers are placed at
+/// the first address in a slightly larger allocation aligned for the type.
+fn write_alloc_buffer_method<T>(config: &Config, buf: &mut T) -> Result<(), Error>
+where
+    T: Write,
+{
+    write!(buf, "\nallocBuffer(type, length) {{\n")?;
+    {
+        let buf = &mut buf.indented(config.indent);
+        writeln!(buf, "let array_type = buffer_array_types.get(type);")?;
+        writeln!(buf, "if (array_type == undefined) {{")?;
+        writeln!(
+            buf.indented(config.indent),
+            "throw new TypeError(`unsupported buffer type: ${{type}}`);"
+        )?;
+        writeln!(buf, "}}")?;
+        writeln!(buf, "if (!Number.isSafeInteger(length) || length < 0) {{")?;
+        writeln!(
+            buf.indented(config.indent),
+            "throw new TypeError(\"buffer length must be a non-negative integer\");"
+        )?;
+        writeln!(buf, "}}")?;
+        write!(
+            buf,
+            r#"let align = array_type.BYTES_PER_ELEMENT;
+let byte_len = length * align;
+let alloc_len = byte_len + align - 1;
+let alloc_ptr = this._alloc(alloc_len);
+let ptr = alloc_ptr + (align - alloc_ptr % align) % align;
+new Uint8Array(this.{}.buffer, ptr, byte_len).fill(0);
+return new JsBuffer(this, array_type, ptr, length, alloc_ptr, alloc_len);
+"#,
+            raw_memory(config)
+        )?;
+    }
+    writeln!(buf, "}}")?;
+
+    Ok(())
+}
+
+/// Writes the class viewing rust memory in place, along with the typed arrays
+/// buffers can be allocated with.
+fn write_buffer_class<T>(config: &Config, buf: &mut T) -> Result<(), Error>
+where
+    T: Write,
+{
+    write!(buf, "\nconst buffer_array_types = new Map([\n")?;
+    for &ty in BUFFER_ELEMENT_TYPES {
+        writeln!(
+            buf.indented(config.indent),
+            "[\"{}\", {}],",
+            ty,
+            javascript_typed_array_for_int(ty)
+        )?;
+    }
+    writeln!(buf, "]);")?;
+
+    write!(buf, "\nclass JsBuffer {{\n")?;
+    {
+        let buf = &mut buf.indented(config.indent);
+        writeln!(
+            buf,
+            "constructor (wrapper, array_type, ptr, length, alloc_ptr, alloc_len) {{"
+        )?;
+        // buffers allocated from JavaScript start after the start of their
+        // allocation, since they're aligned for their type.
+        write!(
+            buf.indented(config.indent),
+            r#"this._wrapper = wrapper;
+this._array_type = array_type;
+this._ptr = ptr;
+this._len = length;
+this._alloc_ptr = alloc_ptr;
+this._alloc_len = alloc_len;
+this._view = null;
+"#
+        )?;
+        writeln!(buf, "}}")?;
+
+        write!(buf, "\nget length() {{\n")?;
+        writeln!(buf.indented(config.indent), "return this._len;")?;
+        writeln!(buf, "}}")?;
+
+        write!(buf, "\nget view() {{\n")?;
+        {
+            let buf = &mut buf.indented(config.indent);
+            writeln!(buf, "if (this._ptr == 0) {{")?;
+            writeln!(
+                buf.indented(config.indent),
+                "throw new Error(\"JsBuffer has already been freed\");"
+            )?;
+            writeln!(buf, "}}")?;
+            // empty buffers may not be aligned for their type.
+            writeln!(buf, "if (this._len == 0) {{")?;
+            writeln!(
+                buf.indented(config.indent),
+                "return new this._array_type(0);"
+            )?;
+            writeln!(buf, "}}")?;
+            // views are detached when memory grows, and recreated then.
+            writeln!(
+                buf,
+                "let buffer = this._wrapper.{}.buffer;",
+                raw_memory(config)
+            )?;
+            writeln!(
+                buf,
+                "if (this._view == null || this._view.buffer != buffer) {{"
+            )?;
+            writeln!(
+                buf.indented(config.indent),
+                "this._view = new this._array_type(buffer, this._ptr, this._len);"
+            )?;
+            writeln!(buf, "}}")?;
+            writeln!(buf, "return this._view;")?;
+        }
+        writeln!(buf, "}}")?;
+
+        write!(buf, "\nfree() {{\n")?;
+        {
+            let buf = &mut buf.indented(config.indent);
+            writeln!(buf, "if (this._ptr != 0) {{")?;
+            write!(
+                buf.indented(config.indent),
+                r#"this._wrapper._dealloc(this._alloc_ptr, this._alloc_len);
+this._ptr = 0;
+this._view = null;
+"#
+            )?;
+            writeln!(buf, "}}")?;
+        }
+        writeln!(buf, "}}")?;
+    }
+    writeln!(buf, "}}")?;
+
+    Ok(())
+}
+
+/// Writes the Error subclass thrown when rust code panics.
+fn write_rust_panic_class<T>(config: &Config, buf: &mut T) -> Result<(), Error>
+where
+    T: Write,
+{
+    write!(buf, "\nclass RustPanic extends Error {{\n")?;
+    {
+        let buf = &mut buf.indented(config.indent);
+        writeln!(buf, "constructor (message, location, trap) {{")?;
         write!(
-            buf,
-            "let {0}_view = new {1}(this._mem.buffer, return_ptr, {2});\n",
-            temp_name,
-            javascript_typed_array_for_int(SupportedCopyTy::U16),
-            byte_length_name
+            buf.indented(config.indent),
+            r#"super(message);
+this.name = "RustPanic";
+this.location = location;
+this.trap = trap;
+"#
         )?;
+        writeln!(buf, "}}")?;
     }
+    writeln!(buf, "}}")?;
 
-    write!(
-        buf,
-        r#"let {2} = "";
-for (var {0}_i = 0; {0}_i < {1}; {0}_i++) {{
-"#,
-        temp_name,
-        length_name,
-        result_name
-    )?;
-    {
-        let mut buf = buf.indented(config.indent);
-        write!(buf, "{0} += String.fromCharCode(", result_name)?;
-        match config.access_style {
-            AccessStyle::TypedArrays => {
-                write!(buf, "{0}_view[{0}_i]", temp_name)?;
-            }
-            AccessStyle::DataView => {
-                js_get_ith_ty_at(
-                    &mut buf,
-                    "this._mem",
-                    SupportedCopyTy::U16,
-                    ptr_name,
-                    format_args!("{0}_i", temp_name),
+    Ok(())
+}
+
+fn write_module_start<T>(config: &Config, buf: &mut T) -> Result<(), Error>
+where
+    T: Write,
+{
+    match config.module_format {
+        ModuleFormat::CommonJs | ModuleFormat::EsModule => {}
+        ModuleFormat::Umd => {
+            writeln!(buf, "(function (root, factory) {{")?;
+            {
+                let buf = &mut buf.indented(config.indent);
+                writeln!(buf, "if (typeof define == 'function' && define.amd) {{")?;
+                writeln!(buf.indented(config.indent), "define([], factory);")?;
+                writeln!(
+                    buf,
+                    "}} else if (typeof module == 'object' && module.exports) {{"
                 )?;
+                writeln!(buf.indented(config.indent), "module.exports = factory();")?;
+                writeln!(buf, "}} else {{")?;
+                writeln!(
+                    buf.indented(config.indent),
+                    "root.{} = factory();",
+                    config.class_name
+                )?;
+                writeln!(buf, "}}")?;
             }
+            writeln!(
+                buf,
+                "}}(typeof self != 'undefined' ? self : this, function () {{"
+            )?;
+        }
+        ModuleFormat::Global => {
+            writeln!(buf, "var {} = (function () {{", config.class_name)?;
         }
-        write!(buf, ");\n")?;
     }
-    write!(buf, "}}\n")?;
 
     Ok(())
 }
 
-fn read_return_value_copy_into<T, U, V>(
+fn write_class_definition_finish<T>(
     config: &Config,
     buf: &mut T,
-    ty: &SupportedRetType,
-    from_var: U,
-    to_var: V,
-) -> fmt::Result
+    any_result: bool,
+    self_types: &[&str],
+) -> Result<(), Error>
 where
     T: Write,
-    U: Display,
-    V: Display,
 {
-    match *ty {
-        SupportedRetType::Unit => {
-            write!(buf, "let {} = {};\n", to_var, from_var)?;
-        }
-        SupportedRetType::Integer(SupportedCopyTy::Bool) => {
-            write!(buf, "let {} = Boolean({});\n", to_var, from_var)?;
-        }
-        SupportedRetType::Integer(_) => {
-            write!(buf, "let {} = {};\n", to_var, from_var)?;
-        }
-        SupportedRetType::IntegerVec(int_ty) => {
-            read_three_usize_array(
-                config,
-                buf,
-                "result",
-                "result_temp",
-                "return_ptr",
-                "return_len",
-                "return_cap",
-            )?;
+    // classes exported alongside the main class.
+    let mut exported = Vec::new();
+    if any_result {
+        exported.push(config.error_class_name());
+    }
+    if config.panic_strategy == PanicStrategy::ThrowRustPanic {
+        exported.push("RustPanic".into());
+    }
+    if config.zero_copy_buffers {
+        exported.push("JsBuffer".into());
+    }
+    exported.extend(self_types.iter().map(|&self_ty| self_ty.into()));
+
+    match config.module_format {
+        ModuleFormat::CommonJs => {
             write!(
                 buf,
-                r#"let return_byte_len = return_len * {0};
-let return_byte_cap = return_cap * {0};
+                r#"
+exports = module.exports = {};
 "#,
-                int_ty.size_in_bytes()
-            )?;
-            copy_array_out(
-                config,
-                buf,
-                "return_ptr",
-                "return_len",
-                "return_byte_len",
-                "return_tmp",
-                to_var,
-                int_ty,
+                config.class_name,
             )?;
+            for name in &exported {
+                writeln!(buf, "exports.{0} = {0};", name)?;
+            }
         }
-        SupportedRetType::OwnedString | SupportedRetType::StringSlice => {
-            read_three_usize_array(
-                config,
-                buf,
-                "result",
-                "result_temp",
-                "return_ptr",
-                "return_len",
-                "return_cap",
-            )?;
-            write!(
-                buf,
-                r#"let return_byte_len = return_len * {0};
-let return_byte_cap = return_cap * {0};
-"#,
-                SupportedCopyTy::U16.size_in_bytes()
-            )?;
-            copy_string_out(
-                config,
-                buf,
-                "return_ptr",
-                "return_len",
-                "return_byte_len",
-                "return_tmp",
-                to_var,
-            )?;
+        ModuleFormat::EsModule => {
+            write!(buf, "\nexport default {};\n", config.class_name)?;
+            write!(buf, "export {{ {}", config.class_name)?;
+            for name in &exported {
+                write!(buf, ", {}", name)?;
+            }
+            writeln!(buf, " }};")?;
+        }
+        ModuleFormat::Umd | ModuleFormat::Global => {
+            writeln!(buf)?;
+            for name in &exported {
+                writeln!(buf, "{0}.{1} = {1};", config.class_name, name)?;
+            }
+            writeln!(buf, "return {};", config.class_name)?;
         }
     }
-
     Ok(())
 }
 
-fn deallocate_return_allocation<T, U>(
-    config: &Config,
-    buf: &mut T,
-    from_var: U,
-    ty: &SupportedRetType,
-) -> fmt::Result
+fn write_module_end<T>(config: &Config, buf: &mut T) -> Result<(), Error>
 where
     T: Write,
-    U: Display,
 {
-    match *ty {
-        SupportedRetType::Unit | SupportedRetType::Integer(_) => {}
-        // StringSlice as well because the actual returned value is still a new vec
-        // (this is because of translation to utf16)
-        SupportedRetType::IntegerVec(_)
-        | SupportedRetType::OwnedString
-        | SupportedRetType::StringSlice => {
-            write!(buf, "this._dealloc(return_ptr, return_byte_cap);\n")?;
-            dealloc_three_usize_array(config, buf, from_var)?;
-        }
+    match config.module_format {
+        ModuleFormat::CommonJs | ModuleFormat::EsModule => {}
+        ModuleFormat::Umd => writeln!(buf, "}}));")?,
+        ModuleFormat::Global => writeln!(buf, "}})();")?,
     }
 
     Ok(())
 }
 
-fn write_method<T>(
+/// Name of the method on the main class (or the loader class) calling the
+/// given function.
+///
+/// Functions in impl blocks get hidden methods which are then exposed through
+/// their type's class, as do all functions when using a shared loader.
+fn js_method_name(config: &Config, info: &JsFnInfo) -> String {
+    match info.self_ty {
+        Some(_) => format!("_{}", info.qualified_name()),
+        None if config.shared_loader => format!("_{}", info.rust_name),
+        None => info.rust_name.clone(),
+    }
+}
+
+/// Renames rust identifiers which are reserved in JavaScript, but not rust.
+fn js_identifier(name: &str) -> Cow<'_, str> {
+    // rust keywords can't be argument names, so only JavaScript reserved words
+    // which aren't rust keywords need to be listed.
+    const RESERVED: &[&str] = &[
+        "arguments", "await", "case", "catch", "class", "debugger", "default", "delete",
+        "eval", "export", "extends", "finally", "function", "implements", "import",
+        "instanceof", "interface", "new", "null", "package", "private", "protected", "public",
+        "switch", "this", "throw", "try", "typeof", "undefined", "var", "void", "with",
+    ];
+    if RESERVED.contains(&name) {
+        Cow::Owned(format!("{}_", name))
+    } else {
+        Cow::Borrowed(name)
+    }
+}
+
+/// Names of the JavaScript parameters for a function's arguments: the rust
+/// names, renamed where they would clash with reserved words or with the
+/// variables generated for other arguments, like `{name}_ptr`.
+fn js_argument_names(args_names: &[String]) -> Vec<String> {
+    let mut names = Vec::<String>::with_capacity(args_names.len());
+    for name in args_names {
+        let mut js_name = js_identifier(name).into_owned();
+        while is_generated_variable(&js_name)
+            || names.iter().any(|other| variables_clash(&js_name, other))
+        {
+            js_name.insert(0, '_');
+        }
+        names.push(js_name);
+    }
+    names
+}
+
+/// Whether the name is used by generated code which isn't for an argument,
+/// including module-level helpers called from methods.
+fn is_generated_variable(name: &str) -> bool {
+    name == "result" || name == "ptr" || name == "self_ptr" || name == "scratch_mark"
+        || name == "get_loader" || name.starts_with("result_") || name.starts_with("return_")
+}
+
+fn variables_clash(a: &str, b: &str) -> bool {
+    a == b || a.starts_with(&format!("{}_", b)) || b.starts_with(&format!("{}_", a))
+}
+
+/// Writes a JSDoc comment with the function's doc comment and types.
+fn write_jsdoc<T>(
     config: &Config,
     buf: &mut T,
     info: &JsFnInfo,
-    stats: &FuncStats,
-) -> Result<(), Error>
+    arg_names: &[String],
+) -> fmt::Result
 where
     T: Write,
 {
-    write!(buf, "\n{}(", info.rust_name)?;
-    let mut first_iteration = true;
-    for i in 0..info.args_ty.len() {
-        if !first_iteration {
-            write!(buf, ", ")?;
-        }
-        write!(buf, "arg{}", i)?;
-        first_iteration = false;
+    let has_tags = !info.args_ty.is_empty() || info.ret_ty != SupportedRetType::Unit;
+    if info.doc_lines.is_empty() && !has_tags {
+        return Ok(());
     }
-    write!(buf, ") {{\n")?;
-
-    {
-        let buf = &mut buf.indented(config.indent);
-        // argument testing
-        for (i, &ty) in info.args_ty.iter().enumerate() {
-            validate_argument(
-                config,
-                buf,
-                format_args!("arg{}", i),
-                ty,
-                "throw new Error();",
-            )?;
-        }
-        // allocation
-        for (i, &ty) in info.args_ty.iter().enumerate() {
-            prepare_argument_allocation(config, buf, format_args!("arg{}", i), ty)?;
-        }
-
-        // actual function call
-        write!(buf, "let result = this._funcs['{}'](", info.rust_name)?;
-        let mut first_iteration = true;
-        for (i, &ty) in info.args_ty.iter().enumerate() {
-            if !first_iteration {
-                write!(buf, ", ")?;
-            }
-
-            match ty {
-                SupportedArgumentType::IntegerSliceRef(_)
-                | SupportedArgumentType::IntegerSliceMutRef(_)
-                | SupportedArgumentType::IntegerVec(_)
-                | SupportedArgumentType::OwnedString => {
-                    write!(buf, "arg{0}_ptr, arg{0}_len", i)?;
-                }
-                SupportedArgumentType::Integer(_) => {
-                    write!(buf, "arg{0}", i)?;
-                }
-            }
-            first_iteration = false;
-        }
-
-        write!(buf, ");\n")?;
-        if config.access_style == AccessStyle::DataView && stats.uses_post_function_memory_access {
-            write!(buf, "this._check_mem_realloc();\n")?;
+    writeln!(buf, "/**")?;
+    write_doc_lines(buf, &info.doc_lines)?;
+    if !info.doc_lines.is_empty() && has_tags {
+        writeln!(buf, " *")?;
+    }
+    let required_args = typescript::required_argument_count(info);
+    for (i, (name, ty)) in arg_names.iter().zip(&info.args_ty).enumerate() {
+        let ty = typescript::argument_type(config, ty, "");
+        if i < required_args {
+            writeln!(buf, " * @param {{{}}} {}", ty, name)?;
+        } else {
+            writeln!(buf, " * @param {{{}}} [{}]", ty, name)?;
         }
+    }
+    if info.ret_ty != SupportedRetType::Unit {
+        let ty = typescript::return_type(config, &info.ret_ty, info.self_ty.as_deref(), "");
+        writeln!(buf, " * @returns {{{}}}", ty)?;
+    }
+    if let SupportedRetType::Result(_) = info.ret_ty {
+        writeln!(buf, " * @throws {{{}}}", config.error_class_name())?;
+    }
+    writeln!(buf, " */")
+}
 
-        for (i, &ty) in info.args_ty.iter().enumerate() {
-            propogate_argument_changes_outwards(config, buf, format_args!("arg{}", i), ty)?;
+/// Writes lines inside of a `/** */` comment.
+fn write_doc_lines<T: Write>(buf: &mut T, lines: &[String]) -> fmt::Result {
+    for line in lines {
+        if line.is_empty() {
+            writeln!(buf, " *")?;
+        } else {
+            writeln!(buf, " * {}", line.replace("*/", "*\\/"))?;
         }
+    }
+    Ok(())
+}
 
-        read_return_value_copy_into(config, buf, &info.ret_ty, "result", "return_value")?;
-
-        for (i, &ty) in info.args_ty.iter().enumerate() {
-            deallocate_argument_allocation(config, buf, format_args!("arg{}", i), ty)?;
-        }
+/// Type strings are made of in memory.
+pub(crate) fn string_element_ty(config: &Config) -> SupportedCopyTy {
+    match config.string_encoding {
+        StringEncoding::Utf16 => SupportedCopyTy::U16,
+        StringEncoding::Utf8 => SupportedCopyTy::U8,
+    }
+}
 
-        deallocate_return_allocation(config, buf, "result", &info.ret_ty)?;
+/// Size in bytes of the scratch region before any call needs more.
+const INITIAL_SCRATCH_CAPACITY: usize = 1024;
+
+/// Types buffers can be allocated with, all but `bool`.
+const BUFFER_ELEMENT_TYPES: &[SupportedCopyTy] = &[
+    SupportedCopyTy::U8,
+    SupportedCopyTy::U16,
+    SupportedCopyTy::U32,
+    SupportedCopyTy::U64,
+    SupportedCopyTy::USize,
+    SupportedCopyTy::I8,
+    SupportedCopyTy::I16,
+    SupportedCopyTy::I32,
+    SupportedCopyTy::I64,
+    SupportedCopyTy::ISize,
+    SupportedCopyTy::F32,
+    SupportedCopyTy::F64,
+];
+
+/// Whether slices of the given type may be borrowed from buffers.
+fn accepts_buffer(config: &Config, ty: SupportedCopyTy) -> bool {
+    config.zero_copy_buffers && ty != SupportedCopyTy::Bool
+}
 
-        write!(buf, "return return_value;\n")?;
+/// Property holding the `WebAssembly.Memory` on the main class (or the
+/// loader class).
+fn raw_memory(config: &Config) -> &'static str {
+    match config.access_style {
+        AccessStyle::TypedArrays => "_mem",
+        AccessStyle::DataView => "_raw_mem",
     }
+}
 
-    write!(buf, "}}\n")?;
+/// Finds whether the generated code uses the module's memory and allocation
+/// functions.
+fn uses_memory_access(
+    config: &Config,
+    func_stats: &[FuncStats],
+    imports: &[JsImportInfo],
+) -> bool {
+    // reading panic messages needs memory access, as does allocating buffers.
+    config.panic_strategy == PanicStrategy::ThrowRustPanic
+        || config.zero_copy_buffers
+        || func_stats.iter().any(|stat| stat.uses_memory_access)
+        || imports.iter().any(stats::import_uses_memory_access)
+}
 
-    Ok(())
+/// Finds which of the module's exports the generated code uses: each
+/// function, drop function and builtin, along with `memory`.
+pub(crate) fn used_exports<'a, I>(
+    config: &Config,
+    infos: I,
+    imports: &[JsImportInfo],
+) -> UsedExports<'a>
+where
+    I: IntoIterator<Item = &'a JsFnInfo>,
+{
+    let func_stats = infos.into_iter().map(FuncStats::new).collect::<Vec<_>>();
+    let panic_builtins = config.panic_strategy == PanicStrategy::ThrowRustPanic;
+    let memory = uses_memory_access(config, &func_stats, imports);
+    UsedExports {
+        memory,
+        // panic messages are strings.
+        strings: panic_builtins || func_stats.iter().any(|stat| stat.uses_strings)
+            || imports.iter().any(stats::import_uses_strings),
+        panic_builtins,
+        scratch_arena: config.scratch_arena && memory,
+        self_types: collect_self_types(func_stats.iter().map(|stat| stat.inner)),
+        functions: func_stats.iter().map(|stat| stat.inner).collect(),
+    }
 }
 
+pub(crate) struct UsedExports<'a> {
+    /// Whether `memory` and the alloc and dealloc builtins are used.
+    pub memory: bool,
+    /// Whether any strings are passed, and so the string encoding matters.
+    pub strings: bool,
+    /// Whether the panic message and location builtins are used.
+    pub panic_builtins: bool,
+    /// Whether the scratch region's builtin is used.
+    pub scratch_arena: bool,
+    /// Types whose drop functions are used.
+    pub self_types: Vec<&'a str>,
+    pub functions: Vec<&'a JsFnInfo>,
+}
 
-fn write_class_definition_finish<T>(config: &Config, buf: &mut T) -> Result<(), Error>
+/// Finds types with impl blocks, in the order they were first seen.
+fn collect_self_types<'a, I>(infos: I) -> Vec<&'a str>
 where
-    T: Write,
+    I: IntoIterator<Item = &'a JsFnInfo>,
 {
-    write!(
-        buf,
-        r#"}}
+    let mut self_types = Vec::<&str>::new();
+    for info in infos {
+        if let Some(ref self_ty) = info.self_ty {
+            if !self_types.contains(&&**self_ty) {
+                self_types.push(self_ty);
+            }
+        }
+    }
+    self_types
+}
 
-exports = module.exports = {};
-"#,
-        config.class_name,
-    )?;
-    Ok(())
+fn is_static_in_type(info: &JsFnInfo, self_ty: &str) -> bool {
+    info.self_arg.is_none() && info.self_ty.as_deref() == Some(self_ty)
 }
 
 
@@ -925,9 +2997,11 @@ fn javascript_typed_array_for_int(ty: SupportedCopyTy) -> &'static str {
         U8 => "Uint8Array",
         U16 => "Uint16Array",
         U32 => "Uint32Array",
+        U64 => "BigUint64Array",
         I8 => "Int8Array",
         I16 => "Int16Array",
         I32 => "Int32Array",
+        I64 => "BigInt64Array",
         USize => "Uint32Array",
         ISize => "Int32Array",
         F32 => "Float32Array",
@@ -955,6 +3029,7 @@ where
 
     let value = match ty {
         Bool => format!("Boolean({})", value),
+        U64 | I64 => format!("BigInt({})", value),
         _ => value.to_string(),
     };
 
@@ -962,18 +3037,20 @@ where
         Bool | U8 => "setUint8",
         U16 => "setUint16",
         USize | U32 => "setUint32",
+        U64 => "setBigUint64",
         I8 => "setInt8",
         I16 => "setInt16",
         ISize | I32 => "setInt32",
+        I64 => "setBigInt64",
         F32 => "setFloat32",
         F64 => "setFloat64",
     };
 
     let offset = ty.size_in_bytes();
 
-    write!(
+    writeln!(
         buf,
-        "{}.{}({} + {} * {}, {}, true);\n",
+        "{}.{}({} + {} * {}, {}, true);",
         data_view_name,
         set_func_name,
         ptr_name,
@@ -1002,9 +3079,11 @@ where
         Bool | U8 => "getUint8",
         U16 => "getUint16",
         USize | U32 => "getUint32",
+        U64 => "getBigUint64",
         I8 => "getInt8",
         I16 => "getInt16",
         ISize | I32 => "getInt32",
+        I64 => "getBigInt64",
         F32 => "getFloat32",
         F64 => "getFloat64",
     };
@@ -1032,3 +3111,291 @@ where
         ),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use style::{Config, PanicStrategy, StringEncoding, StringValidation};
+    use test_util::translate_source;
+
+    use super::*;
+
+    fn generate(config: &Config, source: &str) -> String {
+        let (infos, imports) = translate_source(source).unwrap();
+        generate_javascript(config, &infos, &imports).unwrap()
+    }
+
+    fn generate_error(config: &Config, source: &str) -> String {
+        let (infos, imports) = translate_source(source).unwrap();
+        generate_javascript(config, &infos, &imports)
+            .expect_err("expected generation to fail")
+            .to_string()
+    }
+
+    #[track_caller]
+    fn assert_contains(js: &str, expected: &str) {
+        assert!(
+            js.contains(expected),
+            "expected generated code to contain\n{}\n\ngenerated code:\n{}",
+            expected,
+            js
+        );
+    }
+
+    #[track_caller]
+    fn assert_not_contains(js: &str, unexpected: &str) {
+        assert!(
+            !js.contains(unexpected),
+            "expected generated code not to contain\n{}\n\ngenerated code:\n{}",
+            unexpected,
+            js
+        );
+    }
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|&name| name.to_owned()).collect()
+    }
+
+    #[test]
+    fn simple_function() {
+        let js = generate(&Config::new(), "js_fn! { fn add(a: u32, b: u32) -> u32 => add; }");
+        assert_contains(&js, "class WasmWrapper {");
+        assert_contains(&js, "['add']: this._mod.exports[\"__js_fn_add\"],");
+        assert_contains(&js, "    add(a, b) {\n");
+        assert_contains(&js, "let result = this._funcs['add'](a, b);");
+        assert_contains(&js, "exports = module.exports = WasmWrapper;");
+        // nothing needs memory.
+        assert_not_contains(&js, "_alloc");
+    }
+
+    #[test]
+    fn class_name_and_indent() {
+        let mut config = Config::new();
+        config.with_class_name("Adder").with_indent(2);
+        let js = generate(&config, "js_fn! { fn add(a: u32, b: u32) -> u32 => add; }");
+        assert_contains(&js, "class Adder {\n  constructor (");
+        assert_contains(&js, "\n  add(a, b) {\n    if (a == null");
+    }
+
+    #[test]
+    fn self_types() {
+        let js = generate(
+            &Config::new(),
+            "pub struct Counter;
+             js_fn! {
+                 impl Counter {
+                     fn new(start: u32) -> Counter => Counter::new;
+                     fn get(&self) -> u32 => Counter::get;
+                     fn finish(self) -> u32 => Counter::finish;
+                 }
+             }",
+        );
+        assert_contains(&js, "['Counter__builtin_drop']: this._mod.exports[");
+        assert_contains(&js, "['new']: this._Counter__new.bind(this),");
+        assert_contains(&js, "let return_value = new Counter(this, result);");
+        assert_contains(&js, "class Counter {\n    constructor (wrapper, ptr) {");
+        // `self` methods consume the value.
+        assert_contains(
+            &js,
+            "        this._ptr = 0;\n        return this._wrapper._Counter__finish(ptr);",
+        );
+        assert_contains(&js, "this._wrapper._funcs['Counter__builtin_drop'](this._ptr);");
+        assert_contains(&js, "exports.Counter = Counter;");
+    }
+
+    #[test]
+    fn options_results_and_tuples() {
+        let js = generate(
+            &Config::new(),
+            "js_fn! {
+                 fn maybe(x: Option<u32>) -> Option<String> => maybe;
+                 fn parse(text: String) -> Result<(u32, bool), String> => parse;
+             }",
+        );
+        assert_contains(&js, "var x_is_some = x != null;");
+        assert_contains(&js, "this._funcs['maybe'](x_is_some, x);");
+        assert_contains(&js, "if (result == 0) {\n            return_value = null;");
+        assert_contains(&js, "let result_is_ok = this._mem.getUint32(result, true);");
+        assert_contains(
+            &js,
+            "let return_value_inner = [return_value_inner_0, return_value_inner_1];",
+        );
+        assert_contains(
+            &js,
+            "return_value_error = new WasmWrapperError(return_value_error_message);",
+        );
+        assert_contains(&js, "class WasmWrapperError extends Error {");
+        assert_contains(&js, " * @throws {WasmWrapperError}");
+    }
+
+    #[test]
+    fn named_tuple_returns() {
+        let js = generate(
+            &Config::new(),
+            "js_fn! {
+                 #[js_return_names(quotient, remainder)]
+                 fn divide(a: u32, b: u32) -> (u32, u32) => divide;
+             }",
+        );
+        assert_contains(&js, "{ quotient: return_value_0, remainder: return_value_1 }");
+    }
+
+    #[test]
+    fn big_ints() {
+        let js = generate(&Config::new(), "js_fn! { fn big(x: u64) -> i64 => big; }");
+        assert_contains(&js, "if (typeof x != 'bigint' && !Number.isSafeInteger(x)) {");
+        assert_contains(&js, "x = BigInt(x);");
+        assert_contains(&js, " * @param {bigint | number} x\n     * @returns {bigint}");
+    }
+
+    #[test]
+    fn utf16_strings() {
+        let js = generate(&Config::new(), "js_fn! { fn shout(text: &str) -> String => shout; }");
+        assert_contains(
+            &js,
+            "this._mem.setUint16(text_ptr + 2 * text_i, text_str.charCodeAt(text_i), true);",
+        );
+        assert_contains(&js, "String.fromCharCode(");
+        assert_not_contains(&js, "TextEncoder");
+    }
+
+    #[test]
+    fn utf8_strings() {
+        let mut config = Config::new();
+        config.with_string_encoding(StringEncoding::Utf8);
+        let js = generate(&config, "js_fn! { fn shout(text: &str) -> String => shout; }");
+        assert_contains(&js, "this._encoder = new TextEncoder();");
+        assert_contains(&js, "this._decoder = new TextDecoder();");
+        assert_contains(&js, "this._encoder.encodeInto(");
+        assert_not_contains(&js, "charCodeAt");
+    }
+
+    #[test]
+    fn strict_string_validation() {
+        let mut config = Config::new();
+        config.with_string_validation(StringValidation::Strict);
+        let js = generate(&config, "js_fn! { fn shout(text: &str) -> String => shout; }");
+        assert_contains(&js, "    _is_well_formed(string) {");
+        assert_contains(&js, "if (!this._is_well_formed(String(text))) {");
+        assert_contains(&js, "throw new TypeError(\"argument `text` is not well-formed UTF-16\");");
+    }
+
+    #[test]
+    fn imports() {
+        let js = generate(
+            &Config::new(),
+            "mod imports { js_import! { pub fn log(message: &str); } }",
+        );
+        assert_contains(&js, "for (let name of [\"log\"]) {");
+        assert_contains(&js, "__js_import_log: (...args) => get_wrapper()._import_log(...args),");
+        assert_contains(&js, "    _import_log(message_ptr, message_len) {");
+        assert_contains(&js, "this._imports.log(message);");
+    }
+
+    #[test]
+    fn import_clashing_with_method() {
+        let error = generate_error(
+            &Config::new(),
+            "js_fn! { fn _import_log(x: u32) => f; }
+             mod imports { js_import! { pub fn log(message: &str); } }",
+        );
+        assert!(error.contains("_import_log"), "{}", error);
+    }
+
+    #[test]
+    fn callbacks() {
+        let js = generate(
+            &Config::new(),
+            "js_fn! {
+                 fn sort_by(values: Vec<i32>, less: &dyn Fn(i32, i32) -> bool) -> Vec<i32> => f;
+             }",
+        );
+        assert_contains(&js, "if (typeof less != 'function') {");
+        assert_contains(&js, "var less_index = this._callbacks.length;");
+        assert_contains(&js, "this._funcs['sort_by'](values_ptr, values_len, less_index);");
+        assert_contains(
+            &js,
+            "} finally {\n            if (less_index !== undefined) {\n                \
+             this._callbacks.splice(less_index);",
+        );
+        assert_contains(&js, "__js_import__builtin_callback: (index, args_ptr, return_slot) =>");
+    }
+
+    #[test]
+    fn zero_copy_buffers() {
+        let source = "js_fn! {
+                          fn scale(values: &mut [f32], by: f32) => scale;
+                          fn make(len: u32) -> JsBuffer<u16> => make;
+                      }";
+        let error = generate_error(&Config::new(), source);
+        assert_eq!(
+            error,
+            "function `make` returns a `JsBuffer`, which needs zero-copy buffers to be enabled"
+        );
+
+        let mut config = Config::new();
+        config.with_zero_copy_buffers(true);
+        let js = generate(&config, source);
+        assert_contains(&js, " * @param {number[] | Float32Array | JsBuffer<Float32Array>} values");
+        assert_contains(&js, "var values_borrowed = values instanceof JsBuffer;");
+        assert_contains(
+            &js,
+            "new JsBuffer(this, Uint16Array, return_ptr, return_len, return_ptr, return_cap * 2);",
+        );
+        assert_contains(&js, "    allocBuffer(type, length) {");
+        assert_contains(&js, "class JsBuffer {");
+        assert_contains(&js, "exports.JsBuffer = JsBuffer;");
+    }
+
+    #[test]
+    fn panics_and_scratch_arena() {
+        let mut config = Config::new();
+        config
+            .with_panic_strategy(PanicStrategy::ThrowRustPanic)
+            .with_scratch_arena(true);
+        let js = generate(&config, "js_fn! { fn shout(text: &str) -> String => shout; }");
+        assert_contains(&js, "let scratch_mark = this._scratch_used;\n        try {");
+        assert_contains(&js, "var text_ptr = this._scratch_alloc(text_byte_len);");
+        assert_contains(&js, "} catch (e) {");
+        assert_contains(&js, "this._scratch_release(scratch_mark);");
+        assert_contains(&js, "return new RustPanic(message, location, error);");
+    }
+
+    #[test]
+    fn renamed_arguments() {
+        assert_eq!(
+            js_argument_names(&names(&[
+                "ptr",
+                "result",
+                "self_ptr",
+                "scratch_mark",
+                "get_loader",
+                "return_value",
+                "new",
+            ])),
+            names(&[
+                "_ptr",
+                "_result",
+                "_self_ptr",
+                "_scratch_mark",
+                "_get_loader",
+                "_return_value",
+                "new_",
+            ])
+        );
+        assert_eq!(js_argument_names(&names(&["result_len"])), names(&["_result_len"]));
+        // `a_len` would clash with the length generated for `a`.
+        assert_eq!(js_argument_names(&names(&["a", "a_len"])), names(&["a", "_a_len"]));
+        assert_eq!(js_argument_names(&names(&["x", "y"])), names(&["x", "y"]));
+    }
+
+    #[test]
+    fn renamed_arguments_are_used() {
+        let js = generate(
+            &Config::new(),
+            "js_fn! { fn clash(ptr: u32, result: &[u8], new: u32) => clash; }",
+        );
+        assert_contains(&js, "    clash(_ptr, _result, new_) {");
+        assert_contains(&js, "this._funcs['clash'](_ptr, _result_ptr, _result_len, new_);");
+        assert_contains(&js, "this._dealloc(_result_ptr, _result_byte_len);");
+    }
+}
@@ -1,9 +1,15 @@
-use wasm_wrapper_gen_shared::{JsFnInfo, SupportedArgumentType, SupportedRetType};
+use wasm_wrapper_gen_shared::{JsFnInfo, JsImportInfo, SupportedArgumentType, SupportedRetType};
 
 pub(crate) struct FuncStats<'a> {
     pub inner: &'a JsFnInfo,
+    /// Whether any JavaScript callbacks are passed.
+    pub uses_callbacks: bool,
     pub uses_memory_access: bool,
     pub uses_post_function_memory_access: bool,
+    /// Whether any arguments are copied into memory freed after the call.
+    pub uses_borrowed_memory: bool,
+    /// Whether any strings are passed, including error messages.
+    pub uses_strings: bool,
 }
 
 impl<'a> FuncStats<'a> {
@@ -11,34 +17,133 @@ impl<'a> FuncStats<'a> {
         let mut any_alloc = false;
         let mut post_func_mem_access = false;
         for arg in &stats.args_ty {
-            match *arg {
-                SupportedArgumentType::Integer(_) => {}
-                SupportedArgumentType::IntegerSliceRef(_)
-                | SupportedArgumentType::IntegerVec(_)
-                | SupportedArgumentType::OwnedString => {
-                    any_alloc = true;
-                }
-                SupportedArgumentType::IntegerSliceMutRef(_) => {
-                    any_alloc = true;
-                    post_func_mem_access = true;
-                }
-            }
-        }
-        match stats.ret_ty {
-            SupportedRetType::Unit | SupportedRetType::Integer(_) => {}
-            SupportedRetType::IntegerVec(_)
-            | SupportedRetType::OwnedString
-            | SupportedRetType::StringSlice => {
-                any_alloc = true;
-                post_func_mem_access = true;
-            }
+            let (alloc, post_func) = argument_memory_access(arg);
+            any_alloc |= alloc;
+            post_func_mem_access |= post_func;
         }
+        let (alloc, post_func) = return_memory_access(&stats.ret_ty);
+        any_alloc |= alloc;
+        post_func_mem_access |= post_func;
 
         FuncStats {
             inner: stats,
+            uses_callbacks: stats.args_ty.iter().any(is_callback),
             uses_memory_access: any_alloc,
             uses_post_function_memory_access: post_func_mem_access,
+            uses_borrowed_memory: stats.args_ty.iter().any(argument_uses_borrowed_memory),
+            uses_strings: stats.args_ty.iter().any(argument_uses_strings)
+                || return_uses_strings(&stats.ret_ty),
+        }
+    }
+}
+
+/// Finds whether calling an imported function needs memory access, to read
+/// its arguments or to allocate its result.
+pub(crate) fn import_uses_memory_access(info: &JsImportInfo) -> bool {
+    info.args_ty.iter().any(|arg| argument_memory_access(arg).0)
+        || return_memory_access(&info.ret_ty).0
+}
+
+/// Finds whether any strings are passed to or from an imported function.
+pub(crate) fn import_uses_strings(info: &JsImportInfo) -> bool {
+    info.args_ty.iter().any(argument_uses_strings) || return_uses_strings(&info.ret_ty)
+}
+
+/// Finds whether an argument needs memory access at all, and whether it needs
+/// memory access after the function has been called.
+fn argument_memory_access(arg: &SupportedArgumentType) -> (bool, bool) {
+    match *arg {
+        SupportedArgumentType::Integer(_) => (false, false),
+        SupportedArgumentType::IntegerSliceRef(_)
+        | SupportedArgumentType::IntegerVec(_)
+        | SupportedArgumentType::OwnedString
+        | SupportedArgumentType::StringSlice
+        | SupportedArgumentType::JsString
+        | SupportedArgumentType::Json => (true, false),
+        SupportedArgumentType::IntegerSliceMutRef(_) => (true, true),
+        // callbacks read their arguments from memory while the function runs.
+        SupportedArgumentType::Callback(..) => (true, false),
+        SupportedArgumentType::Optional(ref inner) => argument_memory_access(inner),
+    }
+}
+
+/// Finds whether a return type needs memory access at all, and whether it
+/// needs memory access after the function has been called.
+fn return_memory_access(ret: &SupportedRetType) -> (bool, bool) {
+    match *ret {
+        SupportedRetType::Unit | SupportedRetType::Integer(_) | SupportedRetType::OwnedSelf => {
+            (false, false)
+        }
+        SupportedRetType::IntegerVec(_)
+        | SupportedRetType::Buffer(_)
+        | SupportedRetType::OwnedString
+        | SupportedRetType::StringSlice
+        | SupportedRetType::Json
+        | SupportedRetType::Result(_)
+        | SupportedRetType::Tuple(..) => (true, true),
+        SupportedRetType::Optional(ref inner) => match **inner {
+            // present integers are returned boxed.
+            SupportedRetType::Integer(_) => (true, true),
+            ref inner => return_memory_access(inner),
+        },
+    }
+}
+
+/// Finds whether a `JsBuffer` is returned, possibly inside another type.
+pub(crate) fn returns_buffer(ret: &SupportedRetType) -> bool {
+    match *ret {
+        SupportedRetType::Buffer(_) => true,
+        SupportedRetType::Optional(ref inner) | SupportedRetType::Result(ref inner) => {
+            returns_buffer(inner)
+        }
+        SupportedRetType::Tuple(ref elements, _) => elements.iter().any(returns_buffer),
+        _ => false,
+    }
+}
+
+/// Finds whether an argument is copied into memory which JavaScript frees
+/// after the call, rather than rust taking ownership of it.
+pub(crate) fn argument_uses_borrowed_memory(arg: &SupportedArgumentType) -> bool {
+    match *arg {
+        SupportedArgumentType::IntegerSliceRef(_)
+        | SupportedArgumentType::IntegerSliceMutRef(_)
+        | SupportedArgumentType::OwnedString
+        | SupportedArgumentType::StringSlice
+        | SupportedArgumentType::JsString
+        | SupportedArgumentType::Json => true,
+        SupportedArgumentType::Optional(ref inner) => argument_uses_borrowed_memory(inner),
+        SupportedArgumentType::Integer(_)
+        | SupportedArgumentType::IntegerVec(_)
+        | SupportedArgumentType::Callback(..) => false,
+    }
+}
+
+fn is_callback(arg: &SupportedArgumentType) -> bool {
+    matches!(*arg, SupportedArgumentType::Callback(..))
+}
+
+fn argument_uses_strings(arg: &SupportedArgumentType) -> bool {
+    match *arg {
+        SupportedArgumentType::OwnedString
+        | SupportedArgumentType::StringSlice
+        | SupportedArgumentType::Json => true,
+        SupportedArgumentType::Optional(ref inner) => argument_uses_strings(inner),
+        SupportedArgumentType::Callback(ref args_ty, ref ret_ty) => {
+            args_ty.iter().any(argument_uses_strings) || return_uses_strings(ret_ty)
         }
+        _ => false,
+    }
+}
+
+fn return_uses_strings(ret: &SupportedRetType) -> bool {
+    match *ret {
+        SupportedRetType::OwnedString
+        | SupportedRetType::StringSlice
+        | SupportedRetType::Json
+        | SupportedRetType::Result(_) => true,
+        SupportedRetType::Optional(ref inner) => return_uses_strings(inner),
+        SupportedRetType::Tuple(ref elements, _) => elements.iter().any(return_uses_strings),
+        _ => false,
     }
 }
 
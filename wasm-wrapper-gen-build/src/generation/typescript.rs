@@ -0,0 +1,568 @@
+use std::fmt::Write;
+
+use failure::Error;
+
+use wasm_wrapper_gen_shared::{JsFnInfo, JsImportInfo, SupportedArgumentType, SupportedCopyTy,
+                              SupportedRetType};
+
+use style::{AccessStyle, Config, ModuleFormat, PanicStrategy};
+
+use super::indented_write::WriteExt;
+use super::{collect_self_types, is_static_in_type, javascript_typed_array_for_int,
+            js_argument_names, write_doc_lines, BUFFER_ELEMENT_TYPES};
+
+pub fn generate_typescript_declarations<'a, 'b, I>(
+    config: &Config,
+    iter: &'a I,
+    imports: &[JsImportInfo],
+) -> Result<String, Error>
+where
+    &'a I: IntoIterator<Item = &'b JsFnInfo> + 'a,
+{
+    let infos = iter.into_iter().collect::<Vec<_>>();
+    let self_types = collect_self_types(infos.iter().cloned());
+
+    // classes exported alongside the main class.
+    let mut other_classes = Vec::new();
+    if infos.iter().any(|info| matches!(info.ret_ty, SupportedRetType::Result(_))) {
+        other_classes.push(OtherClass::Error);
+    }
+    if config.panic_strategy == PanicStrategy::ThrowRustPanic {
+        other_classes.push(OtherClass::RustPanic);
+    }
+    if config.zero_copy_buffers {
+        other_classes.push(OtherClass::Buffer);
+    }
+    other_classes.extend(self_types.iter().map(|&self_ty| OtherClass::SelfType(self_ty)));
+
+    // other classes are properties of the main class unless using ES modules,
+    // so they're declared in a namespace merged with it.
+    let namespaced = config.module_format != ModuleFormat::EsModule;
+    let type_prefix = if namespaced {
+        format!("{}.", config.class_name)
+    } else {
+        String::new()
+    };
+    // the implementations of imported functions passed to the constructor.
+    let imports_interface = if namespaced {
+        "Imports".to_owned()
+    } else {
+        format!("{}Imports", config.class_name)
+    };
+    let imports_type = if imports.is_empty() {
+        None
+    } else {
+        Some(format!("{}{}", type_prefix, imports_interface))
+    };
+
+    let mut output_buffer = String::new();
+    {
+        let buf = &mut output_buffer;
+        let declare = if namespaced { "declare" } else { "export declare" };
+
+        writeln!(buf, "{} class {} {{", declare, config.class_name)?;
+        write_main_class_body(
+            config,
+            &mut buf.indented(config.indent),
+            &infos,
+            &self_types,
+            &type_prefix,
+            imports_type.as_deref(),
+        )?;
+        writeln!(buf, "}}")?;
+
+        if namespaced {
+            if !other_classes.is_empty() || !imports.is_empty() {
+                write!(buf, "\ndeclare namespace {} {{\n", config.class_name)?;
+                {
+                    let buf = &mut buf.indented(config.indent);
+                    for (i, class) in other_classes.iter().enumerate() {
+                        if i != 0 {
+                            writeln!(buf)?;
+                        }
+                        write_other_class(config, buf, "", class, &infos, &type_prefix)?;
+                    }
+                    if !imports.is_empty() {
+                        if !other_classes.is_empty() {
+                            writeln!(buf)?;
+                        }
+                        write_imports_interface(config, buf, "", &imports_interface, imports)?;
+                    }
+                }
+                writeln!(buf, "}}")?;
+            }
+            match config.module_format {
+                ModuleFormat::CommonJs => {
+                    write!(buf, "\nexport = {};\n", config.class_name)?;
+                }
+                ModuleFormat::Umd => {
+                    write!(
+                        buf,
+                        "\nexport = {0};\nexport as namespace {0};\n",
+                        config.class_name
+                    )?;
+                }
+                ModuleFormat::EsModule | ModuleFormat::Global => {}
+            }
+        } else {
+            writeln!(buf, "export default {};", config.class_name)?;
+            for class in &other_classes {
+                writeln!(buf)?;
+                write_other_class(config, buf, "export declare ", class, &infos, &type_prefix)?;
+            }
+            if !imports.is_empty() {
+                writeln!(buf)?;
+                write_imports_interface(config, buf, "export ", &imports_interface, imports)?;
+            }
+        }
+    }
+    Ok(output_buffer)
+}
+
+enum OtherClass<'a> {
+    Error,
+    RustPanic,
+    Buffer,
+    SelfType(&'a str),
+}
+
+fn write_main_class_body<T>(
+    config: &Config,
+    buf: &mut T,
+    infos: &[&JsFnInfo],
+    self_types: &[&str],
+    type_prefix: &str,
+    imports_type: Option<&str>,
+) -> Result<(), Error>
+where
+    T: Write,
+{
+    let imports_param = imports_param(imports_type);
+    if config.shared_loader {
+        return write_shared_loader_class_body(config, buf, infos, type_prefix, &imports_param);
+    }
+    writeln!(
+        buf,
+        "constructor(wasm_module: WebAssembly.Module | WebAssembly.Instance{});",
+        imports_param
+    )?;
+    if config.async_create {
+        writeln!(
+            buf,
+            "static create(source: WebAssembly.Module | BufferSource | Response | string | URL{}): \
+             Promise<{}>;",
+            imports_param,
+            config.class_name
+        )?;
+    }
+    if config.zero_copy_buffers {
+        write_alloc_buffer_signatures(buf, "", type_prefix)?;
+    }
+    // functions in impl blocks which don't take self.
+    for self_ty in self_types {
+        let mut static_infos = infos
+            .iter()
+            .filter(|info| is_static_in_type(info, self_ty))
+            .peekable();
+        if static_infos.peek().is_none() {
+            continue;
+        }
+        writeln!(buf, "readonly {}: {{", self_ty)?;
+        {
+            let buf = &mut buf.indented(config.indent);
+            for info in static_infos {
+                write_doc_comment(buf, &info.doc_lines)?;
+                // quoted so that `new` isn't a construct signature.
+                write!(buf, "'{}'", info.rust_name)?;
+                write_signature(config, buf, info, type_prefix)?;
+            }
+        }
+        writeln!(buf, "}};")?;
+    }
+    for info in infos.iter().filter(|info| info.self_ty.is_none()) {
+        write_doc_comment(buf, &info.doc_lines)?;
+        write!(buf, "{}", info.rust_name)?;
+        write_signature(config, buf, info, type_prefix)?;
+    }
+
+    Ok(())
+}
+
+/// Writes the main class when using a shared loader, which has only static
+/// methods.
+fn write_shared_loader_class_body<T>(
+    config: &Config,
+    buf: &mut T,
+    infos: &[&JsFnInfo],
+    type_prefix: &str,
+    imports_param: &str,
+) -> Result<(), Error>
+where
+    T: Write,
+{
+    writeln!(buf, "private constructor();")?;
+    writeln!(
+        buf,
+        "static load(wasm_module: WebAssembly.Module | WebAssembly.Instance{}): void;",
+        imports_param
+    )?;
+    if config.async_create {
+        writeln!(
+            buf,
+            "static loadAsync(\
+             source: WebAssembly.Module | BufferSource | Response | string | URL{}): \
+             Promise<void>;",
+            imports_param
+        )?;
+    }
+    if config.zero_copy_buffers {
+        write_alloc_buffer_signatures(buf, "static ", type_prefix)?;
+    }
+    for info in infos.iter().filter(|info| info.self_ty.is_none()) {
+        write_doc_comment(buf, &info.doc_lines)?;
+        write!(buf, "static {}", info.rust_name)?;
+        write_signature(config, buf, info, type_prefix)?;
+    }
+
+    Ok(())
+}
+
+/// Writes an overload of `allocBuffer` for each element type.
+fn write_alloc_buffer_signatures<T>(
+    buf: &mut T,
+    modifier: &str,
+    type_prefix: &str,
+) -> Result<(), Error>
+where
+    T: Write,
+{
+    for &ty in BUFFER_ELEMENT_TYPES {
+        writeln!(
+            buf,
+            "{}allocBuffer(type: \"{}\", length: number): {};",
+            modifier,
+            ty,
+            buffer_type(ty, type_prefix)
+        )?;
+    }
+
+    Ok(())
+}
+
+fn write_other_class<T>(
+    config: &Config,
+    buf: &mut T,
+    declare: &str,
+    class: &OtherClass,
+    infos: &[&JsFnInfo],
+    type_prefix: &str,
+) -> Result<(), Error>
+where
+    T: Write,
+{
+    match *class {
+        OtherClass::Error => {
+            write!(
+                buf,
+                "{}class {} extends Error {{\n}}\n",
+                declare,
+                config.error_class_name()
+            )?;
+        }
+        OtherClass::RustPanic => {
+            writeln!(buf, "{}class RustPanic extends Error {{", declare)?;
+            write!(
+                buf.indented(config.indent),
+                "location: string | null;\ntrap: WebAssembly.RuntimeError;\n"
+            )?;
+            writeln!(buf, "}}")?;
+        }
+        OtherClass::Buffer => {
+            writeln!(buf, "{}class JsBuffer<T> {{", declare)?;
+            write!(
+                buf.indented(config.indent),
+                "private constructor();\n\
+                 readonly length: number;\n\
+                 /** Views the buffer in place, until it's freed. */\n\
+                 readonly view: T;\n\
+                 free(): void;\n"
+            )?;
+            writeln!(buf, "}}")?;
+        }
+        OtherClass::SelfType(self_ty) => {
+            writeln!(buf, "{}class {} {{", declare, self_ty)?;
+            {
+                let buf = &mut buf.indented(config.indent);
+                // instances are only created by the wrapper.
+                writeln!(buf, "private constructor();")?;
+                if config.shared_loader {
+                    for info in infos.iter().filter(|info| is_static_in_type(info, self_ty)) {
+                        write_doc_comment(buf, &info.doc_lines)?;
+                        write!(buf, "static {}", info.rust_name)?;
+                        write_signature(config, buf, info, type_prefix)?;
+                    }
+                }
+                for info in infos {
+                    if info.self_arg.is_some() && info.self_ty.as_deref() == Some(self_ty) {
+                        write_doc_comment(buf, &info.doc_lines)?;
+                        write!(buf, "{}", info.rust_name)?;
+                        write_signature(config, buf, info, type_prefix)?;
+                    }
+                }
+                writeln!(buf, "free(): void;")?;
+            }
+            writeln!(buf, "}}")?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes the rust doc comment of a function, if it has one.
+fn write_doc_comment<T>(buf: &mut T, doc_lines: &[String]) -> Result<(), Error>
+where
+    T: Write,
+{
+    if doc_lines.is_empty() {
+        return Ok(());
+    }
+    writeln!(buf, "/**")?;
+    write_doc_lines(buf, doc_lines)?;
+    writeln!(buf, " */")?;
+
+    Ok(())
+}
+
+/// Writes the interface with the implementation of every imported function.
+fn write_imports_interface<T>(
+    config: &Config,
+    buf: &mut T,
+    export: &str,
+    name: &str,
+    imports: &[JsImportInfo],
+) -> Result<(), Error>
+where
+    T: Write,
+{
+    writeln!(buf, "{}interface {} {{", export, name)?;
+    {
+        let buf = &mut buf.indented(config.indent);
+        for info in imports {
+            write_doc_comment(buf, &info.doc_lines)?;
+            // arguments are converted like return values, and the other way
+            // around.
+            let arg_names = js_argument_names(&info.args_names);
+            let params = import_params(config, &arg_names, &info.args_ty);
+            let ret = import_return_type(config, &info.ret_ty);
+            writeln!(buf, "{}({}): {};", info.rust_name, params, ret)?;
+        }
+    }
+    writeln!(buf, "}}")?;
+
+    Ok(())
+}
+
+/// The parameter taking imported functions, after the other parameters of
+/// the constructor and its alternatives.
+fn imports_param(imports_type: Option<&str>) -> String {
+    match imports_type {
+        Some(imports_type) => format!(", imports: {}", imports_type),
+        None => String::new(),
+    }
+}
+
+/// Writes the parameters and return type of a method, after its name.
+fn write_signature<T>(
+    config: &Config,
+    buf: &mut T,
+    info: &JsFnInfo,
+    type_prefix: &str,
+) -> Result<(), Error>
+where
+    T: Write,
+{
+    let required_args = required_argument_count(info);
+
+    write!(buf, "(")?;
+    for (i, (name, ty)) in js_argument_names(&info.args_names).iter().zip(&info.args_ty).enumerate() {
+        if i != 0 {
+            write!(buf, ", ")?;
+        }
+        let optional_marker = if i < required_args { "" } else { "?" };
+        write!(
+            buf,
+            "{}{}: {}",
+            name,
+            optional_marker,
+            argument_type(config, ty, type_prefix)
+        )?;
+    }
+    writeln!(
+        buf,
+        "): {};",
+        return_type(config, &info.ret_ty, info.self_ty.as_deref(), type_prefix)
+    )?;
+
+    Ok(())
+}
+
+/// Number of arguments which can't be left out, since trailing optional
+/// arguments can be.
+pub(super) fn required_argument_count(info: &JsFnInfo) -> usize {
+    info.args_ty
+        .iter()
+        .rposition(|ty| !matches!(*ty, SupportedArgumentType::Optional(_)))
+        .map_or(0, |i| i + 1)
+}
+
+fn integer_type(ty: SupportedCopyTy, is_argument: bool) -> &'static str {
+    match ty {
+        SupportedCopyTy::Bool => "boolean",
+        // arguments may also be given as safe integer numbers.
+        _ if ty.is_64_bit_integer() && is_argument => "bigint | number",
+        _ if ty.is_64_bit_integer() => "bigint",
+        _ => "number",
+    }
+}
+
+fn array_type(config: &Config, ty: SupportedCopyTy, is_argument: bool) -> String {
+    let element = match integer_type(ty, is_argument) {
+        element if element.contains('|') => format!("({})[]", element),
+        element => format!("{}[]", element),
+    };
+    match ty {
+        SupportedCopyTy::Bool => element,
+        // typed arrays are accepted in either style, but only returned when
+        // using them.
+        _ if is_argument => format!("{} | {}", element, javascript_typed_array_for_int(ty)),
+        _ if config.access_style == AccessStyle::TypedArrays => {
+            javascript_typed_array_for_int(ty).to_owned()
+        }
+        _ => element,
+    }
+}
+
+pub(super) fn argument_type(
+    config: &Config,
+    ty: &SupportedArgumentType,
+    type_prefix: &str,
+) -> String {
+    match *ty {
+        SupportedArgumentType::Integer(int_ty) => integer_type(int_ty, true).to_owned(),
+        // slices may also be borrowed from buffers.
+        SupportedArgumentType::IntegerSliceRef(int_ty)
+        | SupportedArgumentType::IntegerSliceMutRef(int_ty)
+            if config.zero_copy_buffers && int_ty != SupportedCopyTy::Bool =>
+        {
+            format!(
+                "{} | {}",
+                array_type(config, int_ty, true),
+                buffer_type(int_ty, type_prefix)
+            )
+        }
+        SupportedArgumentType::IntegerSliceRef(int_ty)
+        | SupportedArgumentType::IntegerSliceMutRef(int_ty)
+        | SupportedArgumentType::IntegerVec(int_ty) => array_type(config, int_ty, true),
+        SupportedArgumentType::OwnedString
+        | SupportedArgumentType::StringSlice
+        | SupportedArgumentType::JsString => "string".to_owned(),
+        // anything serde can deserialize.
+        SupportedArgumentType::Json => "any".to_owned(),
+        SupportedArgumentType::Optional(ref inner) => {
+            format!(
+                "{} | null | undefined",
+                argument_type(config, inner, type_prefix)
+            )
+        }
+        SupportedArgumentType::Callback(ref args_ty, ref ret_ty) => {
+            let names = (0..args_ty.len())
+                .map(|index| format!("arg{}", index))
+                .collect::<Vec<_>>();
+            format!(
+                "({}) => {}",
+                import_params(config, &names, args_ty),
+                import_return_type(config, ret_ty)
+            )
+        }
+    }
+}
+
+/// Parameters of a JavaScript function called from rust, for an imported
+/// function or a callback.
+fn import_params(config: &Config, names: &[String], args_ty: &[SupportedArgumentType]) -> String {
+    names
+        .iter()
+        .zip(args_ty)
+        .map(|(name, ty)| format!("{}: {}", name, import_argument_type(config, ty)))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Type returned by a JavaScript function called from rust, which is
+/// converted like an argument.
+fn import_return_type(config: &Config, ty: &SupportedRetType) -> String {
+    match *ty {
+        SupportedRetType::Integer(int_ty) => integer_type(int_ty, true).to_owned(),
+        ref ret_ty => return_type(config, ret_ty, None, ""),
+    }
+}
+
+/// Type of an argument passed to an imported function.
+fn import_argument_type(config: &Config, ty: &SupportedArgumentType) -> String {
+    match *ty {
+        SupportedArgumentType::Integer(int_ty) => integer_type(int_ty, false).to_owned(),
+        SupportedArgumentType::IntegerSliceRef(int_ty)
+        | SupportedArgumentType::IntegerSliceMutRef(int_ty)
+        | SupportedArgumentType::IntegerVec(int_ty) => array_type(config, int_ty, false),
+        SupportedArgumentType::Optional(ref inner) => {
+            format!("{} | null", import_argument_type(config, inner))
+        }
+        ref other => argument_type(config, other, ""),
+    }
+}
+
+fn buffer_type(ty: SupportedCopyTy, type_prefix: &str) -> String {
+    format!("{}JsBuffer<{}>", type_prefix, javascript_typed_array_for_int(ty))
+}
+
+pub(super) fn return_type(
+    config: &Config,
+    ty: &SupportedRetType,
+    self_ty: Option<&str>,
+    type_prefix: &str,
+) -> String {
+    match *ty {
+        SupportedRetType::Unit => "void".to_owned(),
+        SupportedRetType::Integer(int_ty) => integer_type(int_ty, false).to_owned(),
+        SupportedRetType::IntegerVec(int_ty) => array_type(config, int_ty, false),
+        SupportedRetType::Buffer(int_ty) => buffer_type(int_ty, type_prefix),
+        SupportedRetType::OwnedString | SupportedRetType::StringSlice => "string".to_owned(),
+        SupportedRetType::Json => "any".to_owned(),
+        SupportedRetType::OwnedSelf => format!(
+            "{}{}",
+            type_prefix,
+            self_ty.expect("expected Self return type only inside impl block")
+        ),
+        SupportedRetType::Optional(ref inner) => {
+            format!("{} | null", return_type(config, inner, self_ty, type_prefix))
+        }
+        // errors are thrown.
+        SupportedRetType::Result(ref inner) => return_type(config, inner, self_ty, type_prefix),
+        SupportedRetType::Tuple(ref elements, ref names) => {
+            let elements = elements
+                .iter()
+                .map(|element| return_type(config, element, self_ty, type_prefix));
+            match *names {
+                Some(ref names) => {
+                    let fields = names
+                        .iter()
+                        .zip(elements)
+                        .map(|(name, element)| format!("{}: {}", name, element))
+                        .collect::<Vec<_>>();
+                    format!("{{ {} }}", fields.join("; "))
+                }
+                None => format!("[{}]", elements.collect::<Vec<_>>().join(", ")),
+            }
+        }
+    }
+}
@@ -1,21 +1,34 @@
 #[macro_use]
 extern crate failure;
+#[macro_use]
+extern crate serde_json;
 extern crate syn;
 extern crate wasm_wrapper_gen_shared;
+extern crate wasmparser;
 
+mod abi;
 mod source_searching;
 mod generation;
+mod manifest;
 mod style;
+mod verification;
+#[cfg(test)]
+mod test_util;
 
-use std::path::Path;
-use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::io::Write;
 use std::fs;
 
 use failure::Error;
 
-use wasm_wrapper_gen_shared::JsFnInfo;
+use wasm_wrapper_gen_shared::{JsFnInfo, JsImportInfo};
+
+use source_searching::ScannedCrate;
 
-pub use style::{AccessStyle, Config};
+pub use style::{AccessStyle, Config, ModuleFormat, PanicStrategy, StringEncoding,
+                StringValidation};
+pub use abi::WasmType;
+pub use verification::{ExportProblem, VerificationError, WasmSignature};
 
 impl<'a> Config<'a> {
     pub fn translate<P, U>(&self, input_file: P, output_file: U) -> Result<(), Error>
@@ -25,6 +38,33 @@ impl<'a> Config<'a> {
     {
         translate_files(input_file, output_file, self)
     }
+
+    /// Checks that a compiled `.wasm` module exports every function the
+    /// wrapper generated for `input_file` uses, with the expected signatures,
+    /// and that it has no exports left over from removed functions.
+    ///
+    /// Fails with a `VerificationError` listing every problem found.
+    pub fn verify<P, U>(&self, input_file: P, wasm_file: U) -> Result<(), Error>
+    where
+        P: AsRef<Path>,
+        U: AsRef<Path>,
+    {
+        let scanned = source_searching::walk_crate_for_js_fns(input_file.as_ref())?;
+        let js_fn_infos = translate_crate(&scanned)?;
+        let js_import_infos = translate_imports(&scanned)?;
+
+        let wasm_file = wasm_file.as_ref();
+        let wasm = fs::read(wasm_file)
+            .map_err(|e| format_err!("failed to read {}: {}", wasm_file.display(), e))?;
+
+        let problems = verification::verify_exports(self, &js_fn_infos, &js_import_infos, &wasm)
+            .map_err(|e| format_err!("failed to parse {}: {}", wasm_file.display(), e))?;
+        if !problems.is_empty() {
+            return Err(VerificationError { problems }.into());
+        }
+
+        Ok(())
+    }
 }
 
 
@@ -34,17 +74,47 @@ where
     P: AsRef<Path>,
     U: AsRef<Path>,
 {
-    let contents = {
-        let mut handle = fs::File::open(input_lib)?;
+    let scanned = source_searching::walk_crate_for_js_fns(input_lib.as_ref())?;
 
-        let mut buffer = String::new();
+    if config.rerun_if_changed {
+        for file in &scanned.files {
+            println!("cargo:rerun-if-changed={}", file.display());
+        }
+    }
 
-        handle.read_to_string(&mut buffer)?;
+    let js_fn_infos = translate_crate(&scanned)?;
+    let js_import_infos = translate_imports(&scanned)?;
 
-        buffer
-    };
+    let output = generation::generate_javascript(config, &js_fn_infos, &js_import_infos)?;
+    write_output(&output_file, &output)?;
 
-    let output = translate_source(&contents, config)?;
+    if config.typescript_declarations {
+        let declarations = generation::generate_typescript_declarations(
+            config,
+            &js_fn_infos,
+            &js_import_infos,
+        )?;
+        write_output(declarations_path(output_file.as_ref()), &declarations)?;
+    }
+
+    if let Some(ref manifest_file) = config.abi_manifest {
+        let manifest = manifest::generate_abi_manifest(config, &js_fn_infos, &js_import_infos)?;
+        write_output(manifest_file, &manifest)?;
+    }
+
+    Ok(())
+}
+
+/// Writes the output file, unless it already has exactly the same contents.
+///
+/// This keeps its modification time from changing, so that tools watching
+/// the output aren't triggered by every build.
+fn write_output<P: AsRef<Path>>(output_file: P, output: &str) -> Result<(), Error> {
+    if let Ok(existing) = fs::read(output_file.as_ref()) {
+        if existing == output.as_bytes() {
+            return Ok(());
+        }
+    }
 
     let mut handle = fs::OpenOptions::new()
         .create(true)
@@ -57,13 +127,225 @@ where
     Ok(())
 }
 
-fn translate_source(source: &str, config: &Config) -> Result<String, Error> {
-    let func_definition_items = source_searching::walk_crate_for_js_fns(source)?;
+/// Finds the path TypeScript looks for declarations of the given JavaScript
+/// file at: `wrapper.js` -> `wrapper.d.ts`, `wrapper.mjs` -> `wrapper.d.mts`.
+fn declarations_path(output_file: &Path) -> PathBuf {
+    let extension = match output_file.extension().and_then(|ext| ext.to_str()) {
+        Some("mjs") => "d.mts",
+        Some("cjs") => "d.cts",
+        _ => "d.ts",
+    };
+    output_file.with_extension(extension)
+}
+
+fn translate_crate(scanned: &ScannedCrate) -> Result<Vec<JsFnInfo>, Error> {
+    // flat_map doesn't work well with Result<Vec<T>, E>.
+    let mut js_fn_infos = Vec::new();
+    // file each function came from, for reporting duplicates.
+    let mut js_fn_files = Vec::<&Path>::new();
+    // each `impl` block type, with the file and index of the `js_fn!`
+    // invocation its blocks are in. every invocation defines a drop function
+    // for the types it has blocks for, so they can't be split between them.
+    let mut self_ty_invocations = Vec::<(String, &Path, usize)>::new();
+    let mut invocation_index = 0;
+
+    for found in &scanned.found {
+        for items in &found.invocations {
+            invocation_index += 1;
+            for item in items {
+                let infos = JsFnInfo::from_item(item).map_err(|e| {
+                    format_err!("invalid function in {}: {}", found.file.display(), e)
+                })?;
+                for info in infos {
+                    let existing = js_fn_infos.iter().position(|other: &JsFnInfo| {
+                        other.qualified_name() == info.qualified_name()
+                    });
+                    if let Some(index) = existing {
+                        bail!(
+                            "function `{}` is defined both in {} and in {}",
+                            info.qualified_name(),
+                            js_fn_files[index].display(),
+                            found.file.display()
+                        );
+                    }
+                    if let Some(ref self_ty) = info.self_ty {
+                        match self_ty_invocations.iter().find(|entry| entry.0 == *self_ty) {
+                            Some(&(_, file, index)) if index != invocation_index => {
+                                let files = if file == found.file {
+                                    format!("in {}", file.display())
+                                } else {
+                                    format!("in {} and in {}", file.display(), found.file.display())
+                                };
+                                bail!(
+                                    "impl blocks for `{}` are in separate js_fn! invocations {}; \
+                                     they must all be in the same invocation",
+                                    self_ty,
+                                    files
+                                );
+                            }
+                            Some(_) => {}
+                            None => self_ty_invocations.push((
+                                self_ty.clone(),
+                                &found.file,
+                                invocation_index,
+                            )),
+                        }
+                    }
+                    js_fn_infos.push(info);
+                    js_fn_files.push(&found.file);
+                }
+            }
+        }
+    }
+
+    Ok(js_fn_infos)
+}
+
+fn translate_imports(scanned: &ScannedCrate) -> Result<Vec<JsImportInfo>, Error> {
+    let mut js_import_infos = Vec::new();
+    // file each import came from, for reporting duplicates.
+    let mut js_import_files = Vec::<&Path>::new();
+
+    for found in &scanned.found {
+        for item in &found.imports {
+            let info = JsImportInfo::from_item(item).map_err(|e| {
+                format_err!("invalid imported function in {}: {}", found.file.display(), e)
+            })?;
+            let existing = js_import_infos
+                .iter()
+                .position(|other: &JsImportInfo| other.rust_name == info.rust_name);
+            if let Some(index) = existing {
+                bail!(
+                    "imported function `{}` is declared both in {} and in {}",
+                    info.rust_name,
+                    js_import_files[index].display(),
+                    found.file.display()
+                );
+            }
+            js_import_infos.push(info);
+            js_import_files.push(&found.file);
+        }
+    }
+
+    Ok(js_import_infos)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
 
-    let js_fn_infos = func_definition_items
-        .into_iter()
-        .map(|item| JsFnInfo::try_from(&item))
-        .collect::<Result<Vec<_>, _>>()?;
+    use test_util::{translate_source, TempDir};
+
+    use super::*;
+
+    fn translate_error(source: &str) -> String {
+        translate_source(source)
+            .err()
+            .expect("expected translation to fail")
+            .to_string()
+    }
+
+    #[test]
+    fn translates_functions_and_imports() {
+        let (infos, imports) = translate_source(
+            "pub struct Counter;
+             js_fn! {
+                 fn add(a: u32, b: u32) -> u32 => add;
+                 impl Counter {
+                     fn get(&self) -> u32 => Counter::get;
+                 }
+             }
+             mod imports { js_import! { pub fn log(message: &str); } }",
+        ).unwrap();
+        let names = infos.iter().map(JsFnInfo::qualified_name).collect::<Vec<_>>();
+        assert_eq!(names, ["add", "Counter__get"]);
+        assert_eq!(infos[1].self_ty, Some("Counter".to_owned()));
+        assert_eq!(imports.len(), 1);
+        assert_eq!(imports[0].rust_name, "log");
+        assert_eq!(imports[0].args_names, ["message"]);
+    }
+
+    #[test]
+    fn duplicate_functions() {
+        let error = translate_error(
+            "js_fn! { fn add(a: u32) -> u32 => add; }
+             js_fn! { fn add(b: u32) -> u32 => other_add; }",
+        );
+        assert!(error.starts_with("function `add` is defined both in "), "{}", error);
+    }
 
-    Ok(generation::generate_javascript(config, &js_fn_infos)?)
+    #[test]
+    fn duplicate_imports() {
+        let error = translate_error(
+            "mod a { js_import! { pub fn log(message: &str); } }
+             mod b { js_import! { pub fn log(message: String); } }",
+        );
+        assert!(
+            error.starts_with("imported function `log` is declared both in "),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn impl_blocks_in_one_invocation() {
+        translate_source(
+            "pub struct Counter;
+             js_fn! {
+                 impl Counter { fn get(&self) -> u32 => Counter::get; }
+                 impl Counter { fn set(&mut self, to: u32) => Counter::set; }
+             }",
+        ).unwrap();
+    }
+
+    #[test]
+    fn impl_blocks_split_between_invocations() {
+        let error = translate_error(
+            "pub struct Counter;
+             js_fn! { impl Counter { fn get(&self) -> u32 => Counter::get; } }
+             js_fn! { impl Counter { fn set(&mut self, to: u32) => Counter::set; } }",
+        );
+        assert!(
+            error.starts_with("impl blocks for `Counter` are in separate js_fn! invocations in "),
+            "{}",
+            error
+        );
+        assert!(error.ends_with("; they must all be in the same invocation"), "{}", error);
+    }
+
+    #[test]
+    fn invalid_function() {
+        let error = translate_error("js_fn! { fn f(x: &mut str) => f; }");
+        assert!(error.starts_with("invalid function in "), "{}", error);
+    }
+
+    #[test]
+    fn declarations_paths() {
+        assert_eq!(declarations_path(Path::new("out/wrapper.js")), Path::new("out/wrapper.d.ts"));
+        assert_eq!(declarations_path(Path::new("wrapper.mjs")), Path::new("wrapper.d.mts"));
+        assert_eq!(declarations_path(Path::new("wrapper.cjs")), Path::new("wrapper.d.cts"));
+    }
+
+    #[test]
+    fn translate_writes_output_files() {
+        let dir = TempDir::new("translate-files");
+        let lib = dir.write("lib.rs", "js_fn! { fn add(a: u32, b: u32) -> u32 => add; }");
+        let output = dir.path().join("wrapper.js");
+        let manifest = dir.path().join("abi.json");
+
+        let mut config = Config::new();
+        config
+            .with_rerun_if_changed(false)
+            .with_typescript_declarations(true)
+            .with_abi_manifest(&manifest);
+        config.translate(&lib, &output).unwrap();
+
+        let js = fs::read_to_string(&output).unwrap();
+        assert!(js.contains("add(a, b) {"), "{}", js);
+        let declarations = fs::read_to_string(dir.path().join("wrapper.d.ts")).unwrap();
+        assert!(declarations.contains("add(a: number, b: number): number;"), "{}", declarations);
+        let manifest: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&manifest).unwrap()).unwrap();
+        assert!(manifest.is_object());
+    }
 }
@@ -0,0 +1,462 @@
+//! Command line interface for generating JavaScript wrappers outside of a
+//! build script.
+extern crate failure;
+extern crate getopts;
+extern crate wasm_wrapper_gen_build;
+
+use std::env;
+use std::process;
+
+use failure::Error;
+use getopts::{Matches, Options};
+
+use wasm_wrapper_gen_build::{AccessStyle, Config, ModuleFormat, PanicStrategy, StringEncoding,
+                             StringValidation};
+
+/// Exit code for errors generating the wrapper.
+const EXIT_FAILURE: i32 = 1;
+/// Exit code for invalid command line arguments.
+const EXIT_USAGE: i32 = 2;
+
+fn main() {
+    let args = env::args().collect::<Vec<_>>();
+    process::exit(run(&args));
+}
+
+/// What the command line asks for.
+enum Command {
+    Help,
+    Version,
+    Generate {
+        config: Config<'static>,
+        input: String,
+        output: String,
+        verify: Option<String>,
+    },
+}
+
+/// Invalid command line arguments, exiting with `EXIT_USAGE`.
+#[derive(Debug)]
+struct Usage(String);
+
+/// Runs the program with the given arguments, returning the exit code.
+fn run(args: &[String]) -> i32 {
+    let program = args.first().map_or("wasm-wrapper-gen", |s| s.as_str());
+
+    let command = match parse_args(args.get(1..).unwrap_or(&[])) {
+        Ok(command) => command,
+        Err(Usage(message)) => {
+            eprintln!("error: {}", message);
+            eprintln!("try '{} --help' for more information", program);
+            return EXIT_USAGE;
+        }
+    };
+
+    match command {
+        Command::Help => {
+            let brief = format!("Usage: {} [options] INPUT OUTPUT", program);
+            print!("{}", options().usage(&brief));
+        }
+        Command::Version => {
+            println!("wasm-wrapper-gen {}", env!("CARGO_PKG_VERSION"));
+        }
+        Command::Generate {
+            config,
+            input,
+            output,
+            verify,
+        } => {
+            if let Err(e) = config.translate(&input, output) {
+                print_error(&e);
+                return EXIT_FAILURE;
+            }
+            if let Some(wasm_file) = verify {
+                if let Err(e) = config.verify(&input, wasm_file) {
+                    print_error(&e);
+                    return EXIT_FAILURE;
+                }
+            }
+        }
+    }
+    0
+}
+
+/// Parses the arguments following the program name.
+fn parse_args(args: &[String]) -> Result<Command, Usage> {
+    let matches = options().parse(args).map_err(|e| Usage(e.to_string()))?;
+
+    if matches.opt_present("help") {
+        return Ok(Command::Help);
+    }
+    if matches.opt_present("version") {
+        return Ok(Command::Version);
+    }
+
+    if matches.free.len() != 2 {
+        return Err(Usage("expected exactly two arguments: INPUT and OUTPUT".to_owned()));
+    }
+
+    Ok(Command::Generate {
+        config: config_from_matches(&matches)?,
+        input: matches.free[0].clone(),
+        output: matches.free[1].clone(),
+        verify: matches.opt_str("verify"),
+    })
+}
+
+fn options() -> Options {
+    let mut opts = Options::new();
+    opts.optopt(
+        "c",
+        "class-name",
+        "name of the generated class (default WasmWrapper)",
+        "NAME",
+    );
+    opts.optopt("", "indent", "number of spaces to indent with (default 4)", "N");
+    opts.optopt(
+        "",
+        "access-style",
+        "how to access arrays in memory: dataview (default) or typed-arrays",
+        "STYLE",
+    );
+    opts.optopt(
+        "f",
+        "module-format",
+        "how to export the classes: commonjs (default), es, umd or global",
+        "FORMAT",
+    );
+    opts.optopt(
+        "",
+        "string-encoding",
+        "how strings are passed: utf16 (default) or utf8",
+        "ENCODING",
+    );
+    opts.optopt(
+        "",
+        "string-validation",
+        "what to do with invalid UTF-16 string arguments: lossy (default) or strict",
+        "POLICY",
+    );
+    opts.optopt(
+        "",
+        "error-class-name",
+        "name of the error class thrown for `Err` results (default CLASSError)",
+        "NAME",
+    );
+    opts.optopt(
+        "",
+        "panic-strategy",
+        "what to do when rust panics: trap (default) or throw",
+        "STRATEGY",
+    );
+    opts.optflag("", "async-create", "generate a static async create(source) function");
+    opts.optflag(
+        "",
+        "shared-loader",
+        "share one module-level instance between classes with static methods",
+    );
+    opts.optflag(
+        "",
+        "zero-copy-buffers",
+        "generate JsBuffer views of rust memory, passed and returned without copying",
+    );
+    opts.optflag(
+        "",
+        "scratch-arena",
+        "copy borrowed arguments into a reused scratch region (needs the scratch-arena feature)",
+    );
+    opts.optflag(
+        "",
+        "typescript",
+        "also write TypeScript declarations next to OUTPUT",
+    );
+    opts.optopt(
+        "",
+        "abi-manifest",
+        "also write a JSON description of the exported functions to FILE",
+        "FILE",
+    );
+    opts.optopt(
+        "",
+        "verify",
+        "check that the compiled module's exports match the wrapper",
+        "WASM",
+    );
+    opts.optflag("h", "help", "print this help and exit");
+    opts.optflag("V", "version", "print the version and exit");
+    opts
+}
+
+fn config_from_matches(matches: &Matches) -> Result<Config<'static>, Usage> {
+    let mut config = Config::new();
+    // there's no build script for cargo to rerun.
+    config.with_rerun_if_changed(false);
+
+    if let Some(name) = matches.opt_str("class-name") {
+        config.with_class_name(name);
+    }
+    if let Some(indent) = matches.opt_str("indent") {
+        let indent = indent
+            .parse()
+            .map_err(|_| invalid_value("indent", &indent))?;
+        config.with_indent(indent);
+    }
+    if let Some(style) = matches.opt_str("access-style") {
+        config.with_array_access_style(match &*style {
+            "dataview" => AccessStyle::DataView,
+            "typed-arrays" => AccessStyle::TypedArrays,
+            _ => return Err(invalid_value("access-style", &style)),
+        });
+    }
+    if let Some(format) = matches.opt_str("module-format") {
+        config.with_module_format(match &*format {
+            "commonjs" => ModuleFormat::CommonJs,
+            "es" => ModuleFormat::EsModule,
+            "umd" => ModuleFormat::Umd,
+            "global" => ModuleFormat::Global,
+            _ => return Err(invalid_value("module-format", &format)),
+        });
+    }
+    if let Some(encoding) = matches.opt_str("string-encoding") {
+        config.with_string_encoding(match &*encoding {
+            "utf16" => StringEncoding::Utf16,
+            "utf8" => StringEncoding::Utf8,
+            _ => return Err(invalid_value("string-encoding", &encoding)),
+        });
+    }
+    if let Some(validation) = matches.opt_str("string-validation") {
+        config.with_string_validation(match &*validation {
+            "lossy" => StringValidation::Lossy,
+            "strict" => StringValidation::Strict,
+            _ => return Err(invalid_value("string-validation", &validation)),
+        });
+    }
+    if let Some(name) = matches.opt_str("error-class-name") {
+        config.with_error_class_name(name);
+    }
+    if let Some(strategy) = matches.opt_str("panic-strategy") {
+        config.with_panic_strategy(match &*strategy {
+            "trap" => PanicStrategy::Trap,
+            "throw" => PanicStrategy::ThrowRustPanic,
+            _ => return Err(invalid_value("panic-strategy", &strategy)),
+        });
+    }
+    config.with_async_create(matches.opt_present("async-create"));
+    config.with_shared_loader(matches.opt_present("shared-loader"));
+    config.with_zero_copy_buffers(matches.opt_present("zero-copy-buffers"));
+    config.with_scratch_arena(matches.opt_present("scratch-arena"));
+    config.with_typescript_declarations(matches.opt_present("typescript"));
+    if let Some(manifest_file) = matches.opt_str("abi-manifest") {
+        config.with_abi_manifest(manifest_file);
+    }
+
+    Ok(config)
+}
+
+fn invalid_value(option: &str, value: &str) -> Usage {
+    Usage(format!("invalid value for --{}: '{}'", option, value))
+}
+
+fn print_error(error: &Error) {
+    eprintln!("error: {}", error);
+    for cause in error.iter_causes() {
+        eprintln!("caused by: {}", cause);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|&s| s.to_owned()).collect()
+    }
+
+    fn parse(arguments: &[&str]) -> Result<Command, Usage> {
+        parse_args(&args(arguments))
+    }
+
+    /// `Config` has no `PartialEq`, but its `Debug` output shows every field.
+    fn assert_config_eq(actual: &Config, expected: &Config) {
+        assert_eq!(format!("{:?}", actual), format!("{:?}", expected));
+    }
+
+    fn generated_config(arguments: &[&str]) -> Config<'static> {
+        match parse(arguments) {
+            Ok(Command::Generate { config, .. }) => config,
+            Ok(_) => panic!("expected a generate command for {:?}", arguments),
+            Err(Usage(message)) => panic!("unexpected usage error: {}", message),
+        }
+    }
+
+    fn usage_error(arguments: &[&str]) -> String {
+        match parse(arguments) {
+            Err(Usage(message)) => message,
+            Ok(_) => panic!("expected a usage error for {:?}", arguments),
+        }
+    }
+
+    #[test]
+    fn defaults() {
+        let mut expected = Config::new();
+        expected.with_rerun_if_changed(false);
+
+        match parse(&["src/lib.rs", "out.js"]) {
+            Ok(Command::Generate {
+                config,
+                input,
+                output,
+                verify,
+            }) => {
+                assert_config_eq(&config, &expected);
+                assert_eq!(input, "src/lib.rs");
+                assert_eq!(output, "out.js");
+                assert_eq!(verify, None);
+            }
+            _ => panic!("expected a generate command"),
+        }
+    }
+
+    #[test]
+    fn every_option() {
+        let config = generated_config(&[
+            "--class-name",
+            "Foo",
+            "--indent",
+            "2",
+            "--access-style",
+            "typed-arrays",
+            "--module-format",
+            "es",
+            "--string-encoding",
+            "utf8",
+            "--string-validation",
+            "strict",
+            "--error-class-name",
+            "Oops",
+            "--panic-strategy",
+            "throw",
+            "--async-create",
+            "--shared-loader",
+            "--zero-copy-buffers",
+            "--scratch-arena",
+            "--typescript",
+            "--abi-manifest",
+            "abi.json",
+            "in.rs",
+            "out.js",
+        ]);
+
+        let mut expected = Config::new();
+        expected
+            .with_rerun_if_changed(false)
+            .with_class_name("Foo")
+            .with_indent(2)
+            .with_array_access_style(AccessStyle::TypedArrays)
+            .with_module_format(ModuleFormat::EsModule)
+            .with_string_encoding(StringEncoding::Utf8)
+            .with_string_validation(StringValidation::Strict)
+            .with_error_class_name("Oops")
+            .with_panic_strategy(PanicStrategy::ThrowRustPanic)
+            .with_async_create(true)
+            .with_shared_loader(true)
+            .with_zero_copy_buffers(true)
+            .with_scratch_arena(true)
+            .with_typescript_declarations(true)
+            .with_abi_manifest("abi.json");
+        assert_config_eq(&config, &expected);
+    }
+
+    #[test]
+    fn short_options() {
+        let mut expected = Config::new();
+        expected
+            .with_rerun_if_changed(false)
+            .with_class_name("Foo")
+            .with_module_format(ModuleFormat::Umd);
+        assert_config_eq(&generated_config(&["-c", "Foo", "-f", "umd", "a", "b"]), &expected);
+    }
+
+    #[test]
+    fn verify() {
+        match parse(&["--verify", "module.wasm", "a", "b"]) {
+            Ok(Command::Generate { verify, .. }) => {
+                assert_eq!(verify, Some("module.wasm".to_owned()))
+            }
+            _ => panic!("expected a generate command"),
+        }
+    }
+
+    #[test]
+    fn help_and_version() {
+        match parse(&["--help"]) {
+            Ok(Command::Help) => {}
+            _ => panic!("expected help"),
+        }
+        match parse(&["-V", "ignored"]) {
+            Ok(Command::Version) => {}
+            _ => panic!("expected version"),
+        }
+    }
+
+    #[test]
+    fn invalid_values() {
+        for &(option, value) in &[
+            ("--indent", "four"),
+            ("--access-style", "arrays"),
+            ("--module-format", "amd"),
+            ("--string-encoding", "utf32"),
+            ("--string-validation", "loose"),
+            ("--panic-strategy", "abort"),
+        ] {
+            assert_eq!(
+                usage_error(&[option, value, "a", "b"]),
+                format!("invalid value for {}: '{}'", option, value)
+            );
+        }
+    }
+
+    #[test]
+    fn wrong_argument_count() {
+        for arguments in &[&[][..], &["a"], &["a", "b", "c"]] {
+            assert_eq!(
+                usage_error(arguments),
+                "expected exactly two arguments: INPUT and OUTPUT"
+            );
+        }
+    }
+
+    #[test]
+    fn unknown_option() {
+        usage_error(&["--no-such-option", "a", "b"]);
+        usage_error(&["--class-name"]);
+    }
+
+    #[test]
+    fn exit_codes() {
+        assert_eq!(run(&args(&["wasm-wrapper-gen", "--version"])), 0);
+        assert_eq!(run(&args(&["wasm-wrapper-gen"])), EXIT_USAGE);
+        assert_eq!(run(&args(&["wasm-wrapper-gen", "--indent", "x", "a", "b"])), EXIT_USAGE);
+        // an empty argument list still reports a usage error.
+        assert_eq!(run(&[]), EXIT_USAGE);
+
+        let dir = env::temp_dir().join(format!("wasm-wrapper-gen-cli-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let missing = dir.join("missing.rs");
+        let output = dir.join("out.js");
+        assert_eq!(
+            run(&args(&[
+                "wasm-wrapper-gen",
+                missing.to_str().unwrap(),
+                output.to_str().unwrap(),
+            ])),
+            EXIT_FAILURE
+        );
+        assert!(!output.exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,425 @@
+//! JSON description of how every function is exported, for generating
+//! bindings other than the JavaScript class.
+//!
+//! All pointers and lengths are 32-bit. Memory for arguments is allocated
+//! with the `alloc` builtin, given a size in bytes, and freed with `dealloc`,
+//! given the pointer and the same size. Callers may instead copy borrowed
+//! arguments into a region they manage, such as the one `scratch_grow`
+//! allocates.
+//!
+//! Arguments are passed as:
+//!
+//! - `value`: the scalar itself
+//! - `borrowed`: a pointer and an element count. The caller allocates and
+//!   fills the array, and frees it after the call
+//! - `borrowed_mut`: like `borrowed`, but the array may have been changed
+//!   when the call returns
+//! - `owned`: a pointer and an element count, with the function taking
+//!   ownership and freeing the array itself
+//! - `copied`: like `borrowed`, but for strings. UTF-16 strings are passed
+//!   as many code units as the JavaScript string has, and UTF-8 strings are
+//!   allocated with three bytes per UTF-16 code unit and passed with the
+//!   number of bytes actually written. String arguments also have a
+//!   `validation`: `lossy` strings have lone surrogates replaced, `strict`
+//!   ones must be checked by the caller, and `none` are passed through as is.
+//!   `json` arguments are copied the same way, as the `JSON.stringify` of
+//!   the value
+//! - `is_some_flag`: for optional scalars, a flag which is 0 when the value
+//!   is absent, followed by the value (ignored when absent)
+//! - `null_pointer`: for other optional values, a null pointer when absent
+//!
+//! Values are returned as:
+//!
+//! - `none`: nothing is returned
+//! - `value`: the scalar itself
+//! - `ptr_len_cap_box`: a pointer to three pointer-sized values: the data
+//!   pointer, the element count and the capacity. The caller frees the data
+//!   (capacity times element size bytes) and the box (12 bytes). `json`
+//!   values are returned as strings to pass to `JSON.parse`. `buffer`
+//!   values are laid out the same way, but only the box is freed after the
+//!   call: the data is viewed in place until the buffer is freed
+//! - `box`: a pointer to a boxed value. `self` values are freed with their
+//!   type's drop function, boxed scalars with `dealloc`
+//! - `box_or_null` and `null_pointer`: like `box` or the inner type's layout,
+//!   with a null pointer when absent
+//! - `is_ok_payload_box`: a pointer to two pointer-sized values, 1 for `Ok`
+//!   or 0 for `Err`, and a payload (8 bytes to free). `Ok` payloads are 0 for
+//!   `()`, boxes for scalars and `self`, and otherwise the inner type's
+//!   layout. `Err` payloads are strings in the `ptr_len_cap_box` layout
+//! - `payload_array_box`: for tuples, a pointer to one pointer-sized payload
+//!   per element (4 bytes each to free), laid out like `Ok` payloads. Tuples
+//!   have `names` for their elements when they're returned as objects
+//!
+//! Functions declared with `js_import!` are listed in `imports`, and are
+//! imported by the module from the `env` module. Their arguments are laid out
+//! the same way, but are always owned by rust, whatever their `passing`: the
+//! implementation copies what it needs and never frees them. Their results
+//! are returned as:
+//!
+//! - `none` and `value`: like results of exported functions
+//! - `ptr_len_size_slot`: for strings and `json` values, the implementation
+//!   allocates the string with `alloc` and writes its pointer, length and
+//!   size in bytes to the three pointer-sized values the `return_slot` param
+//!   points to. Rust frees the allocation
+//!
+//! Arguments taking JavaScript callbacks are passed as a `table_index`: the
+//! index of the function in a table the caller keeps until the call returns.
+//! Rust calls callbacks through the `callback` builtin, imported from `env`,
+//! with the index, a pointer to the arguments and a pointer to a 24-byte
+//! return slot. The arguments are lowered like those of imported functions,
+//! into the values listed in `slots`, each stored in the low bytes of its own
+//! 8-byte slot. Results are written to the return slot: scalars in its low
+//! bytes, and strings and `json` values in the `ptr_len_size_slot` layout
+use failure::Error;
+
+use serde_json::{self, Value};
+
+use wasm_wrapper_gen_shared::{callback_import_name, JsFnInfo, JsImportInfo,
+                              SupportedArgumentType, SupportedCopyTy, SupportedRetType,
+                              SupportedSelfArgument};
+
+use abi::{self, WasmType};
+use generation::{string_element_ty, used_exports};
+use style::{Config, StringEncoding, StringValidation};
+
+/// Version of the manifest format, increased on any incompatible change.
+pub(crate) const MANIFEST_VERSION: u32 = 1;
+
+/// Size of pointers, lengths, and `usize` values in WebAssembly.
+const POINTER_SIZE: usize = 4;
+
+pub(crate) fn generate_abi_manifest(
+    config: &Config,
+    infos: &[JsFnInfo],
+    imports: &[JsImportInfo],
+) -> Result<String, Error> {
+    use abi::WasmType::I32;
+
+    let used = used_exports(config, infos, imports);
+
+    let manifest = json!({
+        "manifest_version": MANIFEST_VERSION,
+        "generator": {
+            "name": env!("CARGO_PKG_NAME"),
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "class_name": &*config.class_name,
+        "pointer_size": POINTER_SIZE,
+        "string_encoding": encoding_name(config),
+        "memory": "memory",
+        "builtins": {
+            "alloc": export_json(abi::builtin_export_name("alloc"), &[("len", I32)], &[I32]),
+            "dealloc": export_json(
+                abi::builtin_export_name("dealloc"),
+                &[("ptr", I32), ("len", I32)],
+                &[],
+            ),
+            // only exported with wasm-wrapper-gen's "scratch-arena" feature.
+            "scratch_grow": export_json(
+                abi::builtin_export_name("scratch_grow"),
+                &[("len", I32)],
+                &[I32],
+            ),
+            // only exported with wasm-wrapper-gen's "panic-messages" feature.
+            "panic_message": export_json(abi::builtin_export_name("panic_message"), &[], &[I32]),
+            "panic_location": export_json(
+                abi::builtin_export_name("panic_location"),
+                &[],
+                &[I32],
+            ),
+            // imported, only when callbacks are used.
+            "callback": {
+                "module": abi::IMPORT_MODULE,
+                "import": callback_import_name(),
+                "params": [
+                    param_json("index", &I32),
+                    param_json("args", &I32),
+                    param_json("return_slot", &I32),
+                ],
+                "results": [],
+            },
+        },
+        "types": used.self_types.iter().map(|self_ty| json!({
+            "name": self_ty,
+            "drop": export_json(abi::drop_export_name(self_ty), &[("ptr", I32)], &[]),
+        })).collect::<Vec<_>>(),
+        "functions": infos
+            .iter()
+            .map(|info| function_json(config, info))
+            .collect::<Vec<_>>(),
+        "imports": imports
+            .iter()
+            .map(|info| import_json(config, info))
+            .collect::<Vec<_>>(),
+    });
+
+    let mut output = serde_json::to_string_pretty(&manifest)?;
+    output.push('\n');
+    Ok(output)
+}
+
+fn export_json(export: String, params: &[(&str, WasmType)], results: &[WasmType]) -> Value {
+    json!({
+        "export": export,
+        "params": params.iter().map(|&(name, ref ty)| param_json(name, ty)).collect::<Vec<_>>(),
+        "results": results.iter().map(ToString::to_string).collect::<Vec<_>>(),
+    })
+}
+
+fn param_json(name: &str, ty: &WasmType) -> Value {
+    json!({
+        "name": name,
+        "type": ty.to_string(),
+    })
+}
+
+fn function_json(config: &Config, info: &JsFnInfo) -> Value {
+    let params = abi::lowered_params(info);
+    let arguments = arguments_json(config, &info.args_names, &info.args_ty, &params);
+
+    json!({
+        "name": info.rust_name,
+        "qualified_name": info.qualified_name(),
+        "self_type": info.self_ty,
+        "self_argument": info.self_arg.map(|self_arg| match self_arg {
+            SupportedSelfArgument::Ref => "ref",
+            SupportedSelfArgument::MutRef => "mut_ref",
+            SupportedSelfArgument::Value => "value",
+        }),
+        "export": abi::export_name(info),
+        "doc": info.doc_lines,
+        "arguments": arguments,
+        "return": return_json(config, &info.ret_ty, info.self_ty.as_deref()),
+        "params": params
+            .iter()
+            .map(|param| param_json(&param.name, &param.ty))
+            .collect::<Vec<_>>(),
+        "results": abi::lowered_results(&info.ret_ty)
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>(),
+    })
+}
+
+fn import_json(config: &Config, info: &JsImportInfo) -> Value {
+    let params = abi::lowered_import_params(info);
+    let arguments = arguments_json(config, &info.args_names, &info.args_ty, &params);
+
+    json!({
+        "name": info.rust_name,
+        "module": abi::IMPORT_MODULE,
+        "import": abi::import_name(info),
+        "doc": info.doc_lines,
+        "arguments": arguments,
+        "return": import_return_json(config, &info.ret_ty),
+        "params": params
+            .iter()
+            .map(|param| param_json(&param.name, &param.ty))
+            .collect::<Vec<_>>(),
+        "results": abi::lowered_import_results(&info.ret_ty)
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>(),
+    })
+}
+
+/// Describes the return type of an imported function or callback.
+fn import_return_json(config: &Config, ty: &SupportedRetType) -> Value {
+    let mut value = return_json(config, ty, None);
+    match *ty {
+        SupportedRetType::Unit | SupportedRetType::Integer(_) => {}
+        _ => value["layout"] = json!("ptr_len_size_slot"),
+    }
+    value
+}
+
+fn arguments_json(
+    config: &Config,
+    names: &[String],
+    types: &[SupportedArgumentType],
+    params: &[abi::WasmParam],
+) -> Vec<Value> {
+    names
+        .iter()
+        .zip(types)
+        .enumerate()
+        .map(|(index, (name, ty))| {
+            // names of the params this argument is lowered to.
+            let arg_params = params
+                .iter()
+                .filter(|param| param.argument == Some(index))
+                .map(|param| &*param.name)
+                .collect::<Vec<_>>();
+            json!({
+                "name": name,
+                "type": argument_json(config, ty),
+                "params": arg_params,
+            })
+        })
+        .collect()
+}
+
+fn scalar_json(ty: SupportedCopyTy) -> Value {
+    json!({
+        "kind": "scalar",
+        "type": ty.to_string(),
+        "size": ty.size_in_bytes(),
+        "wasm_type": abi::copy_ty_to_wasm(ty).to_string(),
+    })
+}
+
+fn array_json(kind: &str, element: SupportedCopyTy, passing: &str) -> Value {
+    json!({
+        "kind": kind,
+        "element": scalar_json(element),
+        "passing": passing,
+    })
+}
+
+fn argument_json(config: &Config, ty: &SupportedArgumentType) -> Value {
+    match *ty {
+        SupportedArgumentType::Integer(int_ty) => {
+            let mut value = scalar_json(int_ty);
+            value["passing"] = json!("value");
+            value
+        }
+        SupportedArgumentType::IntegerSliceRef(int_ty) => array_json("slice", int_ty, "borrowed"),
+        SupportedArgumentType::IntegerSliceMutRef(int_ty) => {
+            array_json("slice", int_ty, "borrowed_mut")
+        }
+        SupportedArgumentType::IntegerVec(int_ty) => array_json("vec", int_ty, "owned"),
+        SupportedArgumentType::OwnedString | SupportedArgumentType::StringSlice => {
+            let mut value = array_json("string", string_element_ty(config), "copied");
+            value["encoding"] = json!(encoding_name(config));
+            value["validation"] = json!(validation_name(config));
+            value
+        }
+        // always UTF-16, and never validated.
+        SupportedArgumentType::JsString => {
+            let mut value = array_json("string", SupportedCopyTy::U16, "copied");
+            value["encoding"] = json!("utf-16");
+            value["validation"] = json!("none");
+            value
+        }
+        // serialized with `JSON.stringify`, then passed like a string.
+        SupportedArgumentType::Json => {
+            let mut value = array_json("json", string_element_ty(config), "copied");
+            value["encoding"] = json!(encoding_name(config));
+            value
+        }
+        SupportedArgumentType::Callback(ref args_ty, ref ret_ty) => {
+            let slots = abi::lowered_callback_args(args_ty);
+            let names = (0..args_ty.len())
+                .map(|index| format!("arg{}", index))
+                .collect::<Vec<_>>();
+            json!({
+                "kind": "callback",
+                "arguments": arguments_json(config, &names, args_ty, &slots),
+                "return": import_return_json(config, ret_ty),
+                "slots": slots
+                    .iter()
+                    .map(|slot| param_json(&slot.name, &slot.ty))
+                    .collect::<Vec<_>>(),
+                "passing": "table_index",
+            })
+        }
+        SupportedArgumentType::Optional(ref inner) => {
+            let passing = match **inner {
+                SupportedArgumentType::Integer(_) => "is_some_flag",
+                _ => "null_pointer",
+            };
+            json!({
+                "kind": "optional",
+                "inner": argument_json(config, inner),
+                "passing": passing,
+            })
+        }
+    }
+}
+
+fn return_json(config: &Config, ty: &SupportedRetType, self_ty: Option<&str>) -> Value {
+    match *ty {
+        SupportedRetType::Unit => json!({
+            "kind": "unit",
+            "layout": "none",
+        }),
+        SupportedRetType::Integer(int_ty) => {
+            let mut value = scalar_json(int_ty);
+            value["layout"] = json!("value");
+            value
+        }
+        SupportedRetType::IntegerVec(int_ty) => json!({
+            "kind": "vec",
+            "element": scalar_json(int_ty),
+            "layout": "ptr_len_cap_box",
+        }),
+        // the data is left allocated, and freed through the buffer.
+        SupportedRetType::Buffer(int_ty) => json!({
+            "kind": "buffer",
+            "element": scalar_json(int_ty),
+            "layout": "ptr_len_cap_box",
+        }),
+        SupportedRetType::OwnedString | SupportedRetType::StringSlice => {
+            string_return_json(config)
+        }
+        SupportedRetType::Json => {
+            let mut value = string_return_json(config);
+            value["kind"] = json!("json");
+            value
+        }
+        SupportedRetType::OwnedSelf => json!({
+            "kind": "self",
+            "type": self_ty,
+            "layout": "box",
+        }),
+        SupportedRetType::Optional(ref inner) => {
+            let layout = match **inner {
+                SupportedRetType::Integer(_) | SupportedRetType::OwnedSelf => "box_or_null",
+                _ => "null_pointer",
+            };
+            json!({
+                "kind": "optional",
+                "inner": return_json(config, inner, self_ty),
+                "layout": layout,
+            })
+        }
+        SupportedRetType::Result(ref inner) => json!({
+            "kind": "result",
+            "ok": return_json(config, inner, self_ty),
+            "err": string_return_json(config),
+            "layout": "is_ok_payload_box",
+        }),
+        SupportedRetType::Tuple(ref elements, ref names) => json!({
+            "kind": "tuple",
+            "elements": elements
+                .iter()
+                .map(|element| return_json(config, element, self_ty))
+                .collect::<Vec<_>>(),
+            "names": names,
+            "layout": "payload_array_box",
+        }),
+    }
+}
+
+fn string_return_json(config: &Config) -> Value {
+    json!({
+        "kind": "string",
+        "element": scalar_json(string_element_ty(config)),
+        "encoding": encoding_name(config),
+        "layout": "ptr_len_cap_box",
+    })
+}
+
+fn validation_name(config: &Config) -> &'static str {
+    match config.string_validation {
+        StringValidation::Lossy => "lossy",
+        StringValidation::Strict => "strict",
+    }
+}
+
+fn encoding_name(config: &Config) -> &'static str {
+    match config.string_encoding {
+        StringEncoding::Utf16 => "utf-16",
+        StringEncoding::Utf8 => "utf-8",
+    }
+}
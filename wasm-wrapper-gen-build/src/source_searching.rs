@@ -1,27 +1,163 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
 use failure::Error;
 
 use syn;
 
-use wasm_wrapper_gen_shared::transform_mac_to_items;
+use wasm_wrapper_gen_shared::{parse_file, transform_import_mac_to_items, transform_mac_to_items};
+
+/// Items defined inside `js_fn!` and `js_import!` invocations in a single
+/// file.
+pub struct FoundItems {
+    pub file: PathBuf,
+    /// Items of each `js_fn!` invocation, in the order they're in the file.
+    pub invocations: Vec<Vec<syn::Item>>,
+    pub imports: Vec<syn::ForeignItem>,
+}
+
+/// Result of scanning a crate's module tree.
+#[derive(Default)]
+pub struct ScannedCrate {
+    /// Every file read, whether or not it contained `js_fn!` invocations.
+    pub files: Vec<PathBuf>,
+    pub found: Vec<FoundItems>,
+}
+
+/// Finds the items inside every `js_fn!` and `js_import!` invocation in the
+/// crate with the given root file, following `mod` declarations into other
+/// files.
+pub fn walk_crate_for_js_fns(root_file: &Path) -> Result<ScannedCrate, Error> {
+    let mut scanned = ScannedCrate::default();
+    // the crate root is treated like a `mod.rs` file.
+    let root_dir = root_file.parent().unwrap_or_else(|| Path::new(""));
+    walk_file_for_js_fns(root_file, root_dir, &mut scanned)?;
+    Ok(scanned)
+}
 
-pub fn walk_crate_for_js_fns(source: &str) -> Result<Vec<syn::Item>, Error> {
+/// Finds `js_fn!` items in one file and any modules it declares, given the
+/// directory its child modules are in.
+fn walk_file_for_js_fns(
+    file: &Path,
+    module_dir: &Path,
+    scanned: &mut ScannedCrate,
+) -> Result<(), Error> {
     use syn::visit::Visitor;
 
-    let ast = syn::parse_crate(source)
-        .map_err(|e| format_err!("failed to parse macro input as an item: {}", e))?;
+    let source = fs::read_to_string(file)
+        .map_err(|e| format_err!("failed to read {}: {}", file.display(), e))?;
+    scanned.files.push(file.to_owned());
 
-    let mut v = FindMacrosVisitor::find_js_fn();
+    let ast = parse_file(&source).map_err(|e| {
+        format_err!("failed to parse {} as a rust file: {}", file.display(), e)
+    })?;
+
+    let mut v = FindMacrosVisitor::find_named("js_fn");
+    let mut import_v = FindMacrosVisitor::find_named("js_import");
 
     v.visit_crate(&ast);
+    import_v.visit_crate(&ast);
 
-    // flat_map doesn't work well with Result<Vec<T>, E>.
-    let mut func_definition_items = Vec::new();
+    let mut invocations = Vec::new();
 
     for found_macro in v.found {
-        func_definition_items.extend(transform_mac_to_items(found_macro)?);
+        invocations.push(transform_mac_to_items(found_macro).map_err(|e| {
+            format_err!("invalid js_fn! invocation in {}: {}", file.display(), e)
+        })?);
+    }
+
+    let mut imports = Vec::new();
+
+    for found_macro in import_v.found {
+        imports.extend(transform_import_mac_to_items(found_macro).map_err(|e| {
+            format_err!("invalid js_import! invocation in {}: {}", file.display(), e)
+        })?);
     }
 
-    Ok(func_definition_items)
+    if !invocations.is_empty() || !imports.is_empty() {
+        scanned.found.push(FoundItems {
+            file: file.to_owned(),
+            invocations,
+            imports,
+        });
+    }
+
+    // `#[path]` attributes outside of inline modules are relative to the
+    // file's directory, even when child modules are in a subdirectory.
+    let file_dir = file.parent().unwrap_or_else(|| Path::new(""));
+    walk_items_for_modules(&ast.items, module_dir, file_dir, scanned)
+}
+
+/// Follows `mod name;` declarations in the given items, including inside of
+/// inline modules.
+fn walk_items_for_modules(
+    items: &[syn::Item],
+    module_dir: &Path,
+    path_attr_dir: &Path,
+    scanned: &mut ScannedCrate,
+) -> Result<(), Error> {
+    for item in items {
+        let content = match item.node {
+            syn::ItemKind::Mod(ref content) => content,
+            _ => continue,
+        };
+        let path_attr = find_path_attribute(&item.attrs);
+        match *content {
+            Some(ref inline_items) => {
+                let inner_dir = match path_attr {
+                    Some(path) => path_attr_dir.join(path),
+                    None => module_dir.join(item.ident.as_ref()),
+                };
+                walk_items_for_modules(inline_items, &inner_dir, &inner_dir, scanned)?;
+            }
+            None => {
+                let (file, inner_module_dir) = match path_attr {
+                    // files loaded through `#[path]` are treated like `mod.rs` files.
+                    Some(path) => {
+                        let file = path_attr_dir.join(path);
+                        let dir = file.parent().unwrap_or_else(|| Path::new("")).to_owned();
+                        (file, dir)
+                    }
+                    None => {
+                        let name = item.ident.as_ref();
+                        let non_mod_rs = module_dir.join(format!("{}.rs", name));
+                        let mod_rs = module_dir.join(name).join("mod.rs");
+                        if mod_rs.is_file() && !non_mod_rs.is_file() {
+                            (mod_rs, module_dir.join(name))
+                        } else {
+                            (non_mod_rs, module_dir.join(name))
+                        }
+                    }
+                };
+                if !file.is_file() {
+                    if has_cfg_attribute(&item.attrs) {
+                        // probably a module for another configuration.
+                        continue;
+                    }
+                    bail!(
+                        "file for module `{}` not found: expected {}",
+                        item.ident,
+                        file.display()
+                    );
+                }
+                walk_file_for_js_fns(&file, &inner_module_dir, scanned)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn find_path_attribute(attrs: &[syn::Attribute]) -> Option<&str> {
+    attrs.iter().filter_map(|attr| match attr.value {
+        syn::MetaItem::NameValue(ref ident, syn::Lit::Str(ref path, _)) if ident == "path" => {
+            Some(&**path)
+        }
+        _ => None,
+    }).next()
+}
+
+fn has_cfg_attribute(attrs: &[syn::Attribute]) -> bool {
+    attrs.iter().any(|attr| attr.value.name() == "cfg")
 }
 
 struct FindMacrosVisitor {
@@ -37,12 +173,12 @@ impl FindMacrosVisitor {
         }
     }
 
-    fn find_js_fn() -> Self {
+    fn find_named(name: &str) -> Self {
         FindMacrosVisitor::new(syn::Path {
             global: false,
             segments: vec![
                 syn::PathSegment {
-                    ident: syn::Ident::new("js_fn"),
+                    ident: syn::Ident::new(name),
                     parameters: syn::PathParameters::none(),
                 },
             ],
@@ -53,11 +189,156 @@ impl FindMacrosVisitor {
 impl syn::visit::Visitor for FindMacrosVisitor {
     fn visit_mac(&mut self, mac: &syn::Mac) {
         // TODO: can macros ever have global paths? This would break if that's
-        // the case. Right now we require an exact match on non-global 'js_fn!',
-        // if there could be another way to invoke it, we might want to use
-        // some fuzzy matching.
+        // the case. Right now we require an exact match on non-global 'js_fn!'
+        // or 'js_import!', if there could be another way to invoke it, we
+        // might want to use some fuzzy matching.
         if mac.path == self.ident_to_find {
             self.found.push(mac.clone());
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use test_util::TempDir;
+
+    use super::*;
+
+    const JS_FN: &str = "js_fn! { fn f(x: u32) -> u32 => f; }\n";
+    const JS_IMPORT: &str = "js_import! { fn log(message: &str); }\n";
+
+    fn relative_files(dir: &TempDir, files: &[PathBuf]) -> Vec<PathBuf> {
+        files
+            .iter()
+            .map(|file| file.strip_prefix(dir.path()).unwrap().to_owned())
+            .collect()
+    }
+
+    fn scanned_files(dir: &TempDir) -> Vec<PathBuf> {
+        let scanned = walk_crate_for_js_fns(&dir.path().join("lib.rs")).unwrap();
+        relative_files(dir, &scanned.files)
+    }
+
+    fn paths(paths: &[&str]) -> Vec<PathBuf> {
+        paths.iter().map(Path::new).map(Path::to_owned).collect()
+    }
+
+    #[test]
+    fn finds_invocations_and_imports() {
+        let dir = TempDir::new("find-items");
+        dir.write("lib.rs", &format!("mod a;\n{}{}", JS_FN, JS_FN));
+        dir.write("a.rs", JS_IMPORT);
+
+        let scanned = walk_crate_for_js_fns(&dir.path().join("lib.rs")).unwrap();
+        assert_eq!(scanned.found.len(), 2);
+        assert_eq!(scanned.found[0].file, dir.path().join("lib.rs"));
+        assert_eq!(scanned.found[0].invocations.len(), 2);
+        assert!(scanned.found[0].imports.is_empty());
+        assert_eq!(scanned.found[1].file, dir.path().join("a.rs"));
+        assert!(scanned.found[1].invocations.is_empty());
+        assert_eq!(scanned.found[1].imports.len(), 1);
+    }
+
+    #[test]
+    fn mod_rs_and_name_rs() {
+        let dir = TempDir::new("mod-layouts");
+        dir.write("lib.rs", "mod a;\nmod b;\n");
+        // child modules of `a.rs` are in `a/`.
+        dir.write("a.rs", "mod inner;\n");
+        dir.write("a/inner.rs", JS_FN);
+        dir.write("b/mod.rs", "mod inner;\n");
+        dir.write("b/inner.rs", JS_FN);
+
+        assert_eq!(
+            scanned_files(&dir),
+            paths(&["lib.rs", "a.rs", "a/inner.rs", "b/mod.rs", "b/inner.rs"])
+        );
+    }
+
+    #[test]
+    fn inline_modules() {
+        let dir = TempDir::new("inline-modules");
+        dir.write("lib.rs", "mod outer { mod inner; }\n");
+        dir.write("outer/inner.rs", "mod deeper;\n");
+        dir.write("outer/inner/deeper.rs", JS_FN);
+
+        assert_eq!(
+            scanned_files(&dir),
+            paths(&["lib.rs", "outer/inner.rs", "outer/inner/deeper.rs"])
+        );
+    }
+
+    #[test]
+    fn path_attributes() {
+        let dir = TempDir::new("path-attributes");
+        dir.write(
+            "lib.rs",
+            "#[path = \"elsewhere/renamed.rs\"] mod a;\n\
+             #[path = \"inline\"] mod b { mod c; }\n\
+             mod d;\n",
+        );
+        // files loaded through `#[path]` are treated like `mod.rs` files.
+        dir.write("elsewhere/renamed.rs", "mod sibling;\n");
+        dir.write("elsewhere/sibling.rs", JS_FN);
+        dir.write("inline/c.rs", JS_FN);
+        // `#[path]` in a non-`mod.rs` file is relative to its own directory.
+        dir.write("d.rs", "#[path = \"e.rs\"] mod e;\n");
+        dir.write("e.rs", JS_FN);
+
+        assert_eq!(
+            scanned_files(&dir),
+            paths(&[
+                "lib.rs",
+                "elsewhere/renamed.rs",
+                "elsewhere/sibling.rs",
+                "inline/c.rs",
+                "d.rs",
+                "e.rs",
+            ])
+        );
+    }
+
+    #[test]
+    fn missing_file_behind_cfg() {
+        let dir = TempDir::new("missing-cfg");
+        dir.write(
+            "lib.rs",
+            "#[cfg(target_os = \"none\")] mod other_platform;\nmod a;\n",
+        );
+        dir.write("a.rs", JS_FN);
+
+        assert_eq!(scanned_files(&dir), paths(&["lib.rs", "a.rs"]));
+    }
+
+    #[test]
+    fn missing_file() {
+        let dir = TempDir::new("missing");
+        dir.write("lib.rs", "mod a;\n");
+
+        let error = walk_crate_for_js_fns(&dir.path().join("lib.rs"))
+            .err()
+            .expect("expected a missing module error")
+            .to_string();
+        assert_eq!(
+            error,
+            format!(
+                "file for module `a` not found: expected {}",
+                dir.path().join("a.rs").display()
+            )
+        );
+    }
+
+    #[test]
+    fn invalid_invocation() {
+        let dir = TempDir::new("invalid");
+        dir.write("lib.rs", "js_fn! { struct Foo; }\n");
+
+        let error = walk_crate_for_js_fns(&dir.path().join("lib.rs"))
+            .err()
+            .expect("expected an invalid invocation error")
+            .to_string();
+        assert!(error.starts_with("invalid js_fn! invocation in "), "{}", error);
+    }
+}
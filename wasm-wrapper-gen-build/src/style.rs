@@ -1,4 +1,5 @@
 use std::borrow::Cow;
+use std::path::{Path, PathBuf};
 
 // TODO: add hyperlinks to documentation.
 #[derive(Clone, Debug)]
@@ -9,9 +10,37 @@ pub struct Config<'a> {
     pub(crate) indent: u32,
     /// Array access style to use. Default DataView.
     pub(crate) access_style: AccessStyle,
+    /// Name of the Error subclass thrown when a function returns `Err`.
+    /// Default is the class name followed by "Error".
+    pub(crate) error_class_name: Option<Cow<'a, str>>,
+    /// What to do when rust code panics. Default Trap.
+    pub(crate) panic_strategy: PanicStrategy,
+    /// Whether to generate a static async `create(source)` function. Default false.
+    pub(crate) async_create: bool,
+    /// Whether classes share one module-level instance, rather than being
+    /// accessed through an instance of the main class. Default false.
+    pub(crate) shared_loader: bool,
+    /// Whether `JsBuffer`s viewing rust memory in place can be allocated,
+    /// passed and returned. Default false.
+    pub(crate) zero_copy_buffers: bool,
+    /// Whether borrowed arguments are bump-allocated in a scratch region
+    /// rather than allocated and deallocated one by one. Default false.
+    pub(crate) scratch_arena: bool,
+    /// How the generated classes are exported. Default CommonJs.
+    pub(crate) module_format: ModuleFormat,
+    /// Whether to write TypeScript declarations next to the output. Default false.
+    pub(crate) typescript_declarations: bool,
+    /// Whether to print `cargo:rerun-if-changed` for each scanned file. Default true.
+    pub(crate) rerun_if_changed: bool,
+    /// File to write a JSON description of the exported functions to. Default none.
+    pub(crate) abi_manifest: Option<PathBuf>,
+    /// How strings are passed to and from rust. Default Utf16.
+    pub(crate) string_encoding: StringEncoding,
+    /// What happens to string arguments which aren't valid UTF-16. Default Lossy.
+    pub(crate) string_validation: StringValidation,
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
 pub enum AccessStyle {
     /// Construct typed arrays in each function for use.
     ///
@@ -23,14 +52,85 @@ pub enum AccessStyle {
     /// Construct a single DataView for the module and use its methods in each
     /// function to set individual values of arrays.
     ///
+    /// Typed arrays of the right type are still copied in bulk on
+    /// little-endian platforms, as are returned arrays.
+    ///
     /// This is the default.
+    #[default]
     DataView,
 }
 
-impl Default for AccessStyle {
-    fn default() -> Self {
-        AccessStyle::DataView
-    }
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum PanicStrategy {
+    /// Let the WebAssembly trap caused by a panic propagate as is.
+    ///
+    /// Memory allocated for arguments of the panicking call is leaked, unless
+    /// using a scratch arena, in which case borrowed arguments are freed as
+    /// with `ThrowRustPanic`.
+    ///
+    /// This is the default.
+    #[default]
+    Trap,
+    /// Catch traps, deallocate borrowed arguments, and throw a `RustPanic`
+    /// error with the panic's message and location.
+    ///
+    /// This requires the "panic-messages" feature of `wasm-wrapper-gen` to be
+    /// enabled. Owned arguments, such as `Vec`s and `String`s, are still
+    /// leaked, since the rust code may have already freed them.
+    ///
+    /// The instance should be discarded after a `RustPanic`: the trap skips
+    /// restoring rust's stack pointer, leaking the stack space the call used,
+    /// and leaves any `RefCell` or `Mutex` held during the panic locked.
+    ThrowRustPanic,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum ModuleFormat {
+    /// Assign the class to `module.exports`, for node and bundlers.
+    ///
+    /// This is the default.
+    #[default]
+    CommonJs,
+    /// Export the class with `export default`, and it along with other
+    /// classes as named exports, for `<script type=module>` and bundlers.
+    EsModule,
+    /// Universal module definition, working as a CommonJS module, an AMD
+    /// module, or a plain script defining a global.
+    Umd,
+    /// A plain script defining the class as a global variable.
+    Global,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum StringEncoding {
+    /// Copy strings one UTF-16 code unit at a time, converting them to and
+    /// from UTF-8 in rust.
+    ///
+    /// This is the default.
+    #[default]
+    Utf16,
+    /// Use `TextEncoder` and `TextDecoder` to pass UTF-8 directly.
+    ///
+    /// This requires the "utf8-strings" feature of `wasm-wrapper-gen` to be
+    /// enabled, and is much faster for large strings.
+    Utf8,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum StringValidation {
+    /// Replace lone surrogates in string arguments with U+FFFD REPLACEMENT
+    /// CHARACTER.
+    ///
+    /// This is the default.
+    #[default]
+    Lossy,
+    /// Throw a `TypeError` naming the argument when a string argument contains
+    /// lone surrogates, before calling into rust.
+    ///
+    /// This uses `String.prototype.isWellFormed` where it's available (node 20
+    /// and current browsers), and a regular expression otherwise. `JsString`
+    /// arguments are never checked.
+    Strict,
 }
 
 impl<'a> Default for Config<'a> {
@@ -39,6 +139,18 @@ impl<'a> Default for Config<'a> {
             class_name: "WasmWrapper".into(),
             indent: 4,
             access_style: AccessStyle::default(),
+            error_class_name: None,
+            panic_strategy: PanicStrategy::default(),
+            async_create: false,
+            shared_loader: false,
+            zero_copy_buffers: false,
+            scratch_arena: false,
+            module_format: ModuleFormat::default(),
+            typescript_declarations: false,
+            rerun_if_changed: true,
+            abi_manifest: None,
+            string_encoding: StringEncoding::default(),
+            string_validation: StringValidation::default(),
         }
     }
 }
@@ -62,4 +174,125 @@ impl<'a> Config<'a> {
         self.access_style = style;
         self
     }
+
+    pub fn with_error_class_name<T: Into<Cow<'a, str>>>(&mut self, name: T) -> &mut Self {
+        self.error_class_name = Some(name.into());
+        self
+    }
+
+    pub fn with_panic_strategy(&mut self, strategy: PanicStrategy) -> &mut Self {
+        self.panic_strategy = strategy;
+        self
+    }
+
+    /// Generates a static `async create(source)` function, instantiating the
+    /// module asynchronously from a `WebAssembly.Module`, `ArrayBuffer`,
+    /// `Response` or URL.
+    ///
+    /// Browsers refuse to synchronously instantiate modules over 4KB on the
+    /// main thread, so this is needed to use the constructor there.
+    pub fn with_async_create(&mut self, async_create: bool) -> &mut Self {
+        self.async_create = async_create;
+        self
+    }
+
+    /// Generates a module-level loader owning the single `WebAssembly.Instance`,
+    /// along with classes which all use it: one with static methods for free
+    /// functions, named after the class name, and one per `impl` block type.
+    ///
+    /// The module is loaded with `ClassName.load(module)`, or with
+    /// `await ClassName.loadAsync(source)` when also using async create, after
+    /// which functions are called as `ClassName.func()` and constructors as
+    /// `TypeName.func()`. Loading again replaces the instance used by later
+    /// calls, while existing values keep using the one they were created in.
+    pub fn with_shared_loader(&mut self, shared_loader: bool) -> &mut Self {
+        self.shared_loader = shared_loader;
+        self
+    }
+
+    /// Generates a `JsBuffer` class wrapping a typed array which views rust
+    /// memory in place, along with an `allocBuffer(type, length)` method
+    /// allocating one.
+    ///
+    /// Buffers can be filled in place and passed to any number of calls as
+    /// slice arguments without copying them, and functions returning
+    /// `JsBuffer<T>` return one viewing the rust `Vec`. Either way, the buffer
+    /// stays allocated until its `free()` is called.
+    pub fn with_zero_copy_buffers(&mut self, zero_copy_buffers: bool) -> &mut Self {
+        self.zero_copy_buffers = zero_copy_buffers;
+        self
+    }
+
+    /// Copies arguments which are only borrowed for the call, like slices and
+    /// strings, into a scratch region reused by every call, rather than
+    /// allocating and deallocating memory for each of them.
+    ///
+    /// This requires the "scratch-arena" feature of `wasm-wrapper-gen` to be
+    /// enabled. Owned `Vec` arguments still get their own allocations, as do
+    /// arguments which don't fit, with the region growing to fit them once no
+    /// call is using it. The region is released even if the call traps.
+    pub fn with_scratch_arena(&mut self, scratch_arena: bool) -> &mut Self {
+        self.scratch_arena = scratch_arena;
+        self
+    }
+
+    pub fn with_module_format(&mut self, format: ModuleFormat) -> &mut Self {
+        self.module_format = format;
+        self
+    }
+
+    /// Also writes TypeScript declarations for the output file, replacing its
+    /// extension with `.d.ts`.
+    pub fn with_typescript_declarations(&mut self, declarations: bool) -> &mut Self {
+        self.typescript_declarations = declarations;
+        self
+    }
+
+    /// Prints `cargo:rerun-if-changed` lines for every source file scanned,
+    /// so that build scripts only rerun when one of them changes.
+    ///
+    /// Cargo then no longer reruns the build script when other files in the
+    /// package change, so build scripts doing more than translating should
+    /// print their own lines for anything else they depend on.
+    pub fn with_rerun_if_changed(&mut self, rerun_if_changed: bool) -> &mut Self {
+        self.rerun_if_changed = rerun_if_changed;
+        self
+    }
+
+    pub fn with_string_encoding(&mut self, encoding: StringEncoding) -> &mut Self {
+        self.string_encoding = encoding;
+        self
+    }
+
+    pub fn with_string_validation(&mut self, validation: StringValidation) -> &mut Self {
+        self.string_validation = validation;
+        self
+    }
+
+    /// Also writes a versioned JSON manifest describing how every function is
+    /// exported: names, lowered wasm parameters, and how each argument and
+    /// return value is laid out in memory.
+    ///
+    /// This is meant for generating bindings other than the JavaScript class.
+    pub fn with_abi_manifest<P: AsRef<Path>>(&mut self, manifest_file: P) -> &mut Self {
+        self.abi_manifest = Some(manifest_file.as_ref().to_owned());
+        self
+    }
+
+    /// Name of the class owning the `WebAssembly.Instance`: the main class,
+    /// or the hidden loader class when using a shared loader.
+    pub(crate) fn instance_class_name(&self) -> Cow<'_, str> {
+        if self.shared_loader {
+            Cow::Owned(format!("{}Loader", self.class_name))
+        } else {
+            Cow::Borrowed(&self.class_name)
+        }
+    }
+
+    pub(crate) fn error_class_name(&self) -> Cow<'_, str> {
+        match self.error_class_name {
+            Some(ref name) => Cow::Borrowed(name),
+            None => Cow::Owned(format!("{}Error", self.class_name)),
+        }
+    }
 }
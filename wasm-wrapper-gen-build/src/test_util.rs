@@ -0,0 +1,61 @@
+//! Helpers shared by the unit tests.
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use failure::Error;
+
+use wasm_wrapper_gen_shared::{JsFnInfo, JsImportInfo};
+
+use source_searching::walk_crate_for_js_fns;
+use {translate_crate, translate_imports};
+
+/// A directory in the system's temporary directory, removed when dropped.
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    pub fn new(name: &str) -> Self {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+
+        let path = env::temp_dir().join(format!(
+            "wasm-wrapper-gen-{}-{}-{}",
+            name,
+            process::id(),
+            COUNT.fetch_add(1, Ordering::SeqCst)
+        ));
+        // left over from an earlier run with the same process id.
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TempDir { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Writes a file relative to the directory, creating its parents.
+    pub fn write(&self, file: &str, contents: &str) -> PathBuf {
+        let path = self.path.join(file);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, contents).unwrap();
+        path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+/// Translates the `js_fn!` and `js_import!` invocations of a crate made of a
+/// single `lib.rs` with the given source.
+pub fn translate_source(source: &str) -> Result<(Vec<JsFnInfo>, Vec<JsImportInfo>), Error> {
+    let dir = TempDir::new("translate");
+    let scanned = walk_crate_for_js_fns(&dir.write("lib.rs", source))?;
+    Ok((translate_crate(&scanned)?, translate_imports(&scanned)?))
+}
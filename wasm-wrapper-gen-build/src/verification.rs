@@ -0,0 +1,658 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use failure::{Error, Fail};
+
+use wasmparser::{CompositeType, ExternalKind, FuncType, Parser, Payload, TypeRef, ValType};
+
+use wasm_wrapper_gen_shared::{JsFnInfo, JsImportInfo};
+
+use abi::{self, WasmType, BUILTIN_EXPORT_PREFIX, EXPORT_PREFIX};
+use generation::used_exports;
+use style::{Config, StringEncoding};
+
+/// Parameter and result types of an exported WebAssembly function.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WasmSignature {
+    pub params: Vec<WasmType>,
+    pub results: Vec<WasmType>,
+}
+
+/// A difference between the exports the generated JavaScript uses and the
+/// exports of the compiled module.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ExportProblem {
+    /// Something the wrapper uses isn't exported.
+    Missing { name: String },
+    /// A `js_fn!` function is exported, but isn't in the source anymore.
+    Stale { name: String },
+    /// Something the wrapper uses is exported, but as the wrong kind of item.
+    WrongKind {
+        name: String,
+        expected: &'static str,
+    },
+    /// The module was compiled with a different string encoding than the
+    /// wrapper uses, detected through `wasm-wrapper-gen`'s "utf8-strings"
+    /// feature.
+    StringEncodingMismatch { expected: StringEncoding },
+    /// A function is exported with a different signature than `js_fn!`
+    /// would give it.
+    SignatureMismatch {
+        name: String,
+        expected: WasmSignature,
+        found: WasmSignature,
+    },
+}
+
+/// Error returned when the compiled module doesn't match the generated
+/// wrapper.
+#[derive(Debug)]
+pub struct VerificationError {
+    pub problems: Vec<ExportProblem>,
+}
+
+impl fmt::Display for WasmSignature {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "(")?;
+        for (i, param) in self.params.iter().enumerate() {
+            if i != 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", param)?;
+        }
+        write!(f, ") -> ")?;
+        match self.results.len() {
+            0 => write!(f, "()"),
+            1 => write!(f, "{}", self.results[0]),
+            _ => {
+                write!(f, "(")?;
+                for (i, result) in self.results.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", result)?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+impl fmt::Display for ExportProblem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ExportProblem::Missing { ref name } => write!(f, "missing export `{}`", name),
+            ExportProblem::Stale { ref name } => write!(
+                f,
+                "stale export `{}` has no matching function in the source",
+                name
+            ),
+            ExportProblem::WrongKind {
+                ref name,
+                expected,
+            } => write!(f, "export `{}` is not a {}", name, expected),
+            ExportProblem::StringEncodingMismatch { expected } => {
+                let (wrapper, module) = match expected {
+                    StringEncoding::Utf16 => ("UTF-16", "with"),
+                    StringEncoding::Utf8 => ("UTF-8", "without"),
+                };
+                write!(
+                    f,
+                    "wrapper uses {} strings, but the module was compiled {} \
+                     wasm-wrapper-gen's \"utf8-strings\" feature",
+                    wrapper, module
+                )
+            }
+            ExportProblem::SignatureMismatch {
+                ref name,
+                ref expected,
+                ref found,
+            } => write!(
+                f,
+                "export `{}` has signature {}, expected {}",
+                name, found, expected
+            ),
+        }
+    }
+}
+
+impl fmt::Display for VerificationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "compiled module doesn't match the generated wrapper ({} problems):",
+            self.problems.len()
+        )?;
+        for problem in &self.problems {
+            write!(f, "\n    {}", problem)?;
+        }
+        Ok(())
+    }
+}
+
+impl Fail for VerificationError {}
+
+/// What a module exports under some name.
+enum ExportedItem {
+    Function(WasmSignature),
+    Memory,
+    Other,
+}
+
+/// Reads the exports of a WebAssembly module, along with the signatures of
+/// exported functions.
+fn read_exports(wasm: &[u8]) -> Result<HashMap<String, ExportedItem>, Error> {
+    // `None` for types which aren't functions.
+    let mut types = Vec::<Option<WasmSignature>>::new();
+    // type index of each function, starting with imported functions.
+    let mut function_types = Vec::<u32>::new();
+    let mut exports = Vec::new();
+
+    for payload in Parser::new(0).parse_all(wasm) {
+        match payload? {
+            Payload::TypeSection(reader) => for rec_group in reader {
+                for sub_type in rec_group?.into_types() {
+                    types.push(match sub_type.composite_type {
+                        CompositeType::Func(ref func_ty) => Some(signature_of(func_ty)),
+                        _ => None,
+                    });
+                }
+            },
+            Payload::ImportSection(reader) => for import in reader {
+                if let TypeRef::Func(type_index) = import?.ty {
+                    function_types.push(type_index);
+                }
+            },
+            Payload::FunctionSection(reader) => for type_index in reader {
+                function_types.push(type_index?);
+            },
+            Payload::ExportSection(reader) => for export in reader {
+                let export = export?;
+                exports.push((export.name.to_owned(), export.kind, export.index));
+            },
+            _ => {}
+        }
+    }
+
+    exports
+        .into_iter()
+        .map(|(name, kind, index)| {
+            let item = match kind {
+                ExternalKind::Func => {
+                    let signature = function_types
+                        .get(index as usize)
+                        .and_then(|&type_index| types.get(type_index as usize))
+                        .and_then(Option::as_ref)
+                        .ok_or_else(|| {
+                            format_err!("export `{}` refers to an invalid function", name)
+                        })?;
+                    ExportedItem::Function(signature.clone())
+                }
+                ExternalKind::Memory => ExportedItem::Memory,
+                _ => ExportedItem::Other,
+            };
+            Ok((name, item))
+        })
+        .collect()
+}
+
+fn signature_of(func_ty: &FuncType) -> WasmSignature {
+    fn convert(ty: &ValType) -> WasmType {
+        match *ty {
+            ValType::I32 => WasmType::I32,
+            ValType::I64 => WasmType::I64,
+            ValType::F32 => WasmType::F32,
+            ValType::F64 => WasmType::F64,
+            ref other => WasmType::Other(other.to_string()),
+        }
+    }
+    WasmSignature {
+        params: func_ty.params().iter().map(convert).collect(),
+        results: func_ty.results().iter().map(convert).collect(),
+    }
+}
+
+/// Finds the signature `js_fn!` gives a function's export.
+fn expected_signature(info: &JsFnInfo) -> WasmSignature {
+    WasmSignature {
+        params: abi::lowered_params(info)
+            .into_iter()
+            .map(|param| param.ty)
+            .collect(),
+        results: abi::lowered_results(&info.ret_ty),
+    }
+}
+
+fn signature(params: &[WasmType], results: &[WasmType]) -> WasmSignature {
+    WasmSignature {
+        params: params.to_vec(),
+        results: results.to_vec(),
+    }
+}
+
+/// Checks a compiled module's exports against the exports generated
+/// JavaScript for the given functions and imported functions uses.
+pub(crate) fn verify_exports(
+    config: &Config,
+    infos: &[JsFnInfo],
+    imports: &[JsImportInfo],
+    wasm: &[u8],
+) -> Result<Vec<ExportProblem>, Error> {
+    use abi::WasmType::I32;
+
+    let exports = read_exports(wasm)?;
+    let used = used_exports(config, infos, imports);
+
+    let mut expected_functions = Vec::new();
+    for info in &used.functions {
+        expected_functions.push((abi::export_name(info), expected_signature(info)));
+    }
+    for self_ty in &used.self_types {
+        expected_functions.push((abi::drop_export_name(self_ty), signature(&[I32], &[])));
+    }
+    if used.memory {
+        expected_functions.push((abi::builtin_export_name("alloc"), signature(&[I32], &[I32])));
+        expected_functions.push((
+            abi::builtin_export_name("dealloc"),
+            signature(&[I32, I32], &[]),
+        ));
+    }
+    if used.scratch_arena {
+        expected_functions.push((
+            abi::builtin_export_name("scratch_grow"),
+            signature(&[I32], &[I32]),
+        ));
+    }
+    if used.panic_builtins {
+        for builtin in &["panic_message", "panic_location"] {
+            expected_functions.push((abi::builtin_export_name(builtin), signature(&[], &[I32])));
+        }
+    }
+
+    let mut problems = Vec::new();
+
+    if used.memory {
+        match exports.get("memory") {
+            Some(&ExportedItem::Memory) => {}
+            Some(_) => problems.push(ExportProblem::WrongKind {
+                name: "memory".to_owned(),
+                expected: "memory",
+            }),
+            None => problems.push(ExportProblem::Missing {
+                name: "memory".to_owned(),
+            }),
+        }
+    }
+
+    if used.strings {
+        // only exported with the "utf8-strings" feature.
+        let module_uses_utf8 = exports.contains_key(&abi::builtin_export_name("utf8_strings"));
+        let wrapper_uses_utf8 = config.string_encoding == StringEncoding::Utf8;
+        if module_uses_utf8 != wrapper_uses_utf8 {
+            problems.push(ExportProblem::StringEncodingMismatch {
+                expected: config.string_encoding,
+            });
+        }
+    }
+
+    for (name, expected) in &expected_functions {
+        match exports.get(name) {
+            Some(ExportedItem::Function(found)) => if found != expected {
+                problems.push(ExportProblem::SignatureMismatch {
+                    name: name.clone(),
+                    expected: expected.clone(),
+                    found: found.clone(),
+                });
+            },
+            Some(_) => problems.push(ExportProblem::WrongKind {
+                name: name.clone(),
+                expected: "function",
+            }),
+            None => problems.push(ExportProblem::Missing { name: name.clone() }),
+        }
+    }
+
+    let mut stale = exports
+        .keys()
+        .filter(|name| {
+            name.starts_with(EXPORT_PREFIX) && !name.starts_with(BUILTIN_EXPORT_PREFIX)
+                && !expected_functions.iter().any(|(expected, _)| expected == *name)
+        })
+        .collect::<Vec<_>>();
+    // hash map order isn't stable.
+    stale.sort();
+    problems.extend(stale.into_iter().map(|name| ExportProblem::Stale { name: name.clone() }));
+
+    Ok(problems)
+}
+
+#[cfg(test)]
+mod tests {
+    use wasm_wrapper_gen_shared::{JsFnInfo, JsImportInfo};
+
+    use abi::WasmType::{F32, F64, I32, I64};
+    use test_util::translate_source;
+
+    use super::*;
+
+    const FUNC: u8 = 0x00;
+    const MEMORY: u8 = 0x02;
+
+    /// Assembles a WebAssembly module by hand, since there's no text format
+    /// parser available.
+    #[derive(Default)]
+    struct ModuleBuilder {
+        types: Vec<WasmSignature>,
+        imported_functions: Vec<u32>,
+        functions: Vec<u32>,
+        memory: bool,
+        exports: Vec<(String, u8, u32)>,
+    }
+
+    impl ModuleBuilder {
+        fn add_type(&mut self, signature: WasmSignature) -> u32 {
+            self.types.push(signature);
+            self.types.len() as u32 - 1
+        }
+
+        /// Imports a function, which must be done before defining any.
+        fn import(&mut self, signature: WasmSignature) -> &mut Self {
+            assert!(self.functions.is_empty());
+            let type_index = self.add_type(signature);
+            self.imported_functions.push(type_index);
+            self
+        }
+
+        fn function(&mut self, name: &str, signature: WasmSignature) -> &mut Self {
+            let type_index = self.add_type(signature);
+            self.functions.push(type_index);
+            let index = self.imported_functions.len() + self.functions.len() - 1;
+            self.exports.push((name.to_owned(), FUNC, index as u32));
+            self
+        }
+
+        fn memory(&mut self, name: &str) -> &mut Self {
+            self.memory = true;
+            self.exports.push((name.to_owned(), MEMORY, 0));
+            self
+        }
+
+        fn build(&self) -> Vec<u8> {
+            let mut module = b"\0asm\x01\0\0\0".to_vec();
+
+            section(&mut module, 1, &self.types, |buf, signature| {
+                buf.push(0x60);
+                vec(buf, &signature.params, |buf, ty| buf.push(value_type(ty)));
+                vec(buf, &signature.results, |buf, ty| buf.push(value_type(ty)));
+            });
+            section(&mut module, 2, &self.imported_functions, |buf, &type_index| {
+                name(buf, "env");
+                name(buf, "imported");
+                buf.push(FUNC);
+                leb128(buf, type_index);
+            });
+            section(&mut module, 3, &self.functions, |buf, &type_index| {
+                leb128(buf, type_index)
+            });
+            let memories = if self.memory { &[1][..] } else { &[] };
+            section(&mut module, 5, memories, |buf, &min_pages| {
+                buf.push(0x00);
+                leb128(buf, min_pages);
+            });
+            section(&mut module, 7, &self.exports, |buf, &(ref export, kind, index)| {
+                name(buf, export);
+                buf.push(kind);
+                leb128(buf, index);
+            });
+            section(&mut module, 10, &self.functions, |buf, &type_index| {
+                // no locals, and a zero constant for each result.
+                let mut body = vec![0x00];
+                for ty in &self.types[type_index as usize].results {
+                    let (opcode, immediate) = match *ty {
+                        I32 => (0x41, &[0][..]),
+                        I64 => (0x42, &[0][..]),
+                        F32 => (0x43, &[0; 4][..]),
+                        _ => (0x44, &[0; 8][..]),
+                    };
+                    body.push(opcode);
+                    body.extend(immediate);
+                }
+                body.push(0x0b);
+                leb128(buf, body.len() as u32);
+                buf.extend(body);
+            });
+
+            ::wasmparser::validate(&module).expect("built an invalid module");
+            module
+        }
+    }
+
+    fn leb128(buf: &mut Vec<u8>, mut value: u32) {
+        loop {
+            let byte = (value & 0x7f) as u8;
+            value >>= 7;
+            if value == 0 {
+                buf.push(byte);
+                return;
+            }
+            buf.push(byte | 0x80);
+        }
+    }
+
+    fn name(buf: &mut Vec<u8>, name: &str) {
+        leb128(buf, name.len() as u32);
+        buf.extend(name.as_bytes());
+    }
+
+    fn vec<T, F>(buf: &mut Vec<u8>, items: &[T], mut write_item: F)
+    where
+        F: FnMut(&mut Vec<u8>, &T),
+    {
+        leb128(buf, items.len() as u32);
+        for item in items {
+            write_item(buf, item);
+        }
+    }
+
+    /// Writes a section, unless it would be empty.
+    fn section<T, F>(module: &mut Vec<u8>, id: u8, items: &[T], write_item: F)
+    where
+        F: FnMut(&mut Vec<u8>, &T),
+    {
+        if items.is_empty() {
+            return;
+        }
+        let mut contents = Vec::new();
+        vec(&mut contents, items, write_item);
+        module.push(id);
+        leb128(module, contents.len() as u32);
+        module.extend(contents);
+    }
+
+    fn value_type(ty: &WasmType) -> u8 {
+        match *ty {
+            I32 => 0x7f,
+            I64 => 0x7e,
+            F32 => 0x7d,
+            F64 => 0x7c,
+            WasmType::Other(_) => panic!("unsupported type"),
+        }
+    }
+
+    const SOURCE: &str = "
+        js_fn! {
+            fn add(a: u32, b: u32) -> u32 => add;
+            fn shout(text: &str) -> String => shout;
+        }
+    ";
+
+    fn translate() -> (Vec<JsFnInfo>, Vec<JsImportInfo>) {
+        translate_source(SOURCE).unwrap()
+    }
+
+    fn builtin(name: &str) -> String {
+        abi::builtin_export_name(name)
+    }
+
+    /// Builds a module exporting everything the wrapper for `SOURCE` uses,
+    /// except for the functions named in `skip`.
+    fn matching_module(infos: &[JsFnInfo], skip: &[&str]) -> ModuleBuilder {
+        let mut module = ModuleBuilder::default();
+        for info in infos {
+            if !skip.contains(&&*info.rust_name) {
+                module.function(&abi::export_name(info), expected_signature(info));
+            }
+        }
+        module
+            .function(&builtin("alloc"), signature(&[I32], &[I32]))
+            .function(&builtin("dealloc"), signature(&[I32, I32], &[]));
+        if !skip.contains(&"memory") {
+            module.memory("memory");
+        }
+        module
+    }
+
+    fn verify(config: &Config, wasm: &[u8]) -> Vec<ExportProblem> {
+        let (infos, imports) = translate();
+        verify_exports(config, &infos, &imports, wasm).unwrap()
+    }
+
+    #[test]
+    fn matching_exports() {
+        let (infos, _) = translate();
+        assert_eq!(expected_signature(&infos[0]), signature(&[I32, I32], &[I32]));
+        let wasm = matching_module(&infos, &[])
+            // builtins the wrapper doesn't use are fine.
+            .function(&builtin("panic_message"), signature(&[], &[I32]))
+            .function("unrelated", signature(&[F64], &[I64]))
+            .build();
+        assert_eq!(verify(&Config::new(), &wasm), vec![]);
+    }
+
+    #[test]
+    fn missing_exports() {
+        let (infos, _) = translate();
+        let wasm = matching_module(&infos, &["add", "memory"]).build();
+        assert_eq!(
+            verify(&Config::new(), &wasm),
+            vec![
+                ExportProblem::Missing {
+                    name: "memory".to_owned(),
+                },
+                ExportProblem::Missing {
+                    name: abi::export_name(&infos[0]),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn stale_exports() {
+        let (infos, _) = translate();
+        let wasm = matching_module(&infos, &[])
+            .function("__js_fn_removed", signature(&[], &[]))
+            .function("__js_fn_also_removed", signature(&[I32], &[]))
+            .build();
+        assert_eq!(
+            verify(&Config::new(), &wasm),
+            vec![
+                ExportProblem::Stale {
+                    name: "__js_fn_also_removed".to_owned(),
+                },
+                ExportProblem::Stale {
+                    name: "__js_fn_removed".to_owned(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn wrong_kinds() {
+        let (infos, _) = translate();
+        let wasm = matching_module(&infos, &["add", "memory"])
+            .function("memory", signature(&[], &[]))
+            .memory(&abi::export_name(&infos[0]))
+            .build();
+        assert_eq!(
+            verify(&Config::new(), &wasm),
+            vec![
+                ExportProblem::WrongKind {
+                    name: "memory".to_owned(),
+                    expected: "memory",
+                },
+                ExportProblem::WrongKind {
+                    name: abi::export_name(&infos[0]),
+                    expected: "function",
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn signature_mismatch() {
+        let (infos, _) = translate();
+        let wasm = matching_module(&infos, &["add"])
+            .function(&abi::export_name(&infos[0]), signature(&[I64, F32], &[]))
+            .build();
+        assert_eq!(
+            verify(&Config::new(), &wasm),
+            vec![
+                ExportProblem::SignatureMismatch {
+                    name: abi::export_name(&infos[0]),
+                    expected: signature(&[I32, I32], &[I32]),
+                    found: signature(&[I64, F32], &[]),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn string_encoding_mismatch() {
+        let (infos, _) = translate();
+        let utf16_module = matching_module(&infos, &[]).build();
+        let utf8_module = matching_module(&infos, &[])
+            .function(&builtin("utf8_strings"), signature(&[], &[]))
+            .build();
+        let mut utf8 = Config::new();
+        utf8.with_string_encoding(StringEncoding::Utf8);
+
+        assert_eq!(verify(&utf8, &utf8_module), vec![]);
+        assert_eq!(
+            verify(&Config::new(), &utf8_module),
+            vec![
+                ExportProblem::StringEncodingMismatch {
+                    expected: StringEncoding::Utf16,
+                },
+            ]
+        );
+        assert_eq!(
+            verify(&utf8, &utf16_module),
+            vec![
+                ExportProblem::StringEncodingMismatch {
+                    expected: StringEncoding::Utf8,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn imported_functions_come_first() {
+        let wasm = ModuleBuilder::default()
+            .import(signature(&[F64], &[]))
+            .function("defined", signature(&[I32], &[I64]))
+            .build();
+        let exports = read_exports(&wasm).unwrap();
+        match exports.get("defined") {
+            Some(ExportedItem::Function(found)) => {
+                assert_eq!(*found, signature(&[I32], &[I64]))
+            }
+            _ => panic!("expected a function export"),
+        }
+    }
+
+    #[test]
+    fn invalid_module() {
+        assert!(read_exports(b"\0asm\x01\0\0\0\x07\x05").is_err());
+        assert!(read_exports(b"not wasm").is_err());
+    }
+}
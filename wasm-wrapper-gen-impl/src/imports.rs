@@ -0,0 +1,327 @@
+//! Generation of the rust side of `js_import!`: an `extern` declaration of
+//! each function JavaScript provides, and a safe wrapper lowering arguments
+//! to what the generated JavaScript expects.
+
+use failure::{Error, ResultExt};
+
+use quote;
+use syn;
+
+use wasm_wrapper_gen_shared::{import_fn_decl, import_fn_name,
+                              transform_import_macro_input_to_items, JsImportInfo,
+                              SupportedArgumentType, SupportedRetType};
+
+use ConstructedArgIdent;
+
+pub fn process_all_imports(input: &str) -> Result<String, Error> {
+    let token_trees = syn::parse_token_trees(input)
+        .map_err(|e| format_err!("failed to parse macro input as an item: {}", e))?;
+
+    let items = transform_import_macro_input_to_items(token_trees)?;
+
+    let mut full_out = quote::Tokens::new();
+    for item in &items {
+        let output = generate_import_wrapper(item)
+            .with_context(|e| format!("failed to process function '{:?}': {}", item, e))?;
+
+        full_out.append(output);
+    }
+    Ok(full_out.to_string())
+}
+
+fn generate_import_wrapper(item: &syn::ForeignItem) -> Result<quote::Tokens, Error> {
+    let info = JsImportInfo::from_item(item)?;
+    let decl = import_fn_decl(item)?;
+
+    let argument_names = (0..info.args_ty.len() as u32)
+        .map(|index| ConstructedArgIdent::new("__arg", index))
+        .collect::<Vec<_>>();
+
+    let mut wrapper_arguments = quote::Tokens::new();
+    let mut import_arguments = quote::Tokens::new();
+    let mut setup = quote::Tokens::new();
+    let mut passed_arguments = Vec::new();
+    for ((input, ty), arg_name) in decl.inputs.iter().zip(&info.args_ty).zip(&argument_names) {
+        let written_ty = match *input {
+            syn::FnArg::Captured(_, ref ty) | syn::FnArg::Ignored(ref ty) => ty,
+            _ => bail!("expected regular function parameter, found '{:?}'", input),
+        };
+        wrapper_arguments.append(quote! { #arg_name: #written_ty, });
+        lower_import_argument(
+            arg_name,
+            ty,
+            &mut import_arguments,
+            &mut setup,
+            &mut passed_arguments,
+        );
+    }
+
+    let wrapper_ret = match decl.output {
+        syn::FunctionRetTy::Default => quote::Tokens::new(),
+        syn::FunctionRetTy::Ty(ref ty) => quote! { -> #ty },
+    };
+
+    let import_ident = syn::Ident::new(import_fn_name(&item.ident));
+    let passed_arguments = &passed_arguments;
+    let (import_ret, call) = match info.ret_ty {
+        SupportedRetType::Unit => (
+            quote::Tokens::new(),
+            quote! { unsafe { #import_ident(#(#passed_arguments),*) } },
+        ),
+        SupportedRetType::Integer(int_ty) => (
+            quote! { -> #int_ty },
+            quote! { unsafe { #import_ident(#(#passed_arguments),*) } },
+        ),
+        // JavaScript allocates strings and writes where they are into a slot.
+        ref ret_ty => {
+            import_arguments.append(quote! { __return_slot: *mut usize, });
+            let take_result = match *ret_ty {
+                SupportedRetType::Json => quote! { __js_fn_import_json_result },
+                _ => quote! { __js_fn_import_string_result },
+            };
+            (
+                quote::Tokens::new(),
+                quote! {
+                    let mut __return_slot = [0usize; 3];
+                    unsafe {
+                        #import_ident(#(#passed_arguments,)* __return_slot.as_mut_ptr());
+                        ::wasm_wrapper_gen::#take_result(__return_slot)
+                    }
+                },
+            )
+        }
+    };
+
+    let ident = &item.ident;
+    let vis = &item.vis;
+    let attrs = &item.attrs;
+    Ok(quote! {
+        #[allow(non_snake_case)]
+        extern "C" {
+            fn #import_ident(#import_arguments) #import_ret;
+        }
+
+        #(#attrs)*
+        #vis fn #ident(#wrapper_arguments) #wrapper_ret {
+            #setup
+            #call
+        }
+    })
+}
+
+/// Writes an argument's parameters in the imported function, any setup
+/// converting it, and the values passed for it.
+fn lower_import_argument(
+    arg_name: &ConstructedArgIdent,
+    ty: &SupportedArgumentType,
+    import_arguments: &mut quote::Tokens,
+    setup: &mut quote::Tokens,
+    passed_arguments: &mut Vec<quote::Tokens>,
+) {
+    let ptr_arg_name = arg_name.with_suffix("_ptr");
+    let length_arg_name = arg_name.with_suffix("_len");
+    let encoded_name = arg_name.with_suffix("_encoded");
+    let json_name = arg_name.with_suffix("_json");
+    match *ty {
+        SupportedArgumentType::Integer(int_ty) => {
+            import_arguments.append(quote! { #arg_name: #int_ty, });
+            passed_arguments.push(quote! { #arg_name });
+        }
+        SupportedArgumentType::IntegerSliceRef(int_ty)
+        | SupportedArgumentType::IntegerVec(int_ty) => {
+            import_arguments.append(quote! {
+                #ptr_arg_name: *const #int_ty,
+                #length_arg_name: usize,
+            });
+            passed_arguments.push(quote! { #arg_name.as_ptr() });
+            passed_arguments.push(quote! { #arg_name.len() });
+        }
+        // UTF-16 or UTF-8, depending on wasm-wrapper-gen's features.
+        SupportedArgumentType::OwnedString
+        | SupportedArgumentType::StringSlice
+        | SupportedArgumentType::Json => {
+            import_arguments.append(quote! {
+                #ptr_arg_name: *const u8,
+                #length_arg_name: usize,
+            });
+            if *ty == SupportedArgumentType::Json {
+                setup.append(quote! {
+                    let #json_name = ::wasm_wrapper_gen::__js_fn_encode_json(&#arg_name);
+                    let #encoded_name = ::wasm_wrapper_gen::__js_fn_encode_string(&#json_name);
+                });
+            } else {
+                setup.append(quote! {
+                    let #encoded_name = ::wasm_wrapper_gen::__js_fn_encode_string(&#arg_name);
+                });
+            }
+            passed_arguments.push(quote! { #encoded_name.as_ptr() as *const u8 });
+            passed_arguments.push(quote! { #encoded_name.len() });
+        }
+        SupportedArgumentType::Optional(ref inner) => match **inner {
+            // integers are passed alongside a flag for whether they're present.
+            SupportedArgumentType::Integer(int_ty) => {
+                let is_some_arg_name = arg_name.with_suffix("_is_some");
+                let value_arg_name = arg_name.with_suffix("_value");
+                import_arguments.append(quote! {
+                    #is_some_arg_name: bool,
+                    #value_arg_name: #int_ty,
+                });
+                setup.append(quote! {
+                    let (#is_some_arg_name, #value_arg_name) = match #arg_name {
+                        Some(value) => (true, value),
+                        None => (false, ::std::default::Default::default()),
+                    };
+                });
+                passed_arguments.push(quote! { #is_some_arg_name });
+                passed_arguments.push(quote! { #value_arg_name });
+            }
+            SupportedArgumentType::IntegerSliceRef(int_ty)
+            | SupportedArgumentType::IntegerVec(int_ty) => {
+                import_arguments.append(quote! {
+                    #ptr_arg_name: *const #int_ty,
+                    #length_arg_name: usize,
+                });
+                passed_arguments.push(quote! {
+                    #arg_name.as_ref().map_or(::std::ptr::null(), |value| value.as_ptr())
+                });
+                passed_arguments.push(quote! {
+                    #arg_name.as_ref().map_or(0, |value| value.len())
+                });
+            }
+            // everything else is passed as a null pointer when not present.
+            ref inner => {
+                import_arguments.append(quote! {
+                    #ptr_arg_name: *const u8,
+                    #length_arg_name: usize,
+                });
+                if *inner == SupportedArgumentType::Json {
+                    setup.append(quote! {
+                        let #json_name = #arg_name
+                            .as_ref()
+                            .map(|value| ::wasm_wrapper_gen::__js_fn_encode_json(value));
+                        let #encoded_name = #json_name
+                            .as_ref()
+                            .map(|json| ::wasm_wrapper_gen::__js_fn_encode_string(json));
+                    });
+                } else {
+                    setup.append(quote! {
+                        let #encoded_name = #arg_name
+                            .as_ref()
+                            .map(|value| ::wasm_wrapper_gen::__js_fn_encode_string(value));
+                    });
+                }
+                passed_arguments.push(quote! {
+                    #encoded_name
+                        .as_ref()
+                        .map_or(::std::ptr::null(), |encoded| encoded.as_ptr() as *const u8)
+                });
+                passed_arguments.push(quote! {
+                    #encoded_name.as_ref().map_or(0, |encoded| encoded.len())
+                });
+            }
+        },
+        // rejected by `import_argument_type`.
+        SupportedArgumentType::IntegerSliceMutRef(_)
+        | SupportedArgumentType::JsString
+        | SupportedArgumentType::Callback(..) => {}
+    }
+}
+
+/// Generates the setup turning the index of a JavaScript callback into a
+/// `&dyn Fn` calling it through the callback builtin.
+///
+/// Arguments are lowered like arguments of imported functions, then each
+/// value is stored in an 8-byte slot, see `__JsCallbackSlot`.
+pub fn setup_for_callback(
+    arg_name: &ConstructedArgIdent,
+    args_ty: &[SupportedArgumentType],
+    ret_ty: &SupportedRetType,
+) -> quote::Tokens {
+    let callback_name = arg_name.with_suffix("_callback");
+    let closure_arguments = (0..args_ty.len() as u32)
+        .map(|index| ConstructedArgIdent::new("__callback_arg", index))
+        .collect::<Vec<_>>();
+
+    let mut setup = quote::Tokens::new();
+    let mut passed_arguments = Vec::new();
+    for (closure_arg, ty) in closure_arguments.iter().zip(args_ty) {
+        lower_import_argument(
+            closure_arg,
+            ty,
+            &mut quote::Tokens::new(),
+            &mut setup,
+            &mut passed_arguments,
+        );
+    }
+
+    let take_result = match *ret_ty {
+        SupportedRetType::Unit => quote! { let _ = __return_slot; },
+        SupportedRetType::Integer(_) => quote! {
+            ::wasm_wrapper_gen::__JsCallbackSlot::__from_slot(__return_slot[0])
+        },
+        // JavaScript allocates strings and writes where they are into the slot.
+        ref ret_ty => {
+            let take_string = match *ret_ty {
+                SupportedRetType::Json => quote! { __js_fn_import_json_result },
+                _ => quote! { __js_fn_import_string_result },
+            };
+            quote! {
+                unsafe {
+                    ::wasm_wrapper_gen::#take_string(
+                        ::wasm_wrapper_gen::__js_fn_callback_string_slot(__return_slot),
+                    )
+                }
+            }
+        }
+    };
+
+    let arg_types = args_ty.iter().map(RustArgumentType).collect::<Vec<_>>();
+    let closure_params = closure_arguments
+        .iter()
+        .zip(&arg_types)
+        .map(|(name, ty)| quote! { #name: #ty });
+    quote! {
+        let #callback_name = move |#(#closure_params),*| -> #ret_ty {
+            #setup
+            let __args = [
+                #(::wasm_wrapper_gen::__JsCallbackSlot::__into_slot(#passed_arguments)),*
+            ];
+            let __return_slot = ::wasm_wrapper_gen::__js_fn_call_callback(#arg_name, &__args);
+            #take_result
+        };
+        let #arg_name: &dyn Fn(#(#arg_types),*) -> #ret_ty = &#callback_name;
+    }
+}
+
+/// The rust type of an argument of an imported function or callback.
+struct RustArgumentType<'a>(&'a SupportedArgumentType);
+
+impl<'a> quote::ToTokens for RustArgumentType<'a> {
+    fn to_tokens(&self, tokens: &mut quote::Tokens) {
+        match *self.0 {
+            SupportedArgumentType::Integer(int_ty) => int_ty.to_tokens(tokens),
+            SupportedArgumentType::IntegerSliceRef(int_ty) => {
+                tokens.append(quote! { &[#int_ty] });
+            }
+            SupportedArgumentType::IntegerSliceMutRef(int_ty) => {
+                tokens.append(quote! { &mut [#int_ty] });
+            }
+            SupportedArgumentType::IntegerVec(int_ty) => tokens.append(quote! { Vec<#int_ty> }),
+            SupportedArgumentType::OwnedString => tokens.append("String"),
+            SupportedArgumentType::StringSlice => tokens.append("&str"),
+            SupportedArgumentType::JsString => {
+                tokens.append(quote! { ::wasm_wrapper_gen::JsString });
+            }
+            // the serialized type is left to be inferred.
+            SupportedArgumentType::Json => tokens.append(quote! { ::wasm_wrapper_gen::Json<_> }),
+            SupportedArgumentType::Optional(ref inner) => {
+                let inner = RustArgumentType(inner);
+                tokens.append(quote! { Option<#inner> });
+            }
+            SupportedArgumentType::Callback(ref args_ty, ref ret_ty) => {
+                let args_ty = args_ty.iter().map(RustArgumentType);
+                tokens.append(quote! { &dyn Fn(#(#args_ty),*) -> #ret_ty });
+            }
+        }
+    }
+}
@@ -10,11 +10,14 @@ extern crate syn;
 
 extern crate wasm_wrapper_gen_shared;
 
+mod imports;
+
 use failure::{Error, ResultExt};
 
-use wasm_wrapper_gen_shared::{extract_func_info, get_argument_types, get_ret_type,
-                              transform_macro_input_to_items, SupportedArgumentType,
-                              SupportedRetType, TransformedRustIdent};
+use wasm_wrapper_gen_shared::{drop_fn_name, extract_fn_definitions, qualified_fn_name,
+                              transform_macro_input_to_items, FnDefinition,
+                              SupportedArgumentType, SupportedRetType, SupportedSelfArgument,
+                              TransformedRustIdent};
 
 
 #[derive(Debug, Clone)]
@@ -60,6 +63,15 @@ proc_macro_item_impl! {
             }
         }
     }
+
+    pub fn __js_import_impl(input: &str) -> String {
+        match imports::process_all_imports(input) {
+            Ok(v) => v,
+            Err(e) => {
+                panic!("js_import macro failed: {}", e);
+            }
+        }
+    }
 }
 
 fn process_all_functions(input: &str) -> Result<String, Error> {
@@ -69,32 +81,48 @@ fn process_all_functions(input: &str) -> Result<String, Error> {
     let ast = transform_macro_input_to_items(token_trees)?;
 
     let mut full_out = quote::Tokens::new();
+    // types with impl blocks, which each need one drop function no matter how
+    // many blocks they have.
+    let mut self_tys = Vec::new();
     for item in &ast {
-        let output = process_item(item)
+        let output = process_item(item, &mut self_tys)
             .with_context(|e| format!("failed to process function '{:?}': {}", item, e))?;
 
         full_out.append(output);
     }
+    for self_ty in &self_tys {
+        full_out.append(generate_drop_function(self_ty));
+    }
     Ok(full_out.to_string())
 }
 
-fn process_item(item: &syn::Item) -> Result<quote::Tokens, Error> {
-    let (item, decl, block) = extract_func_info(item)?;
+fn process_item<'a>(
+    item: &'a syn::Item,
+    self_tys: &mut Vec<&'a syn::Ident>,
+) -> Result<quote::Tokens, Error> {
+    let definitions = extract_fn_definitions(item)?;
 
-    let out = generate_function_wrapper(item, decl, block)?;
+    let mut out = quote::Tokens::new();
+    for definition in &definitions {
+        out.append(generate_function_wrapper(definition)?);
+    }
+
+    // all definitions in an impl block share the same self type.
+    if let Some(self_ty) = definitions.first().and_then(|d| d.self_ty) {
+        if !self_tys.contains(&self_ty) {
+            self_tys.push(self_ty);
+        }
+    }
 
     Ok(out)
 }
 
-fn generate_function_wrapper(
-    item: &syn::Item,
-    decl: &syn::FnDecl,
-    code: &syn::Block,
-) -> Result<quote::Tokens, Error> {
-    let callable_body = generate_callable_body(item, decl, code)?;
+fn generate_function_wrapper(definition: &FnDefinition) -> Result<quote::Tokens, Error> {
+    let callable_body = generate_callable_body(definition)?;
 
-    let argument_types = get_argument_types(decl)?;
-    let ret_ty = get_ret_type(decl)?;
+    let self_arg = definition.self_argument();
+    let argument_types = definition.argument_types()?;
+    let ret_ty = definition.ret_type()?;
 
     let argument_names = (0..argument_types.len() as u32)
         .map(|index| ConstructedArgIdent::new("__arg", index))
@@ -102,39 +130,83 @@ fn generate_function_wrapper(
 
     let mut function_body = quote::Tokens::new();
 
+    // does nothing unless wasm-wrapper-gen's "panic-messages" feature is enabled.
+    function_body.append(quote! {
+        ::wasm_wrapper_gen::__js_fn_install_panic_hook();
+    });
+
+    if let (Some(self_arg), Some(self_ty)) = (self_arg, definition.self_ty) {
+        function_body.append(setup_for_self_argument(self_arg, self_ty));
+    }
+
     for (ty, arg_name) in argument_types.iter().zip(&argument_names) {
         function_body.append(setup_for_argument(&arg_name, ty)?);
     }
 
     let mut arg_names_as_argument_list = quote::Tokens::new();
+    if self_arg.is_some() {
+        arg_names_as_argument_list.append(quote! { __self, });
+    }
     for arg_name in &argument_names {
         arg_names_as_argument_list.append(quote! { #arg_name, });
     }
 
+    let ret_ty_written = RustReturnType(&ret_ty, definition.self_ty);
+
     function_body.append(quote! {
-        let result: #ret_ty = (#callable_body)(#arg_names_as_argument_list);
+        let result: #ret_ty_written = (#callable_body)(#arg_names_as_argument_list);
     });
 
     function_body.append(return_handling(&ret_ty)?);
 
-    let func_ident = TransformedRustIdent::new(&item.ident);
+    let func_ident = TransformedRustIdent::new(qualified_fn_name(
+        definition.self_ty,
+        definition.ident,
+    ));
 
     let mut real_arguments_list = quote::Tokens::new();
+    if let (Some(_), Some(self_ty)) = (self_arg, definition.self_ty) {
+        real_arguments_list.append(quote! {
+            __self_ptr: *mut #self_ty,
+        });
+    }
     for (ty, arg_name) in argument_types.iter().zip(&argument_names) {
         expand_argument_into(arg_name, ty, &mut real_arguments_list)?;
     }
 
-    let ret_def = WrittenReturnType(ret_ty);
+    let ret_def = WrittenReturnType(&ret_ty, definition.self_ty);
 
     let full_definition = quote! {
         #[no_mangle]
         #[doc(hidden)]
+        #[allow(non_snake_case)]
         pub extern "C" fn #func_ident (#real_arguments_list) #ret_def {
             #function_body
         }
     };
 
-    Ok(full_definition)
+    match generate_inline_method(definition) {
+        Some(inline_method) => Ok(quote! {
+            #inline_method
+            #full_definition
+        }),
+        None => Ok(full_definition),
+    }
+}
+
+/// Generates the function JavaScript calls to free a boxed value of an
+/// `impl` block's type.
+fn generate_drop_function(self_ty: &syn::Ident) -> quote::Tokens {
+    let func_ident = TransformedRustIdent::new(drop_fn_name(self_ty));
+
+    quote! {
+        #[no_mangle]
+        #[doc(hidden)]
+        #[allow(non_snake_case)]
+        pub extern "C" fn #func_ident (ptr: *mut #self_ty) {
+            ::std::mem::drop(unsafe { ::std::boxed::Box::from_raw(ptr) });
+        }
+    }
 }
 
 fn expand_argument_into(
@@ -160,41 +232,128 @@ fn expand_argument_into(
                 #length_arg_name: usize,
             });
         }
-        SupportedArgumentType::OwnedString => {
+        SupportedArgumentType::OwnedString
+        | SupportedArgumentType::StringSlice
+        | SupportedArgumentType::Json => {
+            // UTF-16 or UTF-8, depending on wasm-wrapper-gen's features.
             let ptr_arg_name = arg_name.with_suffix("_ptr");
             let length_arg_name = arg_name.with_suffix("_len");
             tokens.append(quote! {
-                #ptr_arg_name: *mut u16,
+                #ptr_arg_name: *const u8,
+                #length_arg_name: usize,
+            })
+        }
+        SupportedArgumentType::JsString => {
+            // always UTF-16, regardless of the string encoding.
+            let ptr_arg_name = arg_name.with_suffix("_ptr");
+            let length_arg_name = arg_name.with_suffix("_len");
+            tokens.append(quote! {
+                #ptr_arg_name: *const u16,
                 #length_arg_name: usize,
             })
         }
         SupportedArgumentType::Integer(int_ty) => tokens.append(quote! {
             #arg_name: #int_ty,
         }),
+        // the index the callback is registered at in JavaScript.
+        SupportedArgumentType::Callback(..) => tokens.append(quote! {
+            #arg_name: usize,
+        }),
+        SupportedArgumentType::Optional(ref inner) => {
+            // integers have a separate flag for whether or not they're present,
+            // everything else is passed as a null pointer when not present.
+            if let SupportedArgumentType::Integer(_) = **inner {
+                let is_some_arg_name = arg_name.with_suffix("_is_some");
+                tokens.append(quote! {
+                    #is_some_arg_name: bool,
+                });
+            }
+            expand_argument_into(arg_name, inner, tokens)?;
+        }
     }
 
     Ok(())
 }
 
-struct WrittenReturnType(SupportedRetType);
+/// The rust type of a return value, with `Self` replaced by the impl block's
+/// type since the exported function isn't inside the impl block.
+struct RustReturnType<'a>(&'a SupportedRetType, Option<&'a syn::Ident>);
 
-impl quote::ToTokens for WrittenReturnType {
+impl<'a> quote::ToTokens for RustReturnType<'a> {
     fn to_tokens(&self, tokens: &mut quote::Tokens) {
-        match self.0 {
+        match *self.0 {
+            SupportedRetType::OwnedSelf => {
+                let self_ty = self.1;
+                tokens.append(quote! { #self_ty });
+            }
+            SupportedRetType::Optional(ref inner) => {
+                let inner = RustReturnType(inner, self.1);
+                tokens.append(quote! { Option<#inner> });
+            }
+            SupportedRetType::Result(ref inner) => {
+                let inner = RustReturnType(inner, self.1);
+                tokens.append(quote! { Result<#inner, _> });
+            }
+            SupportedRetType::Tuple(ref elements, _) => {
+                let elements = elements
+                    .iter()
+                    .map(|element| RustReturnType(element, self.1));
+                tokens.append(quote! { (#(#elements,)*) });
+            }
+            ref other => other.to_tokens(tokens),
+        }
+    }
+}
+
+struct WrittenReturnType<'a>(&'a SupportedRetType, Option<&'a syn::Ident>);
+
+impl<'a> quote::ToTokens for WrittenReturnType<'a> {
+    fn to_tokens(&self, tokens: &mut quote::Tokens) {
+        match *self.0 {
             SupportedRetType::Unit => (),
             SupportedRetType::Integer(int_ty) => {
                 tokens.append(quote! { -> #int_ty });
             }
-            SupportedRetType::IntegerVec(_) => {
+            SupportedRetType::IntegerVec(_) | SupportedRetType::Buffer(_) => {
                 tokens.append(quote! { -> *const usize });
             }
-            SupportedRetType::StringSlice| SupportedRetType::OwnedString => {
-                tokens.append(quote! { -> *const usize })
+            SupportedRetType::StringSlice
+            | SupportedRetType::OwnedString
+            | SupportedRetType::Json => tokens.append(quote! { -> *const usize }),
+            SupportedRetType::OwnedSelf => {
+                let self_ty = self.1;
+                tokens.append(quote! { -> *mut #self_ty })
+            }
+            SupportedRetType::Optional(ref inner) => match **inner {
+                // present integers are boxed, with null meaning not present.
+                SupportedRetType::Integer(int_ty) => {
+                    tokens.append(quote! { -> *mut #int_ty });
+                }
+                ref inner => WrittenReturnType(inner, self.1).to_tokens(tokens),
+            },
+            // results are a boxed [discriminant, payload] pair of usizes, and
+            // tuples a boxed array of payloads.
+            SupportedRetType::Result(_) | SupportedRetType::Tuple(..) => {
+                tokens.append(quote! { -> *const usize });
             }
         }
     }
 }
 
+fn setup_for_self_argument(self_arg: SupportedSelfArgument, self_ty: &syn::Ident) -> quote::Tokens {
+    match self_arg {
+        SupportedSelfArgument::Ref => quote! {
+            let __self: &#self_ty = unsafe { &*__self_ptr };
+        },
+        SupportedSelfArgument::MutRef => quote! {
+            let __self: &mut #self_ty = unsafe { &mut *__self_ptr };
+        },
+        SupportedSelfArgument::Value => quote! {
+            let __self: #self_ty = *unsafe { ::std::boxed::Box::from_raw(__self_ptr) };
+        },
+    }
+}
+
 fn setup_for_argument(
     arg_name: &ConstructedArgIdent,
     ty: &SupportedArgumentType,
@@ -233,15 +392,92 @@ fn setup_for_argument(
         SupportedArgumentType::OwnedString => {
             let ptr_arg_name = arg_name.with_suffix("_ptr");
             let length_arg_name = arg_name.with_suffix("_len");
+            // the string is copied, with JavaScript freeing the original. lone
+            // surrogates are replaced, unless JavaScript rejects them first
+            // (see `StringValidation` in wasm-wrapper-gen-build).
             quote! {
-                let #arg_name: String = ::std::string::String::from_utf16_lossy(&unsafe {
-                    ::std::vec::Vec::<u16>::from_raw_parts(
-                        #ptr_arg_name,
-                        #length_arg_name,
-                        #length_arg_name
-                    )
-                });
-                // TODO: configure non-lossy UTF16 handling (maybe through accepting Result? or erroring?)
+                let #arg_name: String = unsafe {
+                    ::wasm_wrapper_gen::__js_fn_string_argument(#ptr_arg_name, #length_arg_name)
+                };
+            }
+        }
+        SupportedArgumentType::StringSlice => {
+            let ptr_arg_name = arg_name.with_suffix("_ptr");
+            let length_arg_name = arg_name.with_suffix("_len");
+            let owned_arg_name = arg_name.with_suffix("_owned");
+            // borrows from JavaScript's allocation, which is freed after the call.
+            quote! {
+                let #owned_arg_name = unsafe {
+                    ::wasm_wrapper_gen::__js_fn_str_argument(#ptr_arg_name, #length_arg_name)
+                };
+                let #arg_name: &str = &#owned_arg_name;
+            }
+        }
+        SupportedArgumentType::JsString => {
+            let ptr_arg_name = arg_name.with_suffix("_ptr");
+            let length_arg_name = arg_name.with_suffix("_len");
+            quote! {
+                let #arg_name: ::wasm_wrapper_gen::JsString = unsafe {
+                    ::wasm_wrapper_gen::__js_fn_js_string_argument(#ptr_arg_name, #length_arg_name)
+                };
+            }
+        }
+        SupportedArgumentType::Json => {
+            let ptr_arg_name = arg_name.with_suffix("_ptr");
+            let length_arg_name = arg_name.with_suffix("_len");
+            // the deserialized type is left to be inferred.
+            quote! {
+                let #arg_name: ::wasm_wrapper_gen::Json<_> = unsafe {
+                    ::wasm_wrapper_gen::__js_fn_json_argument(#ptr_arg_name, #length_arg_name)
+                };
+            }
+        }
+        SupportedArgumentType::Callback(ref args_ty, ref ret_ty) => {
+            imports::setup_for_callback(arg_name, args_ty, ret_ty)
+        }
+        SupportedArgumentType::Optional(ref inner)
+            if **inner == SupportedArgumentType::StringSlice =>
+        {
+            let ptr_arg_name = arg_name.with_suffix("_ptr");
+            let length_arg_name = arg_name.with_suffix("_len");
+            let owned_arg_name = arg_name.with_suffix("_owned");
+            // the string has to outlive the `if`, so it can't use the inner setup.
+            quote! {
+                let #owned_arg_name = if #ptr_arg_name.is_null() {
+                    None
+                } else {
+                    Some(unsafe {
+                        ::wasm_wrapper_gen::__js_fn_str_argument(#ptr_arg_name, #length_arg_name)
+                    })
+                };
+                let #arg_name: Option<&str> = #owned_arg_name.as_ref().map(|s| &**s);
+            }
+        }
+        SupportedArgumentType::Optional(ref inner) => {
+            let inner_setup = setup_for_argument(arg_name, inner)?;
+            match **inner {
+                SupportedArgumentType::Integer(_) => {
+                    let is_some_arg_name = arg_name.with_suffix("_is_some");
+                    quote! {
+                        let #arg_name = if #is_some_arg_name {
+                            #inner_setup
+                            Some(#arg_name)
+                        } else {
+                            None
+                        };
+                    }
+                }
+                _ => {
+                    let ptr_arg_name = arg_name.with_suffix("_ptr");
+                    quote! {
+                        let #arg_name = if #ptr_arg_name.is_null() {
+                            None
+                        } else {
+                            #inner_setup
+                            Some(#arg_name)
+                        };
+                    }
+                }
             }
         }
     };
@@ -264,16 +500,88 @@ fn return_handling(ty: &SupportedRetType) -> Result<quote::Tokens, Error> {
                 }
             }
         }
+        // returned like the Vec it wraps, but JavaScript frees it.
+        SupportedRetType::Buffer(item_ty) => {
+            let vec_handling = return_handling(&SupportedRetType::IntegerVec(item_ty))?;
+            quote! {
+                {
+                    let result = result.into_inner();
+                    #vec_handling
+                }
+            }
+        }
         SupportedRetType::OwnedString | SupportedRetType::StringSlice => {
             quote! {
-                // TODO: remove duplicate between here and above.
+                ::wasm_wrapper_gen::__js_fn_return_string(result)
+            }
+        }
+        SupportedRetType::OwnedSelf => {
+            quote! {
+                ::std::boxed::Box::into_raw(::std::boxed::Box::new(result))
+            }
+        }
+        SupportedRetType::Json => {
+            quote! {
+                ::wasm_wrapper_gen::__js_fn_return_json(result)
+            }
+        }
+        SupportedRetType::Optional(ref inner) => {
+            let (inner_handling, null) = match **inner {
+                SupportedRetType::Integer(_) | SupportedRetType::OwnedSelf => (
+                    quote! { ::std::boxed::Box::into_raw(::std::boxed::Box::new(result)) },
+                    quote! { ::std::ptr::null_mut() },
+                ),
+                ref inner => (return_handling(inner)?, quote! { ::std::ptr::null() }),
+            };
+            quote! {
+                match result {
+                    Some(result) => #inner_handling,
+                    None => #null,
+                }
+            }
+        }
+        SupportedRetType::Result(ref inner) => {
+            let ok_payload = match **inner {
+                SupportedRetType::Unit => quote! { 0 },
+                ref inner => payload_handling(inner)?,
+            };
+            // the Err payload is the error's message, returned like a String.
+            let err_payload = return_handling(&SupportedRetType::OwnedString)?;
+            quote! {
                 {
-                    let result_utf16: Vec<u16> = result.encode_utf16().collect();
-                    let result_ptr = result_utf16.as_slice().as_ptr() as *mut u16;
-                    let result_len = result_utf16.len();
-                    let result_cap = result_utf16.capacity();
-                    let to_return = Box::new([result_ptr as usize, result_len, result_cap]);
-                    ::std::mem::forget(result_utf16);
+                    let to_return = match result {
+                        Ok(result) => Box::new([1usize, #ok_payload]),
+                        Err(err) => {
+                            let result: String = ::std::string::ToString::to_string(&err);
+                            Box::new([0usize, (#err_payload) as usize])
+                        }
+                    };
+                    ::std::boxed::Box::into_raw(to_return) as *const usize
+                }
+            }
+        }
+        SupportedRetType::Tuple(ref elements, _) => {
+            let element_names = (0..elements.len() as u32)
+                .map(|index| ConstructedArgIdent::new("result_", index))
+                .collect::<Vec<_>>();
+            let element_payloads = elements
+                .iter()
+                .zip(&element_names)
+                .map(|(element, name)| {
+                    let payload = payload_handling(element)?;
+                    Ok(quote! {
+                        {
+                            let result = #name;
+                            #payload
+                        }
+                    })
+                })
+                .collect::<Result<Vec<_>, Error>>()?;
+            let pattern_names = &element_names;
+            quote! {
+                {
+                    let (#(#pattern_names,)*) = result;
+                    let to_return = Box::new([#(#element_payloads,)*]);
                     ::std::boxed::Box::into_raw(to_return) as *const usize
                 }
             }
@@ -283,25 +591,43 @@ fn return_handling(ty: &SupportedRetType) -> Result<quote::Tokens, Error> {
     Ok(tokens)
 }
 
-fn generate_callable_body(
-    _item: &syn::Item,
-    decl: &syn::FnDecl,
-    code: &syn::Block,
-) -> Result<quote::Tokens, Error> {
+/// Converts `result` into a single usize payload, for `Ok` values and tuple
+/// elements: whatever the type would return on its own, with integers and
+/// `self` values boxed so that everything fits in a usize.
+fn payload_handling(ty: &SupportedRetType) -> Result<quote::Tokens, Error> {
+    Ok(match *ty {
+        SupportedRetType::Integer(_) | SupportedRetType::OwnedSelf => quote! {
+            ::std::boxed::Box::into_raw(::std::boxed::Box::new(result)) as usize
+        },
+        ref other => {
+            let handling = return_handling(other)?;
+            quote! { (#handling) as usize }
+        }
+    })
+}
+
+fn generate_callable_body(definition: &FnDefinition) -> Result<quote::Tokens, Error> {
+    let decl = definition.decl;
+    let code = definition.block;
     // we'll see what works best here.
     // This set of if statements is for if we've been given a path to the implementing function.
     //
     // In this case, we want to just call the function at that path with the same arguments the
     // function declaration takes.
-    if let Some(statement) = code.stmts.first() {
-        if let syn::Stmt::Expr(ref inner_expr) = *statement {
-            if let syn::ExprKind::Path(_, _) = inner_expr.node {
-                return Ok(quote! {
-                    // output the path alone so that it can be called like (path::to::func)(args)
-                    (#inner_expr)
-                });
-            }
-        }
+    if let Some(inner_expr) = implementing_path(code) {
+        return Ok(quote! {
+            // output the path alone so that it can be called like (path::to::func)(args)
+            (#inner_expr)
+        });
+    }
+
+    // Closures can't take `self` or refer to `Self`, so inline code inside an
+    // impl block is instead put into a hidden method (see `generate_inline_method`).
+    if let Some(self_ty) = definition.self_ty {
+        let inner_ident = inline_method_ident(definition);
+        return Ok(quote! {
+            (#self_ty::#inner_ident)
+        });
     }
 
     // if it isn't our special case of a path, we can assume the full code
@@ -313,8 +639,56 @@ fn generate_callable_body(
             #input,
         });
     }
+    // the declared return type is kept so that things like `?` can infer their types.
+    let output = declared_output(decl);
     Ok(quote! {
         // syn::Block ToTokens includes '{}' always already.
-        (|#arguments| #code )
+        (|#arguments| #output #code )
+    })
+}
+
+/// Finds the path to the implementing function, if the function was defined
+/// like `fn a() => path::to::func;`.
+fn implementing_path(code: &syn::Block) -> Option<&syn::Expr> {
+    if let Some(statement) = code.stmts.first() {
+        if let syn::Stmt::Expr(ref inner_expr) = *statement {
+            if let syn::ExprKind::Path(_, _) = inner_expr.node {
+                return Some(inner_expr);
+            }
+        }
+    }
+    None
+}
+
+fn declared_output(decl: &syn::FnDecl) -> quote::Tokens {
+    match decl.output {
+        syn::FunctionRetTy::Default => quote::Tokens::new(),
+        syn::FunctionRetTy::Ty(ref ty) => quote! { -> #ty },
+    }
+}
+
+fn inline_method_ident(definition: &FnDefinition) -> syn::Ident {
+    syn::Ident::new(format!("__js_fn_inline_{}", definition.ident))
+}
+
+/// Generates a hidden method holding the inline code of a function inside an
+/// impl block, if it has inline code.
+fn generate_inline_method(definition: &FnDefinition) -> Option<quote::Tokens> {
+    let self_ty = definition.self_ty?;
+    let code = definition.block;
+    if implementing_path(code).is_some() {
+        return None;
+    }
+
+    let inner_ident = inline_method_ident(definition);
+    let inputs = &definition.decl.inputs;
+    let output = declared_output(definition.decl);
+
+    Some(quote! {
+        impl #self_ty {
+            #[doc(hidden)]
+            #[allow(non_snake_case)]
+            fn #inner_ident(#(#inputs),*) #output #code
+        }
     })
 }
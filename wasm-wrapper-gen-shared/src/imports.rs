@@ -0,0 +1,239 @@
+use std::fmt;
+
+use syn;
+use quote::{self, ToTokens};
+
+use MacroError;
+
+use processing::{argument_names, doc_lines};
+use types::{SupportedArgumentType, SupportedRetType};
+
+/// Finds the function declarations inside a `js_import!` invocation.
+pub fn transform_import_mac_to_items(
+    source: syn::Mac,
+) -> Result<Vec<syn::ForeignItem>, MacroError> {
+    if source.tts.len() > 1 {
+        return Err(MacroError::UnexpectedMultiTokenMacro { tokens: source.tts });
+    }
+    match source.tts.into_iter().next() {
+        Some(syn::TokenTree::Delimited(delimited)) => {
+            transform_import_macro_input_to_items(delimited.tts)
+        }
+        Some(syn::TokenTree::Token(t)) => Err(MacroError::UnexpectedSingleToken { token: t }),
+        None => Err(MacroError::UnexpectedMultiTokenMacro { tokens: Vec::new() }),
+    }
+}
+
+/// Parses the declarations inside `js_import!`, which are written just like
+/// the contents of an `extern` block.
+pub fn transform_import_macro_input_to_items(
+    tts: Vec<syn::TokenTree>,
+) -> Result<Vec<syn::ForeignItem>, MacroError> {
+    let mut tokens = quote::Tokens::new();
+    tokens.append("extern {");
+    for tt in &tts {
+        tt.to_tokens(&mut tokens);
+    }
+    tokens.append("}");
+
+    let item = syn::parse_item(tokens.as_ref())
+        .map_err(|desc| MacroError::UnexpectedReparseFailure { err_msg: desc })?;
+    match item.node {
+        syn::ItemKind::ForeignMod(foreign_mod) => Ok(foreign_mod.items),
+        kind => Err(MacroError::InvalidItemKind { kind: Box::new(kind) }),
+    }
+}
+
+/// A function declared in `js_import!`, implemented in JavaScript and called
+/// from rust.
+pub struct JsImportInfo {
+    pub rust_name: String,
+    /// Argument names as written in rust, see `FnDefinition::argument_names`.
+    pub args_names: Vec<String>,
+    pub args_ty: Vec<SupportedArgumentType>,
+    pub ret_ty: SupportedRetType,
+    /// Lines of the doc comment, see `FnDefinition::doc_lines`.
+    pub doc_lines: Vec<String>,
+}
+
+impl JsImportInfo {
+    pub fn from_item(item: &syn::ForeignItem) -> Result<Self, MacroError> {
+        let decl = import_fn_decl(item)?;
+
+        let args_ty = decl.inputs
+            .iter()
+            .map(|input| match *input {
+                syn::FnArg::Captured(_, ref ty) | syn::FnArg::Ignored(ref ty) => {
+                    import_argument_type(ty)
+                }
+                _ => Err(MacroError::InvalidArgument { arg: Box::new(input.clone()) }),
+            })
+            .collect::<Result<_, _>>()?;
+
+        let ret_ty = match decl.output {
+            syn::FunctionRetTy::Default => SupportedRetType::unit(),
+            syn::FunctionRetTy::Ty(ref ty) => import_ret_type(ty)?,
+        };
+
+        Ok(JsImportInfo {
+            rust_name: item.ident.to_string(),
+            args_names: argument_names(&decl.inputs),
+            args_ty,
+            ret_ty,
+            doc_lines: doc_lines(&item.attrs),
+        })
+    }
+}
+
+/// Finds the declaration of a function in `js_import!`, which must be a plain
+/// function without generics or variadic arguments.
+pub fn import_fn_decl(item: &syn::ForeignItem) -> Result<&syn::FnDecl, MacroError> {
+    match item.node {
+        syn::ForeignItemKind::Fn(ref decl, ref generics)
+            if *generics == syn::Generics::default() && !decl.variadic =>
+        {
+            Ok(decl)
+        }
+        _ => Err(MacroError::InvalidImportItem { item: Box::new(item.clone()) }),
+    }
+}
+
+/// Parses an argument type of an imported function or callback. These are
+/// copied into JavaScript values, so mutable slices, `JsString`s and
+/// callbacks aren't supported.
+pub fn import_argument_type(ty: &syn::Ty) -> Result<SupportedArgumentType, MacroError> {
+    let arg_ty = SupportedArgumentType::new(ty)?;
+    let supported = match arg_ty {
+        SupportedArgumentType::Optional(ref inner) => is_supported_import_argument(inner),
+        ref other => is_supported_import_argument(other),
+    };
+    if supported {
+        Ok(arg_ty)
+    } else {
+        Err(MacroError::UnhandledImportArgumentType { ty: ty.clone() })
+    }
+}
+
+fn is_supported_import_argument(ty: &SupportedArgumentType) -> bool {
+    !matches!(
+        *ty,
+        SupportedArgumentType::IntegerSliceMutRef(_)
+            | SupportedArgumentType::JsString
+            | SupportedArgumentType::Optional(_)
+            | SupportedArgumentType::Callback(..)
+    )
+}
+
+/// Parses the return type of an imported function or callback, which is
+/// either nothing, an integer, or allocated by JavaScript.
+pub fn import_ret_type(ty: &syn::Ty) -> Result<SupportedRetType, MacroError> {
+    match SupportedRetType::new(ty)? {
+        ret_ty @ SupportedRetType::Unit
+        | ret_ty @ SupportedRetType::Integer(_)
+        | ret_ty @ SupportedRetType::OwnedString
+        | ret_ty @ SupportedRetType::Json => Ok(ret_ty),
+        _ => Err(MacroError::UnhandledImportRetType { ty: ty.clone() }),
+    }
+}
+
+/// Creates the name of the function rust imports for a function declared in
+/// `js_import!`.
+pub fn import_fn_name<T: fmt::Display>(name: T) -> String {
+    format!("__js_import_{}", name)
+}
+
+/// Name of the function rust imports to call JavaScript callbacks, given the
+/// callback's index and pointers to its arguments and result.
+pub fn callback_import_name() -> String {
+    import_fn_name("_builtin_callback")
+}
+
+#[cfg(test)]
+mod tests {
+    use syn;
+
+    use types::SupportedCopyTy::*;
+
+    use super::*;
+
+    /// Creates info for every function in the body of a `js_import!`
+    /// invocation.
+    fn infos(body: &str) -> Result<Vec<JsImportInfo>, MacroError> {
+        let items = transform_import_macro_input_to_items(syn::parse_token_trees(body).unwrap())?;
+        items.iter().map(JsImportInfo::from_item).collect()
+    }
+
+    fn error(body: &str) -> MacroError {
+        match infos(body) {
+            Ok(_) => panic!("expected an error for {}", body),
+            Err(e) => e,
+        }
+    }
+
+    #[test]
+    fn imports() {
+        let infos = infos(
+            "/// Logs a message.
+             pub fn log(message: &str);
+             fn ask(default: Option<String>, _: Vec<u8>) -> String;
+             fn now() -> u64;",
+        ).unwrap();
+        assert_eq!(infos.len(), 3);
+        assert_eq!(infos[0].rust_name, "log");
+        assert_eq!(infos[0].args_names, ["message"]);
+        assert_eq!(infos[0].args_ty, [SupportedArgumentType::StringSlice]);
+        assert_eq!(infos[0].ret_ty, SupportedRetType::Unit);
+        assert_eq!(infos[0].doc_lines, ["Logs a message."]);
+        assert_eq!(infos[1].args_names, ["default", "arg1"]);
+        assert_eq!(
+            infos[1].args_ty,
+            [
+                SupportedArgumentType::Optional(Box::new(SupportedArgumentType::OwnedString)),
+                SupportedArgumentType::IntegerVec(U8),
+            ]
+        );
+        assert_eq!(infos[1].ret_ty, SupportedRetType::OwnedString);
+        assert_eq!(infos[2].ret_ty, SupportedRetType::Integer(U64));
+    }
+
+    #[test]
+    fn unsupported_arguments() {
+        for body in &[
+            "fn f(x: &mut [u8]);",
+            "fn f(x: JsString);",
+            "fn f(x: Option<JsString>);",
+            "fn f(x: &Fn());",
+        ] {
+            match error(body) {
+                MacroError::UnhandledImportArgumentType { .. } => {}
+                other => panic!("expected an argument type error for {}, found {:?}", body, other),
+            }
+        }
+    }
+
+    #[test]
+    fn unsupported_return_types() {
+        for body in &["fn f() -> Vec<u8>;", "fn f() -> Option<u32>;", "fn f() -> &'static str;"] {
+            match error(body) {
+                MacroError::UnhandledImportRetType { .. } => {}
+                other => panic!("expected a return type error for {}, found {:?}", body, other),
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_items() {
+        for body in &["fn f<T>(x: T);", "static X: u32;"] {
+            match error(body) {
+                MacroError::InvalidImportItem { .. } => {}
+                other => panic!("expected an import item error for {}, found {:?}", body, other),
+            }
+        }
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!(import_fn_name("log"), "__js_import_log");
+        assert_eq!(callback_import_name(), "__js_import__builtin_callback");
+    }
+}
@@ -8,22 +8,67 @@ extern crate syn;
 mod types;
 mod processing;
 mod parsing;
+mod imports;
 
-pub use types::{SupportedArgumentType, SupportedCopyTy, SupportedRetType};
-pub use processing::{extract_func_info, get_argument_types, get_ret_type, JsFnInfo,
-                     TransformedRustIdent};
-pub use parsing::{transform_mac_to_items, transform_macro_input_to_items};
+pub use types::{SupportedArgumentType, SupportedCopyTy, SupportedRetType,
+                SupportedSelfArgument};
+pub use processing::{drop_fn_name, extract_fn_definitions, extract_func_info,
+                     get_argument_types, get_ret_type, get_self_argument, qualified_fn_name,
+                     FnDefinition, JsFnInfo, TransformedRustIdent};
+pub use parsing::{parse_file, strip_dyn_keywords, transform_mac_to_items,
+                  transform_macro_input_to_items};
+pub use imports::{callback_import_name, import_argument_type, import_fn_decl, import_fn_name,
+                  import_ret_type,
+                  transform_import_mac_to_items, transform_import_macro_input_to_items,
+                  JsImportInfo};
 
 #[derive(Debug, Fail)]
 pub enum MacroError {
     #[fail(display = "expected function, found invalid item '{:?}'", kind)]
-    InvalidItemKind { kind: syn::ItemKind },
+    InvalidItemKind { kind: Box<syn::ItemKind> },
+    #[fail(display = "expected impl block for a simple type without generics, found '{:?}'",
+           ty)]
+    InvalidImplType { ty: syn::Ty },
+    #[fail(display = "expected method inside impl block, found invalid item '{:?}'", kind)]
+    InvalidImplItemKind { kind: Box<syn::ImplItemKind> },
+    #[fail(display = "method `{}` can't be exported, since its name is used by the code \
+                      generated for the impl block's type",
+           name)]
+    ReservedMethodName { name: syn::Ident },
     #[fail(display = "expected regular non-self function parameter, found '{:?}'", arg)]
-    InvalidArgument { arg: syn::FnArg },
+    InvalidArgument { arg: Box<syn::FnArg> },
     #[fail(display = "expected one of the supported argument types, found '{:?}", ty)]
     UnhandledArgumentType { ty: syn::Ty },
+    #[fail(display = "`&mut str` arguments are not supported, since strings are copied from \
+                      JavaScript; take `&str` or `String` instead, found '{:?}'",
+           ty)]
+    MutableStringSliceArgument { ty: syn::Ty },
     #[fail(display = "expected one of the supported return types, found '{:?}", ty)]
     UnhandledRetType { ty: syn::Ty },
+    #[fail(display = "expected tuple element to be a supported return type other than (), \
+                      Result or a tuple, found '{:?}'",
+           ty)]
+    UnhandledTupleElement { ty: syn::Ty },
+    #[fail(display = "expected `#[js_return_names(..)]` to list one plain name per tuple \
+                      element, found '{:?}'",
+           attr)]
+    InvalidReturnNames { attr: syn::MetaItem },
+    #[fail(display = "`#[js_return_names(..)]` given for function `{}`, which doesn't return \
+                      a tuple",
+           name)]
+    ReturnNamesWithoutTuple { name: syn::Ident },
+    #[fail(display = "expected plain function declaration `fn a(..) -> ..;` inside \
+                      js_import! macro, found '{:?}'",
+           item)]
+    InvalidImportItem { item: Box<syn::ForeignItem> },
+    #[fail(display = "expected an argument type which can be copied into JavaScript (not \
+                      `&mut [_]`, `JsString` or a callback), found '{:?}'",
+           ty)]
+    UnhandledImportArgumentType { ty: syn::Ty },
+    #[fail(display = "expected imported function or callback to return (), an integer, \
+                      `String` or `Json<_>`, found '{:?}'",
+           ty)]
+    UnhandledImportRetType { ty: syn::Ty },
     #[fail(display = "expected macro to contain a single delimited token tree, found \
                       multiple: {:?}",
            tokens)]
@@ -20,12 +20,63 @@ pub fn transform_mac_to_items(source: syn::Mac) -> Result<Vec<syn::Item>, MacroE
 pub fn transform_macro_input_to_items(
     tts: Vec<syn::TokenTree>,
 ) -> Result<Vec<syn::Item>, MacroError> {
+    split_macro_input(strip_dyn_keywords(tts))?
+        .into_iter()
+        .map(|found| {
+            syn::parse_item(found.as_ref())
+                .map_err(|desc| MacroError::UnexpectedReparseFailure { err_msg: desc })
+        })
+        .collect::<Result<Vec<syn::Item>, MacroError>>()
+}
+
+/// Removes the `dyn` keyword from trait object types, which syn doesn't
+/// understand yet: `&dyn Fn(u32)` is parsed as the equivalent `&Fn(u32)`.
+///
+/// `dyn` is only removed when followed by a path, so that it can still be
+/// used as an identifier.
+pub fn strip_dyn_keywords(tts: Vec<syn::TokenTree>) -> Vec<syn::TokenTree> {
+    let mut stripped = Vec::with_capacity(tts.len());
+    let mut iter = tts.into_iter().peekable();
+    while let Some(token_tree) = iter.next() {
+        match token_tree {
+            Token(syn::Token::Ident(ref ident)) if ident == "dyn" => match iter.peek() {
+                Some(&Token(syn::Token::Ident(_))) | Some(&Token(syn::Token::ModSep)) => {}
+                _ => stripped.push(token_tree.clone()),
+            },
+            Delimited(delimited) => stripped.push(Delimited(syn::Delimited {
+                delim: delimited.delim,
+                tts: strip_dyn_keywords(delimited.tts),
+            })),
+            other => stripped.push(other),
+        }
+    }
+    stripped
+}
+
+/// Parses a whole rust file, accepting `dyn` trait objects anywhere in it
+/// (see `strip_dyn_keywords`).
+pub fn parse_file(source: &str) -> Result<syn::Crate, String> {
+    let mut tokens = quote::Tokens::new();
+    for token_tree in strip_dyn_keywords(syn::parse_token_trees(source)?) {
+        token_tree.to_tokens(&mut tokens);
+    }
+    syn::parse_crate(tokens.as_ref())
+}
+
+/// Splits macro input into the tokens for each complete item, rewriting any
+/// `fn a() => path;` definitions into `fn a() { path }`.
+fn split_macro_input(tts: Vec<syn::TokenTree>) -> Result<Vec<quote::Tokens>, MacroError> {
     let mut found_full = Vec::new();
 
     let mut so_far = quote::Tokens::new();
+    let mut in_impl_header = false;
     let mut iter = tts.into_iter();
     while let Some(token_tree) = iter.next() {
         match token_tree {
+            Token(syn::Token::Ident(ref ident)) if ident == "impl" => {
+                in_impl_header = true;
+                token_tree.to_tokens(&mut so_far);
+            }
             // This matches a definition like:
             // ```
             // fn a() => modname::funcname;
@@ -48,6 +99,29 @@ pub fn transform_macro_input_to_items(
             }
             // This matches a definition like:
             // ```
+            // impl Foo {
+            //     fn a(&self) => Foo::a;
+            // }
+            // ```
+            //
+            // The methods inside are definitions in their own right, so
+            // they're split and rewritten the same way as top-level ones.
+            Delimited(syn::Delimited {
+                delim: DelimToken::Brace,
+                tts: inner_tts,
+            }) if in_impl_header =>
+            {
+                so_far.append("{");
+                for method in split_macro_input(inner_tts)? {
+                    so_far.append(method.as_str());
+                }
+                so_far.append("}");
+                found_full.push(so_far);
+                so_far = quote::Tokens::new();
+                in_impl_header = false;
+            }
+            // This matches a definition like:
+            // ```
             // fn a() {
             //     // inline code
             // }
@@ -68,11 +142,91 @@ pub fn transform_macro_input_to_items(
         return Err(MacroError::UnexpectedEndOfMacroInvocation { tokens: so_far });
     }
 
-    found_full
-        .into_iter()
-        .map(|found| {
-            syn::parse_item(found.as_ref())
-                .map_err(|desc| MacroError::UnexpectedReparseFailure { err_msg: desc })
-        })
-        .collect::<Result<Vec<syn::Item>, MacroError>>()
+    Ok(found_full)
+}
+
+#[cfg(test)]
+mod tests {
+    use syn;
+    use quote::{self, ToTokens};
+
+    use super::*;
+
+    fn to_string(tts: &[syn::TokenTree]) -> String {
+        let mut tokens = quote::Tokens::new();
+        for tt in tts {
+            tt.to_tokens(&mut tokens);
+        }
+        tokens.to_string()
+    }
+
+    fn items(body: &str) -> Result<Vec<syn::Item>, MacroError> {
+        transform_macro_input_to_items(syn::parse_token_trees(body).unwrap())
+    }
+
+    fn item_to_string(item: &syn::Item) -> String {
+        let mut tokens = quote::Tokens::new();
+        item.to_tokens(&mut tokens);
+        tokens.to_string()
+    }
+
+    #[test]
+    fn strips_dyn() {
+        let stripped = strip_dyn_keywords(
+            syn::parse_token_trees("f(&dyn Fn(u32), dyn, Box<dyn ::std::any::Any>)").unwrap(),
+        );
+        assert_eq!(
+            to_string(&stripped),
+            to_string(&syn::parse_token_trees("f(&Fn(u32), dyn, Box<::std::any::Any>)").unwrap())
+        );
+    }
+
+    #[test]
+    fn parses_files_with_dyn() {
+        let krate = parse_file("fn f(callback: &dyn Fn(u32)) {}\nmod a;\n").unwrap();
+        assert_eq!(krate.items.len(), 2);
+        assert!(parse_file("fn f(").is_err());
+    }
+
+    #[test]
+    fn rewrites_arrow_definitions() {
+        let items = items(
+            "fn a(x: u32) -> u32 => path::to::a;
+             fn b() { inline() }
+             impl Foo {
+                 fn c(&self) => Foo::c;
+                 fn d(&self) { self.d() }
+             }",
+        ).unwrap();
+        assert_eq!(items.len(), 3);
+        assert_eq!(
+            item_to_string(&items[0]),
+            item_to_string(&syn::parse_item("fn a(x: u32) -> u32 { path::to::a }").unwrap())
+        );
+        assert_eq!(
+            item_to_string(&items[2]),
+            item_to_string(
+                &syn::parse_item("impl Foo { fn c(&self) { Foo::c } fn d(&self) { self.d() } }")
+                    .unwrap()
+            )
+        );
+    }
+
+    #[test]
+    fn incomplete_definitions() {
+        match items("fn a() {} fn b()") {
+            Err(MacroError::UnexpectedEndOfMacroInvocation { .. }) => {}
+            other => panic!("expected an incomplete definition error, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn macro_token_trees() {
+        let mac = |source: &str| match syn::parse_item(source).unwrap().node {
+            syn::ItemKind::Mac(mac) => mac,
+            _ => panic!("expected a macro invocation"),
+        };
+        assert_eq!(transform_mac_to_items(mac("js_fn! { fn a() {} }")).unwrap().len(), 1);
+        assert!(transform_mac_to_items(mac("js_fn!();")).unwrap().is_empty());
+    }
 }
@@ -4,7 +4,7 @@ use {quote, syn};
 
 use MacroError;
 
-use types::{SupportedArgumentType, SupportedRetType};
+use types::{SupportedArgumentType, SupportedRetType, SupportedSelfArgument};
 
 pub fn extract_func_info(
     item: &syn::Item,
@@ -13,16 +13,227 @@ pub fn extract_func_info(
         syn::ItemKind::Fn(ref decleration, _, _, _, _, ref block) => {
             Ok((item, &**decleration, block))
         }
-        ref kind => Err(MacroError::InvalidItemKind { kind: kind.clone() })?,
+        ref kind => Err(MacroError::InvalidItemKind { kind: Box::new(kind.clone()) })?,
     }
 }
 
+/// A single function definition inside of `js_fn!`, either free-standing or a
+/// method inside of an `impl` block.
+pub struct FnDefinition<'a> {
+    pub ident: &'a syn::Ident,
+    pub decl: &'a syn::FnDecl,
+    pub block: &'a syn::Block,
+    pub attrs: &'a [syn::Attribute],
+    /// Type the `impl` block this method is in is for, if any.
+    pub self_ty: Option<&'a syn::Ident>,
+}
+
+impl<'a> FnDefinition<'a> {
+    pub fn self_argument(&self) -> Option<SupportedSelfArgument> {
+        match self.self_ty {
+            Some(_) => get_self_argument(self.decl),
+            None => None,
+        }
+    }
+
+    pub fn argument_types(&self) -> Result<Vec<SupportedArgumentType>, MacroError> {
+        match self.self_argument() {
+            Some(_) => argument_types(&self.decl.inputs[1..]),
+            None => get_argument_types(self.decl),
+        }
+    }
+
+    /// Names of the arguments, not including `self`. Arguments which aren't
+    /// simple identifiers, like `_`, are named by their position instead.
+    pub fn argument_names(&self) -> Vec<String> {
+        let inputs = match self.self_argument() {
+            Some(_) => &self.decl.inputs[1..],
+            None => &self.decl.inputs[..],
+        };
+        argument_names(inputs)
+    }
+
+    /// Lines of the function's doc comment, without comment markers.
+    pub fn doc_lines(&self) -> Vec<String> {
+        doc_lines(self.attrs)
+    }
+
+    /// Return type of the function, with tuple elements named by a
+    /// `#[js_return_names(..)]` attribute if it has one.
+    pub fn ret_type(&self) -> Result<SupportedRetType, MacroError> {
+        let mut ret_ty = match self.decl.output {
+            syn::FunctionRetTy::Default => SupportedRetType::unit(),
+            syn::FunctionRetTy::Ty(ref ty) => SupportedRetType::new_with_self(ty, self.self_ty)?,
+        };
+        if let Some(attr) = self.attrs
+            .iter()
+            .find(|attr| attr.value.name() == "js_return_names")
+        {
+            let names = return_names(&attr.value)?;
+            match tuple_in_ret_type(&mut ret_ty) {
+                Some(&mut SupportedRetType::Tuple(ref elements, ref mut tuple_names)) => {
+                    if elements.len() != names.len() {
+                        return Err(MacroError::InvalidReturnNames {
+                            attr: attr.value.clone(),
+                        });
+                    }
+                    *tuple_names = Some(names);
+                }
+                _ => {
+                    return Err(MacroError::ReturnNamesWithoutTuple {
+                        name: self.ident.clone(),
+                    })
+                }
+            }
+        }
+        Ok(ret_ty)
+    }
+}
+
+/// Finds the tuple a function returns, either directly or inside an `Option`
+/// or `Result`.
+fn tuple_in_ret_type(ret_ty: &mut SupportedRetType) -> Option<&mut SupportedRetType> {
+    match *ret_ty {
+        SupportedRetType::Tuple(..) => Some(ret_ty),
+        SupportedRetType::Optional(ref mut inner) | SupportedRetType::Result(ref mut inner) => {
+            tuple_in_ret_type(inner)
+        }
+        _ => None,
+    }
+}
+
+/// Reads the names listed in `#[js_return_names(a, b, ..)]`, which must be
+/// distinct plain identifiers.
+fn return_names(attr: &syn::MetaItem) -> Result<Vec<String>, MacroError> {
+    let invalid = || MacroError::InvalidReturnNames { attr: attr.clone() };
+    let items = match *attr {
+        syn::MetaItem::List(_, ref items) => items,
+        _ => return Err(invalid()),
+    };
+    let mut names = Vec::<String>::new();
+    for item in items {
+        match *item {
+            syn::NestedMetaItem::MetaItem(syn::MetaItem::Word(ref ident))
+                if !names.iter().any(|name| name == ident.as_ref()) =>
+            {
+                names.push(ident.to_string());
+            }
+            _ => return Err(invalid()),
+        }
+    }
+    Ok(names)
+}
+
+/// Names of methods in `impl` blocks which would clash with the function
+/// freeing boxed values (`Type__builtin_drop`), or with members of the
+/// JavaScript class generated for the type.
+const RESERVED_METHOD_NAMES: &[&str] =
+    &["builtin_drop", "constructor", "free", "_check_ptr", "_ptr", "_wrapper"];
+
+/// Finds all function definitions in an item: either the item itself, or each
+/// method if it's an `impl` block.
+pub fn extract_fn_definitions<'a>(
+    item: &'a syn::Item,
+) -> Result<Vec<FnDefinition<'a>>, MacroError> {
+    match item.node {
+        syn::ItemKind::Fn(ref decleration, _, _, _, _, ref block) => Ok(vec![
+            FnDefinition {
+                ident: &item.ident,
+                decl: decleration,
+                block,
+                attrs: &item.attrs,
+                self_ty: None,
+            },
+        ]),
+        syn::ItemKind::Impl(_, _, ref generics, None, ref ty, ref impl_items) => {
+            let self_ty = match **ty {
+                syn::Ty::Path(None, ref path)
+                    if *generics == syn::Generics::default() && path.segments.len() == 1
+                        && path.segments[0].parameters.is_empty() =>
+                {
+                    &path.segments[0].ident
+                }
+                _ => return Err(MacroError::InvalidImplType { ty: (**ty).clone() }),
+            };
+
+            impl_items
+                .iter()
+                .map(|impl_item| match impl_item.node {
+                    syn::ImplItemKind::Method(..)
+                        if RESERVED_METHOD_NAMES.contains(&impl_item.ident.as_ref()) =>
+                    {
+                        Err(MacroError::ReservedMethodName {
+                            name: impl_item.ident.clone(),
+                        })
+                    }
+                    syn::ImplItemKind::Method(ref sig, ref block) => Ok(FnDefinition {
+                        ident: &impl_item.ident,
+                        decl: &sig.decl,
+                        block,
+                        attrs: &impl_item.attrs,
+                        self_ty: Some(self_ty),
+                    }),
+                    ref kind => Err(MacroError::InvalidImplItemKind {
+                        kind: Box::new(kind.clone()),
+                    }),
+                })
+                .collect()
+        }
+        ref kind => Err(MacroError::InvalidItemKind { kind: Box::new(kind.clone()) })?,
+    }
+}
+
+/// See `FnDefinition::argument_names`.
+pub(crate) fn argument_names(inputs: &[syn::FnArg]) -> Vec<String> {
+    inputs
+        .iter()
+        .enumerate()
+        .map(|(index, input)| match *input {
+            syn::FnArg::Captured(syn::Pat::Ident(_, ref ident, None), _) => ident.to_string(),
+            _ => format!("arg{}", index),
+        })
+        .collect()
+}
+
+/// See `FnDefinition::doc_lines`.
+pub(crate) fn doc_lines(attrs: &[syn::Attribute]) -> Vec<String> {
+    let mut lines = Vec::new();
+    for attr in attrs {
+        if let syn::MetaItem::NameValue(ref ident, syn::Lit::Str(ref doc, _)) = attr.value {
+            if ident != "doc" {
+                continue;
+            }
+            // sugared doc comments keep their markers when parsed from source.
+            let doc = if doc.starts_with("///") || doc.starts_with("//!") {
+                &doc[3..]
+            } else if doc.starts_with("/**") && doc.ends_with("*/") {
+                &doc[3..doc.len() - 2]
+            } else {
+                &doc[..]
+            };
+            for line in doc.split('\n') {
+                let line = line.trim_end();
+                lines.push(line.strip_prefix(' ').unwrap_or(line).to_owned());
+            }
+        }
+    }
+    lines
+}
+
+pub fn get_self_argument(decl: &syn::FnDecl) -> Option<SupportedSelfArgument> {
+    decl.inputs.first().and_then(SupportedSelfArgument::new)
+}
+
 pub fn get_argument_types(decl: &syn::FnDecl) -> Result<Vec<SupportedArgumentType>, MacroError> {
-    Ok(decl.inputs
+    argument_types(&decl.inputs)
+}
+
+fn argument_types(inputs: &[syn::FnArg]) -> Result<Vec<SupportedArgumentType>, MacroError> {
+    Ok(inputs
         .iter()
         .map(|input| match *input {
             syn::FnArg::SelfRef(_, _) | syn::FnArg::SelfValue(_) => {
-                Err(MacroError::InvalidArgument { arg: input.clone() })
+                Err(MacroError::InvalidArgument { arg: Box::new(input.clone()) })
             }
             syn::FnArg::Captured(_, ref ty) | syn::FnArg::Ignored(ref ty) => Ok(ty.clone()),
         })
@@ -37,30 +248,63 @@ pub fn get_ret_type(decl: &syn::FnDecl) -> Result<SupportedRetType, MacroError>
     }
 }
 
-// TODO: find and store doc-comments in here for use in generating JS code comments.
 pub struct JsFnInfo {
     pub rust_name: String,
+    /// Type the `impl` block this method is in is for, if any.
+    pub self_ty: Option<String>,
+    pub self_arg: Option<SupportedSelfArgument>,
+    /// Argument names as written in rust, see `FnDefinition::argument_names`.
+    pub args_names: Vec<String>,
     pub args_ty: Vec<SupportedArgumentType>,
     pub ret_ty: SupportedRetType,
+    /// Lines of the doc comment, see `FnDefinition::doc_lines`.
+    pub doc_lines: Vec<String>,
 }
 
 
 impl JsFnInfo {
-    pub fn try_from(item: &syn::Item) -> Result<Self, MacroError> {
-        let (item, decl, _) = extract_func_info(item)?;
-
-        let argument_types = get_argument_types(decl)?;
-        let ret_ty = match decl.output {
-            syn::FunctionRetTy::Default => SupportedRetType::unit(),
-            syn::FunctionRetTy::Ty(ref ty) => SupportedRetType::new(ty)?,
-        };
+    /// Creates info for each function definition in the given item.
+    pub fn from_item(item: &syn::Item) -> Result<Vec<Self>, MacroError> {
+        extract_fn_definitions(item)?
+            .iter()
+            .map(JsFnInfo::from_definition)
+            .collect()
+    }
 
+    pub fn from_definition(definition: &FnDefinition) -> Result<Self, MacroError> {
         Ok(JsFnInfo {
-            rust_name: item.ident.to_string(),
-            args_ty: argument_types,
-            ret_ty: ret_ty,
+            rust_name: definition.ident.to_string(),
+            self_ty: definition.self_ty.map(ToString::to_string),
+            self_arg: definition.self_argument(),
+            args_names: definition.argument_names(),
+            args_ty: definition.argument_types()?,
+            ret_ty: definition.ret_type()?,
+            doc_lines: definition.doc_lines(),
         })
     }
+
+    /// Name unique to this function, see `qualified_fn_name`.
+    pub fn qualified_name(&self) -> String {
+        qualified_fn_name(self.self_ty.as_ref(), &self.rust_name)
+    }
+}
+
+/// Creates the name used for both the exported function and for looking it up
+/// in JavaScript: `Type__method` for methods, and the plain name otherwise.
+pub fn qualified_fn_name<T, U>(self_ty: Option<T>, name: U) -> String
+where
+    T: fmt::Display,
+    U: fmt::Display,
+{
+    match self_ty {
+        Some(self_ty) => format!("{}__{}", self_ty, name),
+        None => name.to_string(),
+    }
+}
+
+/// Creates the name of the function which drops a boxed `self_ty` value.
+pub fn drop_fn_name<T: fmt::Display>(self_ty: T) -> String {
+    format!("{}__builtin_drop", self_ty)
 }
 
 static TRANSFORMED_FUNC_PREFX: &'static str = "__js_fn_";
@@ -87,3 +331,163 @@ impl<T: fmt::Display> fmt::Display for TransformedRustIdent<T> {
         write!(f, "{}{}", TRANSFORMED_FUNC_PREFX, self.name)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use syn;
+
+    use parsing::transform_macro_input_to_items;
+    use types::SupportedCopyTy::*;
+
+    use super::*;
+
+    /// Creates info for every function in the body of a `js_fn!` invocation.
+    fn infos(body: &str) -> Result<Vec<JsFnInfo>, MacroError> {
+        let items = transform_macro_input_to_items(syn::parse_token_trees(body).unwrap())?;
+        let mut infos = Vec::new();
+        for item in &items {
+            infos.extend(JsFnInfo::from_item(item)?);
+        }
+        Ok(infos)
+    }
+
+    fn error(body: &str) -> MacroError {
+        match infos(body) {
+            Ok(_) => panic!("expected an error for {}", body),
+            Err(e) => e,
+        }
+    }
+
+    #[test]
+    fn free_functions() {
+        let infos = infos(
+            "/// Adds two numbers.
+             ///
+             /// Wrapping on overflow.
+             fn add(a: u32, _: u32) -> u32 => add;
+             fn inline() {}",
+        ).unwrap();
+        assert_eq!(infos.len(), 2);
+        assert_eq!(infos[0].rust_name, "add");
+        assert_eq!(infos[0].qualified_name(), "add");
+        assert_eq!(infos[0].self_ty, None);
+        assert_eq!(infos[0].self_arg, None);
+        assert_eq!(infos[0].args_names, ["a", "arg1"]);
+        assert_eq!(
+            infos[0].args_ty,
+            [SupportedArgumentType::Integer(U32), SupportedArgumentType::Integer(U32)]
+        );
+        assert_eq!(infos[0].ret_ty, SupportedRetType::Integer(U32));
+        assert_eq!(infos[0].doc_lines, ["Adds two numbers.", "", "Wrapping on overflow."]);
+        assert_eq!(infos[1].ret_ty, SupportedRetType::Unit);
+        assert!(infos[1].doc_lines.is_empty());
+    }
+
+    #[test]
+    fn impl_blocks() {
+        let infos = infos(
+            "impl Counter {
+                 fn new(start: u32) -> Self => Counter::new;
+                 fn get(&self) -> u32 => Counter::get;
+                 fn add(&mut self, by: u32) => Counter::add;
+                 fn finish(self) -> Counter => Counter::finish;
+             }",
+        ).unwrap();
+        let self_args = infos.iter().map(|info| info.self_arg).collect::<Vec<_>>();
+        assert_eq!(
+            self_args,
+            [
+                None,
+                Some(SupportedSelfArgument::Ref),
+                Some(SupportedSelfArgument::MutRef),
+                Some(SupportedSelfArgument::Value),
+            ]
+        );
+        assert!(infos.iter().all(|info| info.self_ty == Some("Counter".to_owned())));
+        assert_eq!(infos[0].qualified_name(), "Counter__new");
+        assert_eq!(infos[0].ret_ty, SupportedRetType::OwnedSelf);
+        // `self` isn't one of the arguments.
+        assert_eq!(infos[2].args_names, ["by"]);
+        assert_eq!(infos[2].args_ty, [SupportedArgumentType::Integer(U32)]);
+        assert_eq!(infos[3].ret_ty, SupportedRetType::OwnedSelf);
+    }
+
+    #[test]
+    fn reserved_method_names() {
+        for name in RESERVED_METHOD_NAMES {
+            match error(&format!("impl Foo {{ fn {}(&self) => Foo::f; }}", name)) {
+                MacroError::ReservedMethodName { name: ref found } => assert_eq!(found, name),
+                other => panic!("expected a reserved name error, found {:?}", other),
+            }
+        }
+        // only methods of impl blocks are reserved.
+        infos("fn free() => free;").unwrap();
+    }
+
+    #[test]
+    fn invalid_impl_blocks() {
+        match error("impl Foo<u32> { fn f(&self) => Foo::f; }") {
+            MacroError::InvalidImplType { .. } => {}
+            other => panic!("expected an impl type error, found {:?}", other),
+        }
+        match error("impl Display for Foo { fn fmt(&self) => Foo::fmt; }") {
+            MacroError::InvalidItemKind { .. } => {}
+            other => panic!("expected an item kind error, found {:?}", other),
+        }
+        match error("impl Foo { items! {} }") {
+            MacroError::InvalidImplItemKind { .. } => {}
+            other => panic!("expected an impl item error, found {:?}", other),
+        }
+        match error("struct Foo {}") {
+            MacroError::InvalidItemKind { .. } => {}
+            other => panic!("expected an item kind error, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn self_arguments_outside_impl_blocks() {
+        match error("fn f(&self) => f;") {
+            MacroError::InvalidArgument { .. } => {}
+            other => panic!("expected an argument error, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn return_names() {
+        let infos = infos(
+            "#[js_return_names(quotient, remainder)]
+             fn divide(a: u32, b: u32) -> Option<(u32, u32)> => divide;",
+        ).unwrap();
+        assert_eq!(
+            infos[0].ret_ty,
+            SupportedRetType::Optional(Box::new(SupportedRetType::Tuple(
+                vec![SupportedRetType::Integer(U32), SupportedRetType::Integer(U32)],
+                Some(vec!["quotient".to_owned(), "remainder".to_owned()])
+            )))
+        );
+
+        for body in &[
+            "#[js_return_names(a)] fn f() -> (u32, u32) => f;",
+            "#[js_return_names(a, a)] fn f() -> (u32, u32) => f;",
+            "#[js_return_names(a, b = 1)] fn f() -> (u32, u32) => f;",
+            "#[js_return_names = \"a\"] fn f() -> (u32, u32) => f;",
+        ] {
+            match error(body) {
+                MacroError::InvalidReturnNames { .. } => {}
+                other => panic!("expected a return names error for {}, found {:?}", body, other),
+            }
+        }
+        match error("#[js_return_names(a)] fn f() -> u32 => f;") {
+            MacroError::ReturnNamesWithoutTuple { .. } => {}
+            other => panic!("expected a missing tuple error, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!(qualified_fn_name(None::<&str>, "f"), "f");
+        assert_eq!(qualified_fn_name(Some("Foo"), "f"), "Foo__f");
+        assert_eq!(drop_fn_name("Foo"), "Foo__builtin_drop");
+        assert_eq!(TransformedRustIdent::new("Foo__f").to_string(), "__js_fn_Foo__f");
+    }
+}
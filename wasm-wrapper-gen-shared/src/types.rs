@@ -6,15 +6,19 @@ use syn;
 
 use MacroError;
 
+use imports::{import_argument_type, import_ret_type};
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum SupportedCopyTy {
     U8,
     U16,
     U32,
+    U64,
     USize,
     I8,
     I16,
     I32,
+    I64,
     ISize,
     F32,
     F64,
@@ -28,10 +32,12 @@ impl SupportedCopyTy {
             "u8" => Some(U8),
             "u16" => Some(U16),
             "u32" => Some(U32),
+            "u64" => Some(U64),
             "usize" => Some(USize),
             "i8" => Some(I8),
             "i16" => Some(I16),
             "i32" => Some(I32),
+            "i64" => Some(I64),
             "isize" => Some(ISize),
             "f32" => Some(F32),
             "f64" => Some(F64),
@@ -47,13 +53,25 @@ impl SupportedCopyTy {
             Bool | U8 => 1,
             U16 => 2,
             USize | U32 => 4,
+            U64 => 8,
             I8 => 1,
             I16 => 2,
             ISize | I32 => 4,
+            I64 => 8,
             F32 => 4,
             F64 => 8,
         }
     }
+
+    /// True for integers too large to fit in a JavaScript number, which are
+    /// represented as `BigInt`s instead.
+    pub fn is_64_bit_integer(&self) -> bool {
+        use self::SupportedCopyTy::*;
+        match *self {
+            U64 | I64 => true,
+            U8 | U16 | U32 | USize | I8 | I16 | I32 | ISize | F32 | F64 | Bool => false,
+        }
+    }
 }
 
 impl AsRef<str> for SupportedCopyTy {
@@ -63,10 +81,12 @@ impl AsRef<str> for SupportedCopyTy {
             U8 => "u8",
             U16 => "u16",
             U32 => "u32",
+            U64 => "u64",
             USize => "usize",
             I8 => "i8",
             I16 => "i16",
             I32 => "i32",
+            I64 => "i64",
             ISize => "isize",
             F32 => "f32",
             F64 => "f64",
@@ -87,7 +107,7 @@ impl ToTokens for SupportedCopyTy {
     }
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum SupportedArgumentType {
     // &[u8]
     IntegerSliceRef(SupportedCopyTy),
@@ -99,12 +119,46 @@ pub enum SupportedArgumentType {
     Integer(SupportedCopyTy),
     // String
     OwnedString,
-    // String slice is not supported due to string always needing allocation
-    // to convert from JavaScript to rust.
-    // TODO: wtf-8 or utf16 type.
+    // &str
+    StringSlice,
+    // JsString, the string's UTF-16 code units without validation
+    JsString,
+    // Json<_> where _ is any serde type, passed as a JSON string
+    Json,
+    // Option<_> where _ is any of the above
+    Optional(Box<SupportedArgumentType>),
+    // &dyn Fn(_, ..) -> _, a JavaScript function called back during the call,
+    // with arguments and a return type supported by imported functions
+    Callback(Vec<SupportedArgumentType>, SupportedRetType),
     // TODO: more types, and more nesting.
 }
 
+/// The way a method inside of an `impl` block takes `self`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SupportedSelfArgument {
+    // &self
+    Ref,
+    // &mut self
+    MutRef,
+    // self
+    Value,
+}
+
+impl SupportedSelfArgument {
+    pub fn new(arg: &syn::FnArg) -> Option<Self> {
+        match *arg {
+            syn::FnArg::SelfRef(_, syn::Mutability::Immutable) => {
+                Some(SupportedSelfArgument::Ref)
+            }
+            syn::FnArg::SelfRef(_, syn::Mutability::Mutable) => {
+                Some(SupportedSelfArgument::MutRef)
+            }
+            syn::FnArg::SelfValue(_) => Some(SupportedSelfArgument::Value),
+            syn::FnArg::Captured(_, _) | syn::FnArg::Ignored(_) => None,
+        }
+    }
+}
+
 fn resolve_parens(mut ty: &syn::Ty) -> &syn::Ty {
     while let syn::Ty::Paren(ref temp) = *ty {
         ty = temp;
@@ -169,22 +223,118 @@ fn as_vec_simple_integer_type(ty: &syn::Ty) -> Option<SupportedCopyTy> {
     None
 }
 
+/// Finds the item type of a `JsBuffer<_>` of numbers. Booleans aren't
+/// supported, since JavaScript could write any byte into their view.
+fn as_js_buffer_type(ty: &syn::Ty) -> Option<SupportedCopyTy> {
+    if let Some(segment) = path_as_single_segment(ty) {
+        if segment.ident == "JsBuffer" {
+            if let syn::PathParameters::AngleBracketed(ref params) = segment.parameters {
+                if params.lifetimes.is_empty() && params.bindings.is_empty()
+                    && params.types.len() == 1
+                {
+                    return params
+                        .types
+                        .first()
+                        .and_then(as_simple_integer)
+                        .filter(|&item_ty| item_ty != SupportedCopyTy::Bool);
+                }
+            }
+        }
+    }
+    None
+}
+
 fn is_string_slice(ty: &syn::Ty) -> bool {
+    str_reference_mutability(ty) == Some(syn::Mutability::Immutable)
+}
+
+/// Finds the mutability of a `&str` or `&mut str` type.
+fn str_reference_mutability(ty: &syn::Ty) -> Option<syn::Mutability> {
     let ty = resolve_parens(ty);
     if let syn::Ty::Rptr(_, ref str_ty_with_mut) = *ty {
-        if str_ty_with_mut.mutability == syn::Mutability::Immutable {
-            let should_be_str = resolve_parens(&str_ty_with_mut.ty);
-            if let Some(segment) = path_as_single_segment(should_be_str) {
-                if segment.ident == "str" && segment.parameters.is_empty() {
-                    return true;
+        let should_be_str = resolve_parens(&str_ty_with_mut.ty);
+        if let Some(segment) = path_as_single_segment(should_be_str) {
+            if segment.ident == "str" && segment.parameters.is_empty() {
+                return Some(str_ty_with_mut.mutability);
+            }
+        }
+    }
+
+    None
+}
+
+fn is_self_type(ty: &syn::Ty, self_ty: Option<&syn::Ident>) -> bool {
+    if let Some(segment) = path_as_single_segment(ty) {
+        if segment.parameters.is_empty() {
+            return segment.ident == "Self" || Some(&segment.ident) == self_ty;
+        }
+    }
+
+    false
+}
+
+fn as_option_inner_type(ty: &syn::Ty) -> Option<&syn::Ty> {
+    if let Some(segment) = path_as_single_segment(ty) {
+        if segment.ident == "Option" {
+            if let syn::PathParameters::AngleBracketed(ref params) = segment.parameters {
+                if params.lifetimes.is_empty() && params.bindings.is_empty()
+                    && params.types.len() == 1
+                {
+                    return params.types.first();
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Finds the arguments and return type of a `&Fn(..) -> ..` trait object
+/// reference (written `&dyn Fn(..) -> ..`, see `strip_dyn_keywords`).
+fn as_fn_reference(ty: &syn::Ty) -> Option<&syn::ParenthesizedParameterData> {
+    let ty = resolve_parens(ty);
+    if let syn::Ty::Rptr(_, ref fn_ty_mut) = *ty {
+        if fn_ty_mut.mutability != syn::Mutability::Immutable {
+            return None;
+        }
+        if let Some(segment) = path_as_single_segment(&fn_ty_mut.ty) {
+            if segment.ident == "Fn" {
+                if let syn::PathParameters::Parenthesized(ref data) = segment.parameters {
+                    return Some(data);
                 }
             }
         }
     }
+    None
+}
+
+fn is_json(ty: &syn::Ty) -> bool {
+    if let Some(segment) = path_as_single_segment(ty) {
+        if segment.ident == "Json" {
+            if let syn::PathParameters::AngleBracketed(ref params) = segment.parameters {
+                return params.lifetimes.is_empty() && params.bindings.is_empty()
+                    && params.types.len() == 1;
+            }
+        }
+    }
 
     false
 }
 
+fn as_result_ok_type(ty: &syn::Ty) -> Option<&syn::Ty> {
+    if let Some(segment) = path_as_single_segment(ty) {
+        if segment.ident == "Result" {
+            if let syn::PathParameters::AngleBracketed(ref params) = segment.parameters {
+                if params.lifetimes.is_empty() && params.bindings.is_empty()
+                    && params.types.len() == 2
+                {
+                    return params.types.first();
+                }
+            }
+        }
+    }
+    None
+}
+
 fn is_owned_string(ty: &syn::Ty) -> bool {
     if let Some(segment) = path_as_single_segment(ty) {
         if segment.ident == "String" && segment.parameters.is_empty() {
@@ -195,6 +345,16 @@ fn is_owned_string(ty: &syn::Ty) -> bool {
     false
 }
 
+fn is_js_string(ty: &syn::Ty) -> bool {
+    if let Some(segment) = path_as_single_segment(ty) {
+        if segment.ident == "JsString" && segment.parameters.is_empty() {
+            return true;
+        }
+    }
+
+    false
+}
+
 impl SupportedArgumentType {
     pub fn new(ty: &syn::Ty) -> Result<Self, MacroError> {
         let ty = resolve_parens(ty);
@@ -220,16 +380,46 @@ impl SupportedArgumentType {
         if let Some(item_ty) = as_vec_simple_integer_type(ty) {
             return Ok(SupportedArgumentType::IntegerVec(item_ty));
         }
-        // if is_string_slice(ty) {
-        //     return Ok(SupportedArgumentType::StringSlice);
-        // }
+        match str_reference_mutability(ty) {
+            Some(syn::Mutability::Immutable) => return Ok(SupportedArgumentType::StringSlice),
+            Some(syn::Mutability::Mutable) => {
+                Err(MacroError::MutableStringSliceArgument { ty: ty.clone() })?
+            }
+            None => {}
+        }
         if is_owned_string(ty) {
             return Ok(SupportedArgumentType::OwnedString);
         }
+        if is_js_string(ty) {
+            return Ok(SupportedArgumentType::JsString);
+        }
+        if is_json(ty) {
+            return Ok(SupportedArgumentType::Json);
+        }
+        if let Some(signature) = as_fn_reference(ty) {
+            // callbacks are called like imported functions.
+            let args_ty = signature
+                .inputs
+                .iter()
+                .map(import_argument_type)
+                .collect::<Result<_, _>>()?;
+            let ret_ty = match signature.output {
+                Some(ref output) => import_ret_type(output)?,
+                None => SupportedRetType::unit(),
+            };
+            return Ok(SupportedArgumentType::Callback(args_ty, ret_ty));
+        }
+        if let Some(inner_ty) = as_option_inner_type(ty) {
+            match SupportedArgumentType::new(inner_ty)? {
+                SupportedArgumentType::Optional(_) | SupportedArgumentType::Callback(..) => {}
+                inner => return Ok(SupportedArgumentType::Optional(Box::new(inner))),
+            }
+        }
         Err(MacroError::UnhandledArgumentType { ty: ty.clone() })?
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum SupportedRetType {
     // Vec<u8>
     IntegerVec(SupportedCopyTy),
@@ -241,11 +431,30 @@ pub enum SupportedRetType {
     StringSlice,
     // String
     OwnedString,
+    // Self, inside of an impl block
+    OwnedSelf,
+    // Json<_> where _ is any serde type, returned as a JSON string
+    Json,
+    // JsBuffer<u8>, returned in the layout of a Vec and viewed in place
+    Buffer(SupportedCopyTy),
+    // Option<_> where _ is any of the above except ()
+    Optional(Box<SupportedRetType>),
+    // Result<_, E> where _ is any of the above and E: Display
+    Result(Box<SupportedRetType>),
+    // (_, ..) where each _ is any of the above except (), Result and tuples,
+    // along with names for each element if given with `#[js_return_names(..)]`
+    Tuple(Vec<SupportedRetType>, Option<Vec<String>>),
 }
 
 
 impl SupportedRetType {
     pub fn new(ty: &syn::Ty) -> Result<Self, MacroError> {
+        SupportedRetType::new_with_self(ty, None)
+    }
+
+    /// Creates a return type for a function which may be inside of an `impl`
+    /// block for `self_ty`, allowing it to return `Self`.
+    pub fn new_with_self(ty: &syn::Ty, self_ty: Option<&syn::Ident>) -> Result<Self, MacroError> {
         let ty = resolve_parens(ty);
         if let Some(int_ty) = as_simple_integer(ty) {
             return Ok(SupportedRetType::Integer(int_ty));
@@ -257,6 +466,18 @@ impl SupportedRetType {
             if items.is_empty() {
                 return Ok(SupportedRetType::Unit);
             }
+            let elements = items
+                .iter()
+                .map(|item| match SupportedRetType::new_with_self(item, self_ty)? {
+                    SupportedRetType::Unit
+                    | SupportedRetType::Result(_)
+                    | SupportedRetType::Tuple(..) => {
+                        Err(MacroError::UnhandledTupleElement { ty: item.clone() })
+                    }
+                    element => Ok(element),
+                })
+                .collect::<Result<_, _>>()?;
+            return Ok(SupportedRetType::Tuple(elements, None));
         }
         if is_string_slice(ty) {
             return Ok(SupportedRetType::StringSlice);
@@ -264,6 +485,29 @@ impl SupportedRetType {
         if is_owned_string(ty) {
             return Ok(SupportedRetType::OwnedString);
         }
+        if self_ty.is_some() && is_self_type(ty, self_ty) {
+            return Ok(SupportedRetType::OwnedSelf);
+        }
+        if is_json(ty) {
+            return Ok(SupportedRetType::Json);
+        }
+        if let Some(item_ty) = as_js_buffer_type(ty) {
+            return Ok(SupportedRetType::Buffer(item_ty));
+        }
+        if let Some(inner_ty) = as_option_inner_type(ty) {
+            match SupportedRetType::new_with_self(inner_ty, self_ty)? {
+                SupportedRetType::Unit
+                | SupportedRetType::Optional(_)
+                | SupportedRetType::Result(_) => {}
+                inner => return Ok(SupportedRetType::Optional(Box::new(inner))),
+            }
+        }
+        if let Some(ok_ty) = as_result_ok_type(ty) {
+            match SupportedRetType::new_with_self(ok_ty, self_ty)? {
+                SupportedRetType::Result(_) => {}
+                inner => return Ok(SupportedRetType::Result(Box::new(inner))),
+            }
+        }
         Err(MacroError::UnhandledRetType { ty: ty.clone() })?
     }
 
@@ -282,6 +526,146 @@ impl ToTokens for SupportedRetType {
             // TODO: handle reference lifetime
             StringSlice => tokens.append("&str"),
             OwnedString => tokens.append("String"),
+            OwnedSelf => tokens.append("Self"),
+            // the serialized type is left to be inferred.
+            Json => tokens.append(quote! { ::wasm_wrapper_gen::Json<_> }),
+            Buffer(item_ty) => tokens.append(quote! { ::wasm_wrapper_gen::JsBuffer<#item_ty> }),
+            Optional(ref inner) => tokens.append(quote! { Option<#inner> }),
+            // the error type is left to be inferred, it only needs to implement Display.
+            Result(ref inner) => tokens.append(quote! { Result<#inner, _> }),
+            // trailing comma so that single element tuples stay tuples.
+            Tuple(ref elements, _) => tokens.append(quote! { (#(#elements,)*) }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use syn;
+
+    use super::SupportedArgumentType as Arg;
+    use super::SupportedCopyTy::*;
+    use super::SupportedRetType as Ret;
+    use super::*;
+
+    fn arg(ty: &str) -> Result<Arg, MacroError> {
+        Arg::new(&syn::parse_type(ty).unwrap())
+    }
+
+    fn ret(ty: &str) -> Result<Ret, MacroError> {
+        Ret::new(&syn::parse_type(ty).unwrap())
+    }
+
+    #[test]
+    fn copy_types() {
+        assert_eq!(SupportedCopyTy::new(&"usize"), Some(USize));
+        assert_eq!(SupportedCopyTy::new(&"char"), None);
+        assert_eq!(U16.size_in_bytes(), 2);
+        assert_eq!(F64.size_in_bytes(), 8);
+        assert_eq!(Bool.size_in_bytes(), 1);
+        assert!(U64.is_64_bit_integer() && I64.is_64_bit_integer());
+        assert!(!F64.is_64_bit_integer());
+    }
+
+    #[test]
+    fn argument_types() {
+        assert_eq!(arg("u32").unwrap(), Arg::Integer(U32));
+        assert_eq!(arg("(bool)").unwrap(), Arg::Integer(Bool));
+        assert_eq!(arg("&[u8]").unwrap(), Arg::IntegerSliceRef(U8));
+        assert_eq!(arg("&mut [f32]").unwrap(), Arg::IntegerSliceMutRef(F32));
+        assert_eq!(arg("Vec<i64>").unwrap(), Arg::IntegerVec(I64));
+        assert_eq!(arg("String").unwrap(), Arg::OwnedString);
+        assert_eq!(arg("&str").unwrap(), Arg::StringSlice);
+        assert_eq!(arg("JsString").unwrap(), Arg::JsString);
+        assert_eq!(arg("Json<Point>").unwrap(), Arg::Json);
+        assert_eq!(arg("Option<&str>").unwrap(), Arg::Optional(Box::new(Arg::StringSlice)));
+    }
+
+    #[test]
+    fn callback_arguments() {
+        assert_eq!(
+            arg("&Fn(i32, &str) -> bool").unwrap(),
+            Arg::Callback(vec![Arg::Integer(I32), Arg::StringSlice], Ret::Integer(Bool))
+        );
+        assert_eq!(arg("&Fn()").unwrap(), Arg::Callback(vec![], Ret::Unit));
+        // callbacks can't be optional, or take callbacks themselves.
+        assert!(arg("Option<&Fn()>").is_err());
+        assert!(arg("&Fn(&Fn())").is_err());
+    }
+
+    #[test]
+    fn invalid_argument_types() {
+        match arg("&mut str") {
+            Err(MacroError::MutableStringSliceArgument { .. }) => {}
+            other => panic!("expected a `&mut str` error, found {:?}", other),
+        }
+        match arg("HashMap<u32, u32>") {
+            Err(MacroError::UnhandledArgumentType { .. }) => {}
+            other => panic!("expected an unhandled type error, found {:?}", other),
+        }
+        assert!(arg("Option<Option<u32>>").is_err());
+    }
+
+    #[test]
+    fn return_types() {
+        assert_eq!(ret("()").unwrap(), Ret::Unit);
+        assert_eq!(ret("u64").unwrap(), Ret::Integer(U64));
+        assert_eq!(ret("Vec<f32>").unwrap(), Ret::IntegerVec(F32));
+        assert_eq!(ret("&'static str").unwrap(), Ret::StringSlice);
+        assert_eq!(ret("String").unwrap(), Ret::OwnedString);
+        assert_eq!(ret("Json<Point>").unwrap(), Ret::Json);
+        assert_eq!(ret("JsBuffer<u16>").unwrap(), Ret::Buffer(U16));
+        assert_eq!(
+            ret("Option<String>").unwrap(),
+            Ret::Optional(Box::new(Ret::OwnedString))
+        );
+        assert_eq!(
+            ret("Result<(u32, bool), ParseError>").unwrap(),
+            Ret::Result(Box::new(Ret::Tuple(
+                vec![Ret::Integer(U32), Ret::Integer(Bool)],
+                None
+            )))
+        );
+        assert_eq!(
+            ret("Option<(String, Vec<u8>)>").unwrap(),
+            Ret::Optional(Box::new(Ret::Tuple(
+                vec![Ret::OwnedString, Ret::IntegerVec(U8)],
+                None
+            )))
+        );
+    }
+
+    #[test]
+    fn invalid_return_types() {
+        assert!(ret("Option<()>").is_err());
+        assert!(ret("Option<Result<u32, E>>").is_err());
+        assert!(ret("Result<Result<u32, E>, E>").is_err());
+        match ret("(u32, ())") {
+            Err(MacroError::UnhandledTupleElement { .. }) => {}
+            other => panic!("expected a tuple element error, found {:?}", other),
         }
+        match ret("(u32, Result<u32, E>)") {
+            Err(MacroError::UnhandledTupleElement { .. }) => {}
+            other => panic!("expected a tuple element error, found {:?}", other),
+        }
+        match ret("HashMap<u32, u32>") {
+            Err(MacroError::UnhandledRetType { .. }) => {}
+            other => panic!("expected an unhandled type error, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn self_return_types() {
+        let counter = syn::Ident::new("Counter");
+        let with_self = |ty| Ret::new_with_self(&syn::parse_type(ty).unwrap(), Some(&counter));
+        assert_eq!(with_self("Self").unwrap(), Ret::OwnedSelf);
+        assert_eq!(with_self("Counter").unwrap(), Ret::OwnedSelf);
+        assert_eq!(
+            with_self("Option<Self>").unwrap(),
+            Ret::Optional(Box::new(Ret::OwnedSelf))
+        );
+        assert!(with_self("Other").is_err());
+        // `Self` only means something inside of an impl block.
+        assert!(ret("Self").is_err());
     }
 }